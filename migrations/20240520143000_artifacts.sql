@@ -0,0 +1,9 @@
+CREATE TABLE run_artifacts (
+    id   TEXT NOT NULL,
+    name TEXT NOT NULL,
+    size INT  NOT NULL,
+    data BLOB NOT NULL,
+
+    PRIMARY KEY (id, name),
+    FOREIGN KEY (id) REFERENCES runs (id) ON DELETE CASCADE
+) STRICT;
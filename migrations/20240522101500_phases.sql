@@ -0,0 +1,13 @@
+CREATE TABLE run_phases (
+    id           TEXT NOT NULL,
+    idx          INT  NOT NULL,
+    name         TEXT NOT NULL,
+    start        TEXT NOT NULL,
+    end          TEXT NOT NULL,
+    exit_code    INT  NOT NULL,
+    output_start INT  NOT NULL,
+    output_end   INT  NOT NULL,
+
+    PRIMARY KEY (id, idx),
+    FOREIGN KEY (id) REFERENCES runs (id) ON DELETE CASCADE
+) STRICT;
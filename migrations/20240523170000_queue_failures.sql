@@ -0,0 +1 @@
+ALTER TABLE queue ADD COLUMN failures INT NOT NULL DEFAULT 0;
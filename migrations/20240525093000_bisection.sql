@@ -0,0 +1,5 @@
+-- Position of the parent in the child's list of parents. Edges inserted before
+-- this column existed are filled in during the next repo update.
+ALTER TABLE commit_edges ADD COLUMN position INT;
+
+ALTER TABLE queue ADD COLUMN kind INT NOT NULL DEFAULT 0;
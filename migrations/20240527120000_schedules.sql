@@ -0,0 +1,6 @@
+CREATE TABLE schedules (
+    name TEXT NOT NULL PRIMARY KEY,
+    last TEXT NOT NULL
+) STRICT;
+
+ALTER TABLE runs ADD COLUMN kind INT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- A NULL bench_hash means the bench repo's HEAD at the time the run is handed
+-- out, a NULL benchmarks means all benchmarks.
+ALTER TABLE queue ADD COLUMN bench_hash TEXT;
+ALTER TABLE queue ADD COLUMN benchmarks TEXT;
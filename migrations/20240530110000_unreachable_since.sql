@@ -0,0 +1,4 @@
+-- Set when a commit becomes unreachable from all refs, e.g. after a force-push.
+ALTER TABLE commits ADD COLUMN unreachable_since TEXT;
+
+UPDATE commits SET unreachable_since = strftime('%Y-%m-%dT%H:%M:%SZ', 'now') WHERE reachable = 0;
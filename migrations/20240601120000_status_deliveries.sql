@@ -0,0 +1,10 @@
+-- Commit statuses waiting to be posted, one per run and configured target.
+CREATE TABLE status_deliveries (
+    id           TEXT NOT NULL,
+    target       TEXT NOT NULL,
+    attempts     INT  NOT NULL DEFAULT 0,
+    next_attempt TEXT NOT NULL,
+
+    PRIMARY KEY (id, target),
+    FOREIGN KEY (id) REFERENCES runs (id) ON DELETE CASCADE
+) STRICT;
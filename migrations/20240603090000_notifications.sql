@@ -0,0 +1,10 @@
+-- Notifications waiting to be sent. The payload is rendered when the event
+-- happens, since things like disconnected workers can't be looked up later.
+CREATE TABLE notifications (
+    id           INTEGER NOT NULL PRIMARY KEY,
+    target       TEXT    NOT NULL,
+    event        TEXT    NOT NULL,
+    payload      TEXT    NOT NULL,
+    attempts     INT     NOT NULL DEFAULT 0,
+    next_attempt TEXT    NOT NULL
+) STRICT;
@@ -0,0 +1,2 @@
+-- How many more successful runs a commit needs before it leaves the queue.
+ALTER TABLE queue ADD COLUMN repetitions INT NOT NULL DEFAULT 1;
@@ -0,0 +1,13 @@
+-- Commits that bisection narrowed a jump in a metric down to, compared to their
+-- first parent.
+CREATE TABLE bisect_culprits (
+    hash      TEXT NOT NULL,
+    metric    TEXT NOT NULL,
+    parent    TEXT NOT NULL,
+    old_value REAL NOT NULL,
+    new_value REAL NOT NULL,
+    found     TEXT NOT NULL,
+
+    PRIMARY KEY (hash, metric),
+    FOREIGN KEY (hash) REFERENCES commits (hash) ON DELETE CASCADE
+) STRICT;
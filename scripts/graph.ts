@@ -146,7 +146,7 @@ const plotDiv = document.getElementById("plot")!;
 const metricsDiv = document.getElementById("metrics")!;
 
 const metrics = new Metrics(metricsDiv);
-const state = new State(metrics);
+const state = new State(metrics, plotDiv);
 state.update();
 
 // For debugging
@@ -1,3 +1,4 @@
+import { Marker } from "./markers.js";
 import { CommitsResponse } from "./requests.js";
 import { SECONDS_PER_DAY } from "./util.js";
 
@@ -10,6 +11,7 @@ type Commit = {
   author: string;
   committerDate: number;
   summary: string;
+  tags: string[];
 };
 
 export class Commits {
@@ -66,6 +68,7 @@ export class Commits {
         author: response.authorByHash[idx]!,
         committerDate: response.committerDateByHash[idx]!,
         summary: response.summaryByHash[idx]!,
+        tags: [],
       };
       commits.set(hash, commit);
       commitsByHash.push(commit);
@@ -83,9 +86,38 @@ export class Commits {
       parent.children.push(child);
     }
 
+    // Fill in tags
+    for (const [idx, tag] of response.tagIndexPairs) {
+      commitsByHash[idx]!.tags.push(tag);
+    }
+
     return commitsByHash;
   }
 
+  /**
+   * Positions of all commits on the x axis in seconds, in graph order.
+   */
+  xValues(): number[] {
+    return this.#committerDatesNormal.map((d) => d.getTime() / 1000);
+  }
+
+  /**
+   * Markers for all tagged commits, in graph order.
+   */
+  markers(): Marker[] {
+    const dates = this.#committerDatesNormal;
+
+    const markers = [];
+    for (const commit of this.#commitsByGraph) {
+      if (commit.tags.length === 0) continue;
+      markers.push({
+        x: dates[commit.indexByGraph]!.getTime() / 1000,
+        label: commit.tags.join(", "),
+      });
+    }
+    return markers;
+  }
+
   #sortCommitsByCommitterDate(commits: Commit[]) {
     commits.sort((a, b) => a.committerDate - b.committerDate);
   }
@@ -0,0 +1,52 @@
+import uPlot from "../uPlot.js";
+
+/**
+ * A labelled vertical line at a position on the x axis, e.g. for a release
+ * tag.
+ */
+export type Marker = {
+  x: number;
+  label: string;
+};
+
+const MARKER_COLOR = "#888";
+
+/**
+ * uPlot plugin drawing markers as vertical lines across the plot.
+ *
+ * The markers are retrieved anew every time the plot is drawn.
+ */
+export function markersPlugin(getMarkers: () => Marker[]): uPlot.Plugin {
+  function draw(u: uPlot) {
+    const ctx = u.ctx;
+    const { left, top, width, height } = u.bbox;
+
+    ctx.save();
+    ctx.beginPath();
+    ctx.rect(left, top, width, height);
+    ctx.clip();
+
+    ctx.strokeStyle = MARKER_COLOR;
+    ctx.fillStyle = MARKER_COLOR;
+    ctx.lineWidth = devicePixelRatio;
+    ctx.setLineDash([5 * devicePixelRatio, 5 * devicePixelRatio]);
+    ctx.font = `${12 * devicePixelRatio}px sans-serif`;
+    ctx.textBaseline = "top";
+
+    for (const marker of getMarkers()) {
+      const x = Math.round(u.valToPos(marker.x, "x", true));
+      if (x < left || x > left + width) continue;
+
+      ctx.beginPath();
+      ctx.moveTo(x, top);
+      ctx.lineTo(x, top + height);
+      ctx.stroke();
+
+      ctx.fillText(marker.label, x + 3 * devicePixelRatio, top);
+    }
+
+    ctx.restore();
+  }
+
+  return { hooks: { draw } };
+}
@@ -16,6 +16,7 @@ export type CommitsResponse = {
   committerDateByHash: number[];
   summaryByHash: string[];
   childParentIndexPairs: [number, number][];
+  tagIndexPairs: [number, string][];
 };
 
 /**
@@ -41,9 +42,14 @@ export async function getCommits(): Promise<CommitsResponse> {
   return getData("commits");
 }
 
+/**
+ * Measurements of failed runs are only included if `failed` is true.
+ */
 export async function getMeasurements(
   metrics: string[],
+  failed: boolean = false,
 ): Promise<MeasurementsResponse> {
   const params = new URLSearchParams(metrics.map((m) => ["metric", m]));
+  if (failed) params.append("failed", "true");
   return getData(`measurements?${params}`);
 }
@@ -1,7 +1,12 @@
+import uPlot from "../uPlot.js";
 import { Commits } from "./commits.js";
+import { markersPlugin } from "./markers.js";
 import { Metrics } from "./metrics.js";
 import { getCommits, getMetrics } from "./requests.js";
 
+const PLOT_WIDTH = 800;
+const PLOT_HEIGHT = 400;
+
 export class State {
   #latestGraphId: number = -Infinity;
   #latestDataId: number = -Infinity;
@@ -9,14 +14,18 @@ export class State {
   #metrics: Metrics;
   #commits: Commits = new Commits();
 
+  #plotDiv: HTMLElement;
+  #plot: uPlot | null = null;
+
   #requestingMetrics: boolean = false;
   #requestingCommits: boolean = false;
 
   // raw measurements (with graph id and data id)
   // processed measurements (with graph id and data id)
 
-  constructor(metrics: Metrics) {
+  constructor(metrics: Metrics, plotDiv: HTMLElement) {
     this.#metrics = metrics;
+    this.#plotDiv = plotDiv;
   }
 
   /**
@@ -31,6 +40,27 @@ export class State {
     this.#requestDataWhereNecessary();
   }
 
+  //////////////
+  // Plotting //
+  //////////////
+
+  /**
+   * Replace the plot with one for the current commits.
+   */
+  #replacePlot() {
+    this.#plot?.destroy();
+
+    const opts: uPlot.Options = {
+      width: PLOT_WIDTH,
+      height: PLOT_HEIGHT,
+      series: [{}],
+      plugins: [markersPlugin(() => this.#commits.markers())],
+    };
+    const data: uPlot.AlignedData = [this.#commits.xValues()];
+
+    this.#plot = new uPlot(opts, data, this.#plotDiv);
+  }
+
   //////////////////////////////////
   // Requesting and updating data //
   //////////////////////////////////
@@ -79,6 +109,7 @@ export class State {
       const response = await getCommits();
       this.#updateGraphId(response.graphId);
       this.#commits.update(response);
+      this.#replacePlot();
       this.update();
     } finally {
       this.#requestingCommits = false;
@@ -0,0 +1,152 @@
+//! Data structures of the public json API below `/api/v1/`.
+//!
+//! Unlike the worker protocol in [`crate::shared`], these are meant to be
+//! consumed by other tools, so fields should only ever be added, not changed or
+//! removed.
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::primitive::{Direction, QueueKind, Reachable, Timestamp};
+
+/// What every endpoint responds with when something goes wrong.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Error {
+    pub error: String,
+}
+
+/// One page of a longer list.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+
+    /// Pass this as `cursor` to get the next page, or `null` if this is the
+    /// last page. The format of the cursor is unspecified.
+    pub next: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Commit {
+    pub hash: String,
+    pub author: String,
+    pub author_date: Timestamp,
+    pub committer: String,
+    pub committer_date: Timestamp,
+    pub message: String,
+    pub reachable: Reachable,
+
+    /// In the order they appear in the commit.
+    pub parents: Vec<String>,
+
+    pub children: Vec<String>,
+
+    /// Names of the refs pointing to this commit.
+    pub refs: Vec<String>,
+
+    /// Oldest run first.
+    pub runs: Vec<RunSummary>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct RunSummary {
+    pub id: String,
+    pub hash: String,
+    pub bench_method: String,
+    pub worker_name: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub exit_code: i64,
+    pub kind: QueueKind,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Measurement {
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Phase {
+    pub name: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub exit_code: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Run {
+    #[serde(flatten)]
+    pub summary: RunSummary,
+    pub worker_info: Option<String>,
+    pub phases: Vec<Phase>,
+    pub measurements: BTreeMap<String, Measurement>,
+    /// Names of the artifacts uploaded for this run.
+    pub artifacts: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Metric {
+    pub name: String,
+    pub unit: Option<String>,
+    pub direction: Direction,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct QueueEntry {
+    pub hash: String,
+    pub date: Timestamp,
+    pub priority: i64,
+    pub kind: QueueKind,
+    /// How often runs of this commit failed so far.
+    pub failures: i64,
+    /// The bench repo commit to run, or `null` for the bench repo's HEAD.
+    pub bench_hash: Option<String>,
+    /// The benchmarks to run, or empty to run all benchmarks.
+    pub benchmarks: Vec<String>,
+    /// How many more successful runs this commit needs before it leaves the
+    /// queue.
+    pub repetitions: i64,
+}
+
+/// One measurement of a successful run, as exported below `/api/v1/export`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ExportRow {
+    pub hash: String,
+    pub committer_date: Timestamp,
+    pub run_id: String,
+    pub worker_name: String,
+    pub metric: String,
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+fn default_repetitions() -> u32 {
+    1
+}
+
+/// Request body for adding commits to the queue.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct QueueAdd {
+    /// Commit hashes, unambiguous hash prefixes or ref names.
+    pub commits: Vec<String>,
+    #[serde(default)]
+    pub priority: i32,
+    /// How many successful runs each commit needs before it leaves the queue.
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+    /// A revision of the bench repo to run, or `null` for the bench repo's
+    /// HEAD.
+    #[serde(default)]
+    pub bench_hash: Option<String>,
+    /// The benchmarks to run, or empty to run all benchmarks.
+    #[serde(default)]
+    pub benchmarks: Vec<String>,
+}
+
+/// Request body for changing a queue entry.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct QueueUpdate {
+    pub priority: i32,
+}
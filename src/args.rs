@@ -1,12 +1,16 @@
 use std::path::PathBuf;
 
+use crate::{primitive::Timestamp, server::export::ExportFormat};
+
 pub const NAME: &str = env!("CARGO_PKG_NAME");
 pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("VERGEN_GIT_SHA"), ")");
 
 #[derive(Debug, clap::Parser)]
 pub struct ServerCommand {
     /// Path to a tablejohn database.
-    pub db: PathBuf,
+    ///
+    /// Required unless projects are configured in the config file.
+    pub db: Option<PathBuf>,
 
     /// Path to a git repo.
     pub repo: Option<PathBuf>,
@@ -24,13 +28,104 @@ pub struct ServerCommand {
     pub local_worker: u8,
 }
 
+#[derive(Debug, clap::Parser)]
+pub struct ImportCommand {
+    /// Path to the tablejohn database to import into.
+    ///
+    /// Runs of commits that aren't in the database are skipped, so the server
+    /// should have updated it from the repo at least once.
+    pub db: PathBuf,
+
+    /// Path to the file to import runs from.
+    ///
+    /// Either json lines of finished runs in the format workers submit them
+    /// in, or a velcom database.
+    pub file: PathBuf,
+
+    /// Worker name to attribute runs from json lines to.
+    #[arg(long, short, default_value = "import")]
+    pub worker: String,
+
+    /// Id of the velcom repo whose runs to import.
+    ///
+    /// Required if the velcom database contains runs of more than one repo.
+    #[arg(long)]
+    pub velcom_repo: Option<String>,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct ExportCommand {
+    /// Path to the tablejohn database to export from.
+    pub db: PathBuf,
+
+    /// File to write the export to, instead of stdout.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Format of the export.
+    #[arg(long, short, value_enum, default_value_t)]
+    pub format: ExportFormat,
+
+    /// Only export metrics starting with this prefix.
+    ///
+    /// May be specified multiple times.
+    #[arg(long, short)]
+    pub metric: Vec<String>,
+
+    /// Only export commits reachable from this ref or commit.
+    #[arg(long, short)]
+    pub r#ref: Option<String>,
+
+    /// Only export commits committed at or after this time (RFC 3339).
+    #[arg(long)]
+    pub since: Option<Timestamp>,
+
+    /// Only export commits committed at or before this time (RFC 3339).
+    #[arg(long)]
+    pub until: Option<Timestamp>,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct BenchCommand {
+    /// Path to the git repo to benchmark.
+    pub repo: PathBuf,
+
+    /// Commit to benchmark, may be any revision like a ref name.
+    pub commit: String,
+
+    /// Path to a bench repo whose scripts to run.
+    ///
+    /// Without a bench repo, the internal bench method is used.
+    #[arg(long, short)]
+    pub bench_repo: Option<PathBuf>,
+
+    /// Commit of the bench repo to use, may be any revision like a ref name.
+    #[arg(long, default_value = "HEAD")]
+    pub bench_commit: String,
+
+    /// Only run this benchmark of the bench repo.
+    ///
+    /// May be specified multiple times.
+    #[arg(long)]
+    pub benchmark: Vec<String>,
+
+    /// Print the finished run as json, in the format workers submit it in.
+    #[arg(long)]
+    pub json: bool,
+}
+
 #[derive(Debug, clap::Parser)]
 pub enum Command {
     /// Start a tablejohn server.
     Server(ServerCommand),
     /// Start a tablejohn worker.
     Worker,
-    // TODO bench script command?
+    /// Import runs from other sources into a tablejohn database.
+    Import(ImportCommand),
+    /// Export measurements from a tablejohn database.
+    Export(ExportCommand),
+    /// Perform a single run locally, like a worker would.
+    Bench(BenchCommand),
 }
 
 #[derive(Debug, clap::Parser)]
@@ -4,23 +4,79 @@ use std::{
     collections::HashMap, fs, io::ErrorKind, net::SocketAddr, path::PathBuf, time::Duration,
 };
 
+use anyhow::anyhow;
 use directories::ProjectDirs;
-use log::{info, trace};
+use lettre::message::Mailbox;
+use log::{info, trace, warn};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
 use serde::Deserialize;
 
 use crate::{
-    args::{Args, Command},
-    id, somehow,
+    args::{Args, Command, NAME},
+    cron::Cron,
+    id,
+    primitive::Event,
+    somehow,
 };
 
-#[derive(Debug, Deserialize)]
+fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(with = "serde_humanize_rs")] Duration);
+
+    let wrapper = Option::<Wrapper>::deserialize(deserializer)?;
+    Ok(wrapper.map(|w| w.0))
+}
+
+fn header_map(name: &str, raw: HashMap<String, String>) -> somehow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (k, v) in raw {
+        let k = HeaderName::from_bytes(k.as_bytes())
+            .map_err(|e| somehow::Error(anyhow!("Invalid header name for {name:?}: {e}")))?;
+        let v = HeaderValue::from_str(&v).map_err(|e| {
+            somehow::Error(anyhow!("Invalid value of header {k} for {name:?}: {e}"))
+        })?;
+        headers.insert(k, v);
+    }
+    Ok(headers)
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct RawServerRemote {
+    url: String,
+    refspecs: Option<Vec<String>>,
+}
+
+/// How the repo is fetched from its remotes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoFetcher {
+    /// Using the `git` binary.
+    #[default]
+    Git,
+    /// Natively, without a git installation.
+    Gix,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
 #[serde(default)]
 struct RawServerRepo {
     name: Option<String>,
     #[serde(with = "serde_humanize_rs")]
     update: Duration,
+    fetcher: RepoFetcher,
     fetch_url: Option<String>,
     fetch_refspecs: Vec<String>,
+    remotes: HashMap<String, RawServerRemote>,
+    track: Vec<String>,
+    ignore: Vec<String>,
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    prune_unreachable: Option<Duration>,
 }
 
 impl Default for RawServerRepo {
@@ -28,8 +84,16 @@ impl Default for RawServerRepo {
         Self {
             name: None,
             update: Duration::from_secs(60),
+            fetcher: RepoFetcher::default(),
             fetch_url: None,
-            fetch_refspecs: vec!["+refs/heads/*:refs/heads/*".to_string()],
+            fetch_refspecs: vec![
+                "+refs/heads/*:refs/heads/*".to_string(),
+                "+refs/tags/*:refs/tags/*".to_string(),
+            ],
+            remotes: HashMap::new(),
+            track: vec![],
+            ignore: vec![],
+            prune_unreachable: None,
         }
     }
 }
@@ -58,6 +122,10 @@ struct RawServerWorker {
     timeout: Duration,
     #[serde(with = "serde_humanize_rs")]
     upload: usize,
+    #[serde(with = "serde_humanize_rs")]
+    artifact_upload: usize,
+    #[serde(with = "serde_humanize_rs")]
+    artifact_total: usize,
 }
 
 impl Default for RawServerWorker {
@@ -66,16 +134,195 @@ impl Default for RawServerWorker {
             token: None,
             timeout: Duration::from_secs(60),
             upload: 1024 * 1024 * 8,
+            artifact_upload: 1024 * 1024 * 64,
+            artifact_total: 1024 * 1024 * 256,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default)]
+struct RawServerQueue {
+    retries: u32,
+    bisect_threshold: Option<f64>,
+    bisect_priority: i32,
+    bisect_metrics: Vec<String>,
+    bisect_depth: u32,
+}
+
+impl Default for RawServerQueue {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            bisect_threshold: None,
+            bisect_priority: 10,
+            bisect_metrics: vec![],
+            bisect_depth: 1000,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default)]
+struct RawServerReports {
+    refs: Vec<String>,
+    base: Option<String>,
+    priority: i32,
+}
+
+impl Default for RawServerReports {
+    fn default() -> Self {
+        Self {
+            refs: vec![],
+            base: None,
+            priority: 5,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawServerHooks {
+    secret: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawServerApi {
+    tokens: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawServerSchedule {
+    cron: Cron,
+    #[serde(default)]
+    priority: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct RawServerStatusStates {
+    success: String,
+    failure: String,
+    error: String,
+}
+
+impl Default for RawServerStatusStates {
+    fn default() -> Self {
+        Self {
+            success: "success".to_string(),
+            failure: "failure".to_string(),
+            error: "error".to_string(),
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct RawServerStatus {
+    url: String,
+    method: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    threshold: Option<f64>,
+    retries: Option<u32>,
+    #[serde(default)]
+    states: RawServerStatusStates,
+}
+
+/// How webhook payloads are formatted.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// The full event as a json object.
+    #[default]
+    Json,
+    /// Just the message, as expected by Slack-compatible incoming webhooks.
+    Slack,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RawServerNotificationTarget {
+    Webhook {
+        url: String,
+        #[serde(default)]
+        format: WebhookFormat,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    Email {
+        to: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct RawServerNotification {
+    #[serde(flatten)]
+    target: RawServerNotificationTarget,
+    events: Option<Vec<Event>>,
+    threshold: Option<f64>,
+    retries: Option<u32>,
+}
+
+/// How to secure the connection to the smtp server.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Connect via TLS right away, usually on port 465.
+    Tls,
+    /// Upgrade a plain connection via STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// Don't encrypt anything, only useful for local relays.
+    None,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawServerSmtp {
+    host: Option<String>,
+    port: Option<u16>,
+    tls: SmtpTls,
+    username: Option<String>,
+    password: Option<String>,
+    from: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawServerProject {
+    db: PathBuf,
+    repo: Option<PathBuf>,
+    bench_repo: Option<PathBuf>,
+    #[serde(flatten)]
+    settings: RawServerRepo,
+    #[serde(default)]
+    queue: RawServerQueue,
+    #[serde(default)]
+    reports: RawServerReports,
+    #[serde(default)]
+    schedules: HashMap<String, RawServerSchedule>,
+    #[serde(default)]
+    statuses: HashMap<String, RawServerStatus>,
+    #[serde(default)]
+    notifications: HashMap<String, RawServerNotification>,
+}
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(default)]
 struct RawServer {
     repo: RawServerRepo,
     web: RawServerWeb,
     worker: RawServerWorker,
+    queue: RawServerQueue,
+    reports: RawServerReports,
+    hooks: RawServerHooks,
+    api: RawServerApi,
+    schedules: HashMap<String, RawServerSchedule>,
+    statuses: HashMap<String, RawServerStatus>,
+    notifications: HashMap<String, RawServerNotification>,
+    smtp: RawServerSmtp,
+    projects: HashMap<String, RawServerProject>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -113,12 +360,210 @@ struct RawConfig {
     worker: RawWorker,
 }
 
+/// A schedule for periodically adding the tips of all tracked refs to the
+/// queue, even if they already have runs.
+#[derive(Debug)]
+pub struct ServerSchedule {
+    pub cron: Cron,
+    pub priority: i32,
+}
+
+impl ServerSchedule {
+    fn from_raw_server_schedule(raw: RawServerSchedule) -> Self {
+        Self {
+            cron: raw.cron,
+            priority: raw.priority,
+        }
+    }
+}
+
+/// An HTTP endpoint that commit statuses are posted to whenever a run finishes.
+///
+/// The url and body are templates, see [`crate::server::status`].
+#[derive(Debug)]
+pub struct ServerStatus {
+    pub name: String,
+    pub url: String,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+    /// Relative change of a metric that counts as a regression or improvement.
+    pub threshold: f64,
+    /// How often a failed delivery is retried before it is dropped.
+    pub retries: u32,
+    pub state_success: String,
+    pub state_failure: String,
+    pub state_error: String,
+}
+
+impl ServerStatus {
+    fn from_raw_server_status(name: String, raw: RawServerStatus) -> somehow::Result<Self> {
+        let method = raw.method.as_deref().unwrap_or("POST").to_ascii_uppercase();
+        let method = Method::from_bytes(method.as_bytes())
+            .map_err(|e| somehow::Error(anyhow!("Invalid method for status {name:?}: {e}")))?;
+
+        let headers = header_map(&name, raw.headers)?;
+
+        Ok(Self {
+            name,
+            url: raw.url,
+            method,
+            headers,
+            body: raw.body,
+            threshold: raw.threshold.unwrap_or(0.05),
+            retries: raw.retries.unwrap_or(5),
+            state_success: raw.states.success,
+            state_failure: raw.states.failure,
+            state_error: raw.states.error,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum NotificationTarget {
+    Webhook {
+        url: String,
+        format: WebhookFormat,
+        headers: HeaderMap,
+    },
+    Email {
+        to: Vec<Mailbox>,
+    },
+}
+
+/// Somewhere to send notifications about events to.
+#[derive(Debug)]
+pub struct ServerNotification {
+    pub name: String,
+    pub target: NotificationTarget,
+    pub events: Vec<Event>,
+    /// Relative change of a metric that counts as a regression.
+    pub threshold: f64,
+    /// How often a failed delivery is retried before it is dropped.
+    pub retries: u32,
+}
+
+impl ServerNotification {
+    fn from_raw_server_notification(
+        name: String,
+        raw: RawServerNotification,
+        smtp: Option<&ServerSmtp>,
+    ) -> somehow::Result<Self> {
+        let target = match raw.target {
+            RawServerNotificationTarget::Webhook {
+                url,
+                format,
+                headers,
+            } => NotificationTarget::Webhook {
+                url,
+                format,
+                headers: header_map(&name, headers)?,
+            },
+            RawServerNotificationTarget::Email { to } => {
+                if smtp.is_none() {
+                    return Err(somehow::Error(anyhow!(
+                        "Notification {name:?} sends emails but no smtp host is configured"
+                    )));
+                }
+                let to = to
+                    .iter()
+                    .map(|to| to.parse::<Mailbox>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| {
+                        somehow::Error(anyhow!("Invalid address for notification {name:?}: {e}"))
+                    })?;
+                NotificationTarget::Email { to }
+            }
+        };
+
+        Ok(Self {
+            name,
+            target,
+            events: raw.events.unwrap_or_else(|| Event::ALL.to_vec()),
+            threshold: raw.threshold.unwrap_or(0.05),
+            retries: raw.retries.unwrap_or(5),
+        })
+    }
+}
+
+/// The smtp server that email notifications are sent through.
+#[derive(Debug, Clone)]
+pub struct ServerSmtp {
+    pub host: String,
+    /// Depends on the kind of TLS if not specified.
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: Mailbox,
+}
+
+impl ServerSmtp {
+    fn from_raw_server_smtp(raw: RawServerSmtp) -> somehow::Result<Option<Self>> {
+        let Some(host) = raw.host else {
+            return Ok(None);
+        };
+
+        let from = raw
+            .from
+            .unwrap_or_else(|| format!("{NAME} <{NAME}@{host}>"));
+        let from = from
+            .parse::<Mailbox>()
+            .map_err(|e| somehow::Error(anyhow!("Invalid smtp from address: {e}")))?;
+
+        Ok(Some(Self {
+            host,
+            port: raw.port,
+            tls: raw.tls,
+            username: raw.username,
+            password: raw.password,
+            from,
+        }))
+    }
+}
+
+/// A remote whose refs are fetched into the repo.
+#[derive(Debug)]
+pub struct ServerRemote {
+    pub name: String,
+    pub url: String,
+    /// Each remote should map its refs into a namespace of its own, otherwise
+    /// fetching one remote may prune the refs of another.
+    pub refspecs: Vec<String>,
+}
+
+impl ServerRemote {
+    fn from_raw_server_remote(name: String, raw: RawServerRemote) -> Self {
+        let refspecs = match raw.refspecs {
+            Some(refspecs) => refspecs,
+            // Tags are left out because they would end up in the same
+            // namespace as the tags of other remotes.
+            None => vec![format!("+refs/heads/*:refs/remotes/{name}/*")],
+        };
+
+        Self {
+            name,
+            url: raw.url,
+            refspecs,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ServerConfig {
     pub repo_name: String,
     pub repo_update: Duration,
-    pub repo_fetch_refspecs: Vec<String>,
-    pub repo_fetch_url: Option<String>,
+    pub repo_fetcher: RepoFetcher,
+    /// Remotes to fetch from, in the order they are fetched.
+    pub repo_remotes: Vec<ServerRemote>,
+    /// Glob patterns of refs that are tracked automatically when they first
+    /// appear. If empty, only HEAD is tracked when the repo is first imported.
+    pub repo_track: Vec<String>,
+    /// Glob patterns of refs that are ignored entirely.
+    pub repo_ignore: Vec<String>,
+    /// How long unreachable commits without runs are kept before they are
+    /// removed, or `None` if they are kept forever.
+    pub repo_prune_unreachable: Option<Duration>,
     pub web_address: SocketAddr,
     /// Always starts with a `/` and ends without a `/`, preferring the latter.
     ///
@@ -128,6 +573,45 @@ pub struct ServerConfig {
     pub worker_token: String,
     pub worker_timeout: Duration,
     pub worker_upload: usize,
+    /// Maximum size of a single artifact uploaded by a worker.
+    pub worker_artifact_upload: usize,
+    /// Maximum combined size of all artifacts of a single run.
+    pub worker_artifact_total: usize,
+    /// How often a commit whose runs fail is handed out again before it is
+    /// removed from the queue.
+    pub queue_retries: u32,
+    /// Relative change of a metric between two commits that triggers a
+    /// bisection, or `None` if bisection is disabled.
+    pub queue_bisect_threshold: Option<f64>,
+    pub queue_bisect_priority: i32,
+    /// Prefixes of metrics to consider for bisection. If empty, all metrics are
+    /// considered.
+    pub queue_bisect_metrics: Vec<String>,
+    /// How many commits along the first-parent chain of each tracked ref are
+    /// looked at for jumps.
+    pub queue_bisect_depth: u32,
+    /// Glob patterns of refs whose tip and merge base are added to the queue
+    /// automatically so they can be compared in a report.
+    pub reports_refs: Vec<String>,
+    /// The ref reports compare against, or `None` to use the tracked ref with
+    /// the most recent merge base.
+    pub reports_base: Option<String>,
+    pub reports_priority: i32,
+    /// Secret used to verify push hooks, or `None` if push hooks are disabled.
+    pub hooks_secret: Option<String>,
+    /// Tokens that may change the queue through the json API. If empty, the
+    /// queue can't be changed through the json API.
+    pub api_tokens: Vec<String>,
+    pub schedules: HashMap<String, ServerSchedule>,
+    /// Where to post commit statuses to, sorted by name.
+    pub statuses: Vec<ServerStatus>,
+    /// Where to send notifications about events to, sorted by name.
+    pub notifications: Vec<ServerNotification>,
+    /// The smtp server for email notifications, if any.
+    pub smtp: Option<ServerSmtp>,
+    /// Projects hosted by this server, sorted by name. If empty, the server
+    /// hosts a single project whose paths are given on the command line.
+    pub projects: Vec<ServerProject>,
 }
 
 impl ServerConfig {
@@ -156,30 +640,240 @@ impl ServerConfig {
         base
     }
 
-    fn from_raw_server(raw: RawServer, args: &Args) -> Self {
+    /// The plain `fetch_url` predates named remotes and is treated like a
+    /// remote called `origin` that fetches directly into `refs/heads/`.
+    fn repo_remotes(
+        fetch_url: Option<String>,
+        fetch_refspecs: Vec<String>,
+        remotes: HashMap<String, RawServerRemote>,
+    ) -> Vec<ServerRemote> {
+        let mut result = vec![];
+
+        if let Some(url) = fetch_url {
+            if remotes.contains_key("origin") {
+                warn!("Ignoring fetch_url because a remote called origin is configured");
+            } else {
+                result.push(ServerRemote {
+                    name: "origin".to_string(),
+                    url,
+                    refspecs: fetch_refspecs,
+                });
+            }
+        }
+
+        let mut named = remotes
+            .into_iter()
+            .map(|(k, v)| ServerRemote::from_raw_server_remote(k, v))
+            .collect::<Vec<_>>();
+        named.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        result.extend(named);
+
+        result
+    }
+
+    fn schedules(raw: HashMap<String, RawServerSchedule>) -> HashMap<String, ServerSchedule> {
+        raw.into_iter()
+            .map(|(k, v)| (k, ServerSchedule::from_raw_server_schedule(v)))
+            .collect()
+    }
+
+    fn statuses(raw: HashMap<String, RawServerStatus>) -> somehow::Result<Vec<ServerStatus>> {
+        let mut statuses = raw
+            .into_iter()
+            .map(|(k, v)| ServerStatus::from_raw_server_status(k, v))
+            .collect::<somehow::Result<Vec<_>>>()?;
+        statuses.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Ok(statuses)
+    }
+
+    fn notifications(
+        raw: HashMap<String, RawServerNotification>,
+        smtp: Option<&ServerSmtp>,
+    ) -> somehow::Result<Vec<ServerNotification>> {
+        let mut notifications = raw
+            .into_iter()
+            .map(|(k, v)| ServerNotification::from_raw_server_notification(k, v, smtp))
+            .collect::<somehow::Result<Vec<_>>>()?;
+        notifications.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Ok(notifications)
+    }
+
+    /// Projects bring their own repo, queue, reports, schedules, statuses and
+    /// notifications, so the server's would be silently ignored. Only the repo
+    /// name is used, as the title of the project list.
+    fn check_projects(raw: &RawServer) -> somehow::Result<()> {
+        if raw.projects.is_empty() {
+            return Ok(());
+        }
+
+        let repo = RawServerRepo {
+            name: raw.repo.name.clone(),
+            ..Default::default()
+        };
+        let ignored = [
+            ("repo", raw.repo != repo),
+            ("queue", raw.queue != RawServerQueue::default()),
+            ("reports", raw.reports != RawServerReports::default()),
+            ("schedules", !raw.schedules.is_empty()),
+            ("statuses", !raw.statuses.is_empty()),
+            ("notifications", !raw.notifications.is_empty()),
+        ];
+        for (section, set) in ignored {
+            if set {
+                return Err(somehow::Error(anyhow!(
+                    "Projects are configured, so [server.{section}] settings must be set per project"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn from_raw_server(raw: RawServer, args: &Args) -> somehow::Result<Self> {
+        Self::check_projects(&raw)?;
+
         let repo_name = match raw.repo.name {
             Some(name) => name,
+            None if !raw.projects.is_empty() => NAME.to_string(),
             None => Self::repo_name(args),
         };
 
+        let repo_remotes = Self::repo_remotes(
+            raw.repo.fetch_url,
+            raw.repo.fetch_refspecs,
+            raw.repo.remotes,
+        );
+
         let web_base = Self::web_base(raw.web.base);
 
+        let smtp = ServerSmtp::from_raw_server_smtp(raw.smtp)?;
+        let notifications = Self::notifications(raw.notifications, smtp.as_ref())?;
+
         let worker_token = match raw.worker.token {
             Some(token) => token,
             None => id::random_worker_token(),
         };
 
-        Self {
+        let mut config = Self {
             repo_name,
             repo_update: raw.repo.update,
-            repo_fetch_url: raw.repo.fetch_url,
-            repo_fetch_refspecs: raw.repo.fetch_refspecs,
+            repo_fetcher: raw.repo.fetcher,
+            repo_remotes,
+            repo_track: raw.repo.track,
+            repo_ignore: raw.repo.ignore,
+            repo_prune_unreachable: raw.repo.prune_unreachable,
             web_address: raw.web.address,
             web_base,
             worker_token,
             worker_timeout: raw.worker.timeout,
             worker_upload: raw.worker.upload,
+            worker_artifact_upload: raw.worker.artifact_upload,
+            worker_artifact_total: raw.worker.artifact_total,
+            queue_retries: raw.queue.retries,
+            queue_bisect_threshold: raw.queue.bisect_threshold,
+            queue_bisect_priority: raw.queue.bisect_priority,
+            queue_bisect_metrics: raw.queue.bisect_metrics,
+            queue_bisect_depth: raw.queue.bisect_depth,
+            reports_refs: raw.reports.refs,
+            reports_base: raw.reports.base,
+            reports_priority: raw.reports.priority,
+            hooks_secret: raw.hooks.secret,
+            api_tokens: raw.api.tokens,
+            schedules: Self::schedules(raw.schedules),
+            statuses: Self::statuses(raw.statuses)?,
+            notifications,
+            smtp,
+            projects: vec![],
+        };
+
+        let mut projects = raw
+            .projects
+            .into_iter()
+            .map(|(name, project)| ServerProject::from_raw_server_project(&config, name, project))
+            .collect::<somehow::Result<Vec<_>>>()?;
+        projects.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        config.projects = projects;
+
+        Ok(config)
+    }
+}
+
+/// A project hosted alongside others by the same server, with its own db, repo,
+/// bench repo and settings.
+#[derive(Debug)]
+pub struct ServerProject {
+    pub name: String,
+    pub db: PathBuf,
+    pub repo: Option<PathBuf>,
+    pub bench_repo: Option<PathBuf>,
+    /// Settings of the server as seen by this project. Its web base points to
+    /// the project's pages.
+    pub config: ServerConfig,
+}
+
+impl ServerProject {
+    fn is_name_valid(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    }
+
+    /// Web, worker, hook, api and smtp settings are shared by all projects and
+    /// taken from the server.
+    fn from_raw_server_project(
+        server: &ServerConfig,
+        name: String,
+        raw: RawServerProject,
+    ) -> somehow::Result<Self> {
+        if !Self::is_name_valid(&name) {
+            return Err(somehow::Error(anyhow!("Invalid project name {name:?}")));
         }
+
+        let repo = raw.settings;
+        let config = ServerConfig {
+            repo_name: repo.name.unwrap_or_else(|| name.clone()),
+            repo_update: repo.update,
+            repo_fetcher: repo.fetcher,
+            repo_remotes: ServerConfig::repo_remotes(
+                repo.fetch_url,
+                repo.fetch_refspecs,
+                repo.remotes,
+            ),
+            repo_track: repo.track,
+            repo_ignore: repo.ignore,
+            repo_prune_unreachable: repo.prune_unreachable,
+            web_address: server.web_address,
+            web_base: format!("{}/p/{name}", server.web_base),
+            worker_token: server.worker_token.clone(),
+            worker_timeout: server.worker_timeout,
+            worker_upload: server.worker_upload,
+            worker_artifact_upload: server.worker_artifact_upload,
+            worker_artifact_total: server.worker_artifact_total,
+            queue_retries: raw.queue.retries,
+            queue_bisect_threshold: raw.queue.bisect_threshold,
+            queue_bisect_priority: raw.queue.bisect_priority,
+            queue_bisect_metrics: raw.queue.bisect_metrics,
+            queue_bisect_depth: raw.queue.bisect_depth,
+            reports_refs: raw.reports.refs,
+            reports_base: raw.reports.base,
+            reports_priority: raw.reports.priority,
+            hooks_secret: server.hooks_secret.clone(),
+            api_tokens: server.api_tokens.clone(),
+            schedules: ServerConfig::schedules(raw.schedules),
+            statuses: ServerConfig::statuses(raw.statuses)?,
+            notifications: ServerConfig::notifications(raw.notifications, server.smtp.as_ref())?,
+            smtp: server.smtp.clone(),
+            projects: vec![],
+        };
+
+        Ok(Self {
+            name,
+            db: raw.db,
+            repo: raw.repo,
+            bench_repo: raw.bench_repo,
+            config,
+        })
     }
 }
 
@@ -239,11 +933,11 @@ pub struct Config {
 }
 
 impl Config {
-    fn from_raw_config(raw: RawConfig, args: &Args) -> Self {
-        Self {
-            server: ServerConfig::from_raw_server(raw.server, args),
+    fn from_raw_config(raw: RawConfig, args: &Args) -> somehow::Result<Self> {
+        Ok(Self {
+            server: ServerConfig::from_raw_server(raw.server, args)?,
             worker: WorkerConfig::from_raw_worker(raw.worker),
-        }
+        })
     }
 
     fn path(args: &Args) -> PathBuf {
@@ -271,7 +965,7 @@ impl Config {
         };
         trace!("Raw config: {raw:#?}");
 
-        let config = Self::from_raw_config(raw, args);
+        let config = Self::from_raw_config(raw, args)?;
         trace!("Config: {config:#?}");
 
         Ok(config)
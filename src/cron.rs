@@ -0,0 +1,364 @@
+//! Cron-like schedules.
+//!
+//! Schedules use the five fields known from crontab (minute, hour, day of
+//! month, month, day of week) and are always interpreted in UTC. Each field
+//! can be `*`, a value, a range `a-b` or a comma-separated list of those, each
+//! optionally followed by a step `/n`. The shortcuts `@hourly`, `@daily`,
+//! `@weekly`, `@monthly` and `@yearly` are also supported.
+
+use std::{fmt, str::FromStr};
+
+use serde::de;
+use time::{Duration, OffsetDateTime, Time, UtcOffset};
+
+/// How far into the future to look for the next matching time before giving
+/// up, for schedules like `0 0 30 2 *` that never match.
+const MAX_DAYS: i64 = 366 * 5;
+
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    /// Bit `n` is set if the value `n` is allowed.
+    allowed: u64,
+    /// Whether the field was anything other than `*`.
+    restricted: bool,
+}
+
+impl Field {
+    fn parse(field: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut allowed = 0;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step = step
+                        .parse::<u32>()
+                        .map_err(|_| format!("invalid step {step:?}"))?;
+                    (range, Some(step))
+                }
+                None => (part, None),
+            };
+
+            let value = |s: &str| {
+                s.parse::<u32>()
+                    .ok()
+                    .filter(|v| (min..=max).contains(v))
+                    .ok_or_else(|| format!("invalid value {s:?}, expected {min}-{max}"))
+            };
+            let (start, end) = match range.split_once('-') {
+                _ if range == "*" => (min, max),
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step.is_some() => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            };
+
+            if start > end {
+                return Err(format!("invalid range {range:?}"));
+            }
+            let step = step.unwrap_or(1);
+            if step == 0 {
+                return Err("step must not be 0".to_string());
+            }
+
+            for v in (start..=end).step_by(step as usize) {
+                allowed |= 1 << v;
+            }
+        }
+
+        Ok(Self {
+            allowed,
+            restricted: !field.starts_with('*'),
+        })
+    }
+
+    fn contains(self, value: u8) -> bool {
+        self.allowed & (1 << value) != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cron {
+    source: String,
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl Cron {
+    fn matches_date(&self, date: time::Date) -> bool {
+        if !self.months.contains(date.month() as u8) {
+            return false;
+        }
+
+        let day = self.days.contains(date.day());
+        let weekday = self
+            .weekdays
+            .contains(date.weekday().number_days_from_sunday());
+
+        // Like in crontab, if both fields are restricted, matching either one
+        // is enough.
+        if self.days.restricted && self.weekdays.restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// The first time strictly after `time` matching the schedule.
+    pub fn next_after(&self, time: OffsetDateTime) -> Option<OffsetDateTime> {
+        let time = time.to_offset(UtcOffset::UTC);
+        let mut next = time.replace_time(Time::from_hms(time.hour(), time.minute(), 0).ok()?)
+            + Duration::MINUTE;
+        let limit = next + Duration::days(MAX_DAYS);
+
+        while next < limit {
+            if !self.matches_date(next.date()) {
+                next = next.date().next_day()?.midnight().assume_utc();
+            } else if !self.hours.contains(next.hour()) {
+                next = next.replace_time(Time::from_hms(next.hour(), 0, 0).ok()?) + Duration::HOUR;
+            } else if !self.minutes.contains(next.minute()) {
+                next += Duration::MINUTE;
+            } else {
+                return Some(next);
+            }
+        }
+
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expanded = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+
+        let mut weekdays = Field::parse(weekdays, 0, 7)?;
+        // Both 0 and 7 are sunday.
+        if weekdays.contains(7) {
+            weekdays.allowed |= 1;
+        }
+
+        Ok(Self {
+            source: s.to_string(),
+            minutes: Field::parse(minutes, 0, 59)?,
+            hours: Field::parse(hours, 0, 23)?,
+            days: Field::parse(days, 1, 31)?,
+            months: Field::parse(months, 1, 12)?,
+            weekdays,
+        })
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Cron {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let input: String = serde::Deserialize::deserialize(deserializer)?;
+        input
+            .parse()
+            .map_err(|e| de::Error::custom(format!("invalid schedule {input:?}: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn cron(s: &str) -> Cron {
+        s.parse().unwrap()
+    }
+
+    fn values(field: Field, min: u8, max: u8) -> Vec<u8> {
+        (min..=max).filter(|v| field.contains(*v)).collect()
+    }
+
+    fn next(schedule: &str, time: OffsetDateTime) -> OffsetDateTime {
+        cron(schedule).next_after(time).unwrap()
+    }
+
+    #[test]
+    fn field_values() {
+        let field = |s| values(Field::parse(s, 0, 59).unwrap(), 0, 63);
+
+        assert_eq!(field("*"), (0..=59).collect::<Vec<_>>());
+        assert_eq!(field("0"), [0]);
+        assert_eq!(field("59"), [59]);
+        assert_eq!(field("10-13"), [10, 11, 12, 13]);
+        assert_eq!(field("5,1,3"), [1, 3, 5]);
+        assert_eq!(field("1-3,2-4,50"), [1, 2, 3, 4, 50]);
+    }
+
+    #[test]
+    fn field_steps() {
+        let field = |s| values(Field::parse(s, 0, 59).unwrap(), 0, 63);
+
+        assert_eq!(field("*/15"), [0, 15, 30, 45]);
+        assert_eq!(field("10-30/7"), [10, 17, 24]);
+        assert_eq!(field("50/3"), [50, 53, 56, 59]);
+        assert_eq!(field("*/60"), [0]);
+        assert_eq!(field("0-5/2,*/20"), [0, 2, 4, 20, 40]);
+
+        let days = values(Field::parse("*/10", 1, 31).unwrap(), 0, 63);
+        assert_eq!(days, [1, 11, 21, 31]);
+    }
+
+    #[test]
+    fn field_errors() {
+        let field = |s| Field::parse(s, 1, 12);
+
+        assert!(field("0").is_err());
+        assert!(field("13").is_err());
+        assert!(field("1-13").is_err());
+        assert!(field("5-3").is_err());
+        assert!(field("*/0").is_err());
+        assert!(field("*/x").is_err());
+        assert!(field("jan").is_err());
+        assert!(field("").is_err());
+        assert!(field("1,").is_err());
+        assert!(field("-1").is_err());
+    }
+
+    #[test]
+    fn field_restricted() {
+        assert!(!Field::parse("*", 1, 31).unwrap().restricted);
+        assert!(!Field::parse("*/2", 1, 31).unwrap().restricted);
+        assert!(Field::parse("1-31", 1, 31).unwrap().restricted);
+        assert!(Field::parse("1,15", 1, 31).unwrap().restricted);
+    }
+
+    #[test]
+    fn schedule_errors() {
+        assert!("".parse::<Cron>().is_err());
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("* * * * * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("* 24 * * *".parse::<Cron>().is_err());
+        assert!("* * 0 * *".parse::<Cron>().is_err());
+        assert!("* * 32 * *".parse::<Cron>().is_err());
+        assert!("* * * 0 *".parse::<Cron>().is_err());
+        assert!("* * * 13 *".parse::<Cron>().is_err());
+        assert!("* * * * 8".parse::<Cron>().is_err());
+        assert!("@often".parse::<Cron>().is_err());
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        assert_eq!(values(cron("* * * * 0").weekdays, 0, 6), [0]);
+        assert_eq!(values(cron("* * * * 7").weekdays, 0, 6), [0]);
+        assert_eq!(values(cron("* * * * 5-7").weekdays, 0, 6), [0, 5, 6]);
+    }
+
+    #[test]
+    fn shortcuts() {
+        let time = datetime!(2024-05-15 12:34:56 UTC);
+        assert_eq!(next("@hourly", time), datetime!(2024-05-15 13:00 UTC));
+        assert_eq!(next("@daily", time), datetime!(2024-05-16 00:00 UTC));
+        assert_eq!(next("@midnight", time), datetime!(2024-05-16 00:00 UTC));
+        // 2024-05-19 is a sunday
+        assert_eq!(next("@weekly", time), datetime!(2024-05-19 00:00 UTC));
+        assert_eq!(next("@monthly", time), datetime!(2024-06-01 00:00 UTC));
+        assert_eq!(next("@yearly", time), datetime!(2025-01-01 00:00 UTC));
+        assert_eq!(next("@annually", time), datetime!(2025-01-01 00:00 UTC));
+        assert_eq!(cron(" @daily ").to_string(), " @daily ");
+    }
+
+    #[test]
+    fn next_is_strictly_after() {
+        let time = datetime!(2024-05-15 12:00 UTC);
+        assert_eq!(next("* * * * *", time), datetime!(2024-05-15 12:01 UTC));
+        assert_eq!(next("0 12 * * *", time), datetime!(2024-05-16 12:00 UTC));
+
+        let time = datetime!(2024-05-15 12:00:59.999 UTC);
+        assert_eq!(next("* * * * *", time), datetime!(2024-05-15 12:01 UTC));
+    }
+
+    #[test]
+    fn next_within_day() {
+        let time = datetime!(2024-05-15 12:34 UTC);
+        assert_eq!(next("*/15 * * * *", time), datetime!(2024-05-15 12:45 UTC));
+        assert_eq!(next("5 * * * *", time), datetime!(2024-05-15 13:05 UTC));
+        assert_eq!(next("30 9-17 * * *", time), datetime!(2024-05-15 13:30 UTC));
+        assert_eq!(next("0 6,18 * * *", time), datetime!(2024-05-15 18:00 UTC));
+        assert_eq!(next("0 6 * * *", time), datetime!(2024-05-16 06:00 UTC));
+    }
+
+    #[test]
+    fn next_across_month_and_year() {
+        let time = datetime!(2024-01-31 23:59 UTC);
+        assert_eq!(next("* * * * *", time), datetime!(2024-02-01 00:00 UTC));
+
+        let time = datetime!(2024-12-31 23:59 UTC);
+        assert_eq!(next("* * * * *", time), datetime!(2025-01-01 00:00 UTC));
+        assert_eq!(next("0 0 1 * *", time), datetime!(2025-01-01 00:00 UTC));
+
+        // Months without a 31st are skipped.
+        let time = datetime!(2024-01-31 12:00 UTC);
+        assert_eq!(next("0 0 31 * *", time), datetime!(2024-03-31 00:00 UTC));
+
+        // The 29th of february only exists in leap years.
+        let time = datetime!(2024-03-01 00:00 UTC);
+        assert_eq!(next("0 0 29 2 *", time), datetime!(2028-02-29 00:00 UTC));
+
+        let time = datetime!(2024-06-15 00:00 UTC);
+        assert_eq!(next("15 3 * 2 *", time), datetime!(2025-02-01 03:15 UTC));
+    }
+
+    #[test]
+    fn next_never() {
+        let time = datetime!(2024-01-01 00:00 UTC);
+        assert_eq!(cron("0 0 30 2 *").next_after(time), None);
+        assert_eq!(cron("0 0 31 4,6,9,11 *").next_after(time), None);
+    }
+
+    #[test]
+    fn next_day_of_month_or_week() {
+        // 2024-05-15 is a wednesday.
+        let time = datetime!(2024-05-15 12:00 UTC);
+
+        // Only one of them restricted: it alone decides.
+        assert_eq!(next("0 0 20 * *", time), datetime!(2024-05-20 00:00 UTC));
+        assert_eq!(next("0 0 * * 5", time), datetime!(2024-05-17 00:00 UTC));
+        assert_eq!(next("0 0 */2 * 5", time), datetime!(2024-05-17 00:00 UTC));
+
+        // Both restricted: either one is enough.
+        assert_eq!(next("0 0 20 * 5", time), datetime!(2024-05-17 00:00 UTC));
+        assert_eq!(next("0 0 16 * 5", time), datetime!(2024-05-16 00:00 UTC));
+        assert_eq!(
+            next("0 0 1 * 1", datetime!(2024-05-28 12:00 UTC)),
+            datetime!(2024-06-01 00:00 UTC)
+        );
+
+        // The month still has to match.
+        assert_eq!(next("0 0 1 6 1", time), datetime!(2024-06-01 00:00 UTC));
+        assert_eq!(next("0 0 13 6 5", time), datetime!(2024-06-07 00:00 UTC));
+    }
+
+    #[test]
+    fn next_converts_to_utc() {
+        let time = datetime!(2024-05-15 23:30 +02:00);
+        assert_eq!(next("0 22 * * *", time), datetime!(2024-05-15 22:00 UTC));
+        assert_eq!(next("0 22 * * *", time).offset(), UtcOffset::UTC);
+    }
+}
@@ -1,7 +1,9 @@
 // TODO Re-enable and adapt CSS
 
+mod api;
 mod args;
 mod config;
+mod cron;
 mod id;
 mod primitive;
 mod server;
@@ -25,7 +27,7 @@ use tokio::{select, signal::unix::SignalKind};
 use crate::{
     args::{Args, Command, NAME, VERSION},
     config::{Config, WorkerConfig, WorkerServerConfig},
-    server::Server,
+    server::Host,
     worker::Worker,
 };
 
@@ -176,7 +178,7 @@ async fn run() -> somehow::Result<()> {
             let open = command.open;
             let local_worker = command.local_worker;
 
-            let (server, recurring_rx) = Server::new(&config.server, command).await?;
+            let (host, recurring_rxs) = Host::new(&config.server, command).await?;
 
             if open {
                 tokio::task::spawn(open_in_browser(&config.server));
@@ -188,7 +190,7 @@ async fn run() -> somehow::Result<()> {
 
             select! {
                 _ = wait_for_signal() => {}
-                _ = server.run(recurring_rx) => {}
+                _ = host.run(recurring_rxs) => {}
             }
 
             select! {
@@ -202,7 +204,7 @@ async fn run() -> somehow::Result<()> {
                 // In order to fix this, I could maybe register a bare signal handler
                 // (instead of using tokio streams) that just calls process::exit(1) and
                 // nothing else?
-                _ = server.shut_down() => {}
+                _ = host.shut_down() => {}
             }
         }
         Command::Worker => {
@@ -215,6 +217,15 @@ async fn run() -> somehow::Result<()> {
                 _ = worker.run() => {}
             }
         }
+        Command::Import(command) => {
+            server::import::import(&config.server, command).await?;
+        }
+        Command::Export(command) => {
+            server::export::export(command).await?;
+        }
+        Command::Bench(command) => {
+            worker::bench::bench(command).await?;
+        }
     }
 
     Ok(())
@@ -1,9 +1,36 @@
 //! Primitive serializable and deserializable types.
 
-use serde::de;
+use std::str::FromStr;
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{de, Deserialize, Serialize};
+use serde_json::json;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
+/// Schema of an enum that is serialized as its integer discriminant.
+fn repr_schema(description: &str, variants: &[(i64, &str)]) -> Schema {
+    let mut description = format!("{description}\n");
+    for (value, name) in variants {
+        description.push_str(&format!("\n- `{value}`: {name}"));
+    }
+
+    SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(description),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::Integer.into()),
+        enum_values: Some(variants.iter().map(|(value, _)| json!(value)).collect()),
+        ..Default::default()
+    }
+    .into()
+}
+
 /// The source of a line of output.
 #[derive(Debug, Clone, Serialize_repr, Deserialize_repr, sqlx::Type)]
 #[repr(u8)]
@@ -13,8 +40,21 @@ pub enum Source {
     Stderr = 2,
 }
 
+impl JsonSchema for Source {
+    fn schema_name() -> String {
+        "Source".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        repr_schema(
+            "The source of a line of output.",
+            &[(0, "internal"), (1, "stdout"), (2, "stderr")],
+        )
+    }
+}
+
 /// The direction a measured value improves in.
-#[derive(Debug, Clone, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr, sqlx::Type)]
 #[repr(i8)]
 pub enum Direction {
     LessIsBetter = -1,
@@ -22,6 +62,23 @@ pub enum Direction {
     MoreIsBetter = 1,
 }
 
+impl JsonSchema for Direction {
+    fn schema_name() -> String {
+        "Direction".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        repr_schema(
+            "The direction a measured value improves in.",
+            &[
+                (-1, "less is better"),
+                (0, "neutral"),
+                (1, "more is better"),
+            ],
+        )
+    }
+}
+
 /// How a commit can be reached from refs.
 #[derive(Debug, Clone, Serialize_repr, Deserialize_repr, sqlx::Type)]
 #[repr(u8)]
@@ -31,6 +88,82 @@ pub enum Reachable {
     FromTrackedRef = 2,
 }
 
+impl JsonSchema for Reachable {
+    fn schema_name() -> String {
+        "Reachable".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        repr_schema(
+            "How a commit can be reached from refs.",
+            &[
+                (0, "unreachable"),
+                (1, "from any ref"),
+                (2, "from a tracked ref"),
+            ],
+        )
+    }
+}
+
+/// Why a commit is in the queue, or why a run was performed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, sqlx::Type)]
+#[repr(u8)]
+pub enum QueueKind {
+    Normal = 0,
+    Bisect = 1,
+    Scheduled = 2,
+    Report = 3,
+}
+
+impl JsonSchema for QueueKind {
+    fn schema_name() -> String {
+        "QueueKind".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        repr_schema(
+            "Why a commit is in the queue, or why a run was performed.",
+            &[
+                (0, "normal"),
+                (1, "bisect"),
+                (2, "scheduled"),
+                (3, "report"),
+            ],
+        )
+    }
+}
+
+/// Something happening on the server that notifications can be sent about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    RunFinished,
+    RunFailed,
+    Regression,
+    WorkerDisconnected,
+    QueueEmpty,
+}
+
+impl Event {
+    pub const ALL: [Self; 5] = [
+        Self::RunFinished,
+        Self::RunFailed,
+        Self::Regression,
+        Self::WorkerDisconnected,
+        Self::QueueEmpty,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RunFinished => "run_finished",
+            Self::RunFailed => "run_failed",
+            Self::Regression => "regression",
+            Self::WorkerDisconnected => "worker_disconnected",
+            Self::QueueEmpty => "queue_empty",
+        }
+    }
+}
+
 /// A time stamp, usually formatted using RFC3339.
 #[derive(Debug, Clone, Copy, sqlx::Type)]
 #[sqlx(transparent)]
@@ -42,6 +175,14 @@ impl Timestamp {
     }
 }
 
+impl FromStr for Timestamp {
+    type Err = time::error::Parse;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OffsetDateTime::parse(s, &Rfc3339).map(Self)
+    }
+}
+
 impl serde::Serialize for Timestamp {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -56,9 +197,91 @@ impl<'de> serde::Deserialize<'de> for Timestamp {
     where
         D: serde::Deserializer<'de>,
     {
-        let input: &str = serde::Deserialize::deserialize(deserializer)?;
-        OffsetDateTime::parse(input, &Rfc3339)
+        // Not a &str since query strings and the like may need unescaping.
+        let input: String = serde::Deserialize::deserialize(deserializer)?;
+        OffsetDateTime::parse(&input, &Rfc3339)
             .map_err(de::Error::custom)
             .map(Self)
     }
 }
+
+impl JsonSchema for Timestamp {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Timestamp".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some("date-time".to_string()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::{gen::SchemaGenerator, JsonSchema};
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::Value;
+
+    use super::{Direction, QueueKind, Reachable, Source};
+
+    /// The schema must list exactly the discriminants the variants serialize
+    /// to, and every listed discriminant must deserialize again.
+    fn check_repr_schema<T: JsonSchema + Serialize + DeserializeOwned>(variants: &[T]) {
+        let schema = serde_json::to_value(T::json_schema(&mut SchemaGenerator::default())).unwrap();
+        let values = schema["enum"].as_array().expect("schema has enum values");
+
+        let serialized = variants
+            .iter()
+            .map(|v| serde_json::to_value(v).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, &serialized, "{}", T::schema_name());
+
+        let description = schema["description"].as_str().unwrap();
+        for value in values {
+            assert!(serde_json::from_value::<T>(value.clone()).is_ok());
+            assert!(description.contains(&format!("- `{value}`: ")));
+        }
+        assert_eq!(schema["type"], Value::from("integer"));
+    }
+
+    #[test]
+    fn source_schema() {
+        check_repr_schema(&[Source::Internal, Source::Stdout, Source::Stderr]);
+    }
+
+    #[test]
+    fn direction_schema() {
+        check_repr_schema(&[
+            Direction::LessIsBetter,
+            Direction::Neutral,
+            Direction::MoreIsBetter,
+        ]);
+    }
+
+    #[test]
+    fn reachable_schema() {
+        check_repr_schema(&[
+            Reachable::Unreachable,
+            Reachable::FromAnyRef,
+            Reachable::FromTrackedRef,
+        ]);
+    }
+
+    #[test]
+    fn queue_kind_schema() {
+        check_repr_schema(&[
+            QueueKind::Normal,
+            QueueKind::Bisect,
+            QueueKind::Scheduled,
+            QueueKind::Report,
+        ]);
+    }
+}
@@ -1,6 +1,16 @@
+mod changes;
+mod delivery;
+pub mod export;
 mod format;
 mod git;
+pub mod import;
+mod notify;
 mod recurring;
+mod refs;
+mod report;
+mod status;
+#[cfg(test)]
+mod testing;
 pub mod web;
 mod workers;
 
@@ -12,47 +22,59 @@ use std::{
 
 use anyhow::anyhow;
 use axum::extract::FromRef;
+use futures::future;
 use gix::ThreadSafeRepository;
 use log::{debug, info};
 use sqlx::{
     sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
     SqlitePool,
 };
-use tokio::{select, sync::mpsc};
+use tokio::{
+    select,
+    sync::{mpsc, Mutex as AsyncMutex},
+};
 
-use crate::{args::ServerCommand, config::ServerConfig, somehow};
+use crate::{
+    args::ServerCommand,
+    config::{RepoFetcher, ServerConfig, ServerRemote},
+    somehow,
+};
 
 use self::workers::Workers;
 
 fn open_repo(
     path: &Path,
-    url: &Option<String>,
-    refspecs: &[String],
+    fetcher: RepoFetcher,
+    remotes: &[ServerRemote],
 ) -> somehow::Result<ThreadSafeRepository> {
     if path.exists() {
         info!("Opening repo at {}", path.display());
         Ok(ThreadSafeRepository::open(path)?)
-    } else if let Some(url) = url {
+    } else if let Some(first) = remotes.first() {
         info!(
-            "No repo found at {} but a fetch url is configured",
+            "No repo found at {} but a remote is configured",
             path.display()
         );
 
         info!("Creating bare repo");
-        git::init_bare(path)?;
+        git::init_bare(fetcher, path)?;
 
-        info!("Fetching HEAD from {url}");
-        git::fetch_head(path, url)?;
+        info!("Fetching HEAD from {}", first.url);
+        git::fetch_head(fetcher, path, &first.url, &first.refspecs)?;
 
-        info!("Fetching refs for the first time (this may take a while)");
-        let output = git::fetch(path, url, refspecs)?;
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        info!("Fetched refs:\n{}", stderr.trim_end());
+        for remote in remotes {
+            info!(
+                "Fetching refs from {} for the first time (this may take a while)",
+                remote.name
+            );
+            let summary = git::fetch(fetcher, path, &remote.url, &remote.refspecs)?;
+            info!("Fetched refs:\n{}", summary.trim_end());
+        }
 
         Ok(ThreadSafeRepository::open(path)?)
     } else {
         Err(somehow::Error(anyhow!(
-            "Failed to open repo: No repo found at {} and no fetch url is configured",
+            "Failed to open repo: No repo found at {} and no remote is configured",
             path.display()
         )))
     }
@@ -110,6 +132,16 @@ pub struct Repo(Arc<ThreadSafeRepository>);
 #[derive(Clone)]
 pub struct BenchRepo(Arc<ThreadSafeRepository>);
 
+/// Held while delivering a project's statuses or notifications.
+///
+/// Deliveries are started both by the recurring updates and whenever a worker
+/// reports in. They shouldn't step on each other's toes.
+#[derive(Clone, Default)]
+pub struct Deliveries {
+    status: Arc<AsyncMutex<()>>,
+    notify: Arc<AsyncMutex<()>>,
+}
+
 #[derive(Clone, FromRef)]
 pub struct Server {
     config: &'static ServerConfig,
@@ -118,21 +150,24 @@ pub struct Server {
     bench_repo: Option<BenchRepo>,
     workers: Arc<Mutex<Workers>>,
     recurring_tx: Arc<mpsc::UnboundedSender<()>>,
+    deliveries: Deliveries,
 }
 
 impl Server {
-    pub async fn new(
+    async fn new(
         config: &'static ServerConfig,
-        command: ServerCommand,
+        db: &Path,
+        repo: Option<&Path>,
+        bench_repo: Option<&Path>,
     ) -> somehow::Result<(Self, mpsc::UnboundedReceiver<()>)> {
-        let repo = if let Some(path) = command.repo.as_ref() {
-            let repo = open_repo(path, &config.repo_fetch_url, &config.repo_fetch_refspecs)?;
+        let repo = if let Some(path) = repo {
+            let repo = open_repo(path, config.repo_fetcher, &config.repo_remotes)?;
             Some(Repo(Arc::new(repo)))
         } else {
             None
         };
 
-        let bench_repo = if let Some(path) = command.bench_repo.as_ref() {
+        let bench_repo = if let Some(path) = bench_repo {
             let repo = open_bench_repo(path)?;
             Some(BenchRepo(Arc::new(repo)))
         } else {
@@ -142,29 +177,105 @@ impl Server {
         let (recurring_tx, recurring_rx) = mpsc::unbounded_channel();
         let server = Self {
             config,
-            db: open_db(&command.db).await?,
+            db: open_db(db).await?,
             repo,
             bench_repo,
             workers: Arc::new(Mutex::new(Workers::new(config))),
             recurring_tx: Arc::new(recurring_tx),
+            deliveries: Deliveries::default(),
         };
 
         Ok((server, recurring_rx))
     }
 
-    pub async fn run(&self, recurring_rx: mpsc::UnboundedReceiver<()>) -> somehow::Result<()> {
+    async fn run_recurring(&self, recurring_rx: mpsc::UnboundedReceiver<()>) {
         if let Some(repo) = self.repo.clone() {
-            select! {
-                e = web::run(self.clone()) => e,
-                () = recurring::run(self.clone(), repo, recurring_rx) => Ok(()),
-            }
-        } else {
-            web::run(self.clone()).await
+            recurring::run(self.clone(), repo, recurring_rx).await;
         }
     }
 
-    pub async fn shut_down(self) {
+    async fn shut_down(self) {
         info!("Closing db");
         self.db.close().await;
     }
 }
+
+/// All projects served by a tablejohn instance.
+pub struct Host {
+    config: &'static ServerConfig,
+    /// One server per project, in the same order as the projects in the config.
+    /// If no projects are configured, this contains a single server for the
+    /// paths given on the command line.
+    servers: Vec<Server>,
+}
+
+impl Host {
+    pub async fn new(
+        config: &'static ServerConfig,
+        command: ServerCommand,
+    ) -> somehow::Result<(Self, Vec<mpsc::UnboundedReceiver<()>>)> {
+        let mut servers = vec![];
+        let mut recurring_rxs = vec![];
+
+        if config.projects.is_empty() {
+            let Some(db) = &command.db else {
+                return Err(somehow::Error(anyhow!(
+                    "No db given and no projects are configured"
+                )));
+            };
+            let (server, recurring_rx) = Server::new(
+                config,
+                db,
+                command.repo.as_deref(),
+                command.bench_repo.as_deref(),
+            )
+            .await?;
+            servers.push(server);
+            recurring_rxs.push(recurring_rx);
+        } else {
+            if command.db.is_some() || command.repo.is_some() || command.bench_repo.is_some() {
+                return Err(somehow::Error(anyhow!(
+                    "Projects are configured, so no db or repos may be given"
+                )));
+            }
+            for project in &config.projects {
+                info!("Setting up project {}", project.name);
+                let (server, recurring_rx) = Server::new(
+                    &project.config,
+                    &project.db,
+                    project.repo.as_deref(),
+                    project.bench_repo.as_deref(),
+                )
+                .await?;
+                servers.push(server);
+                recurring_rxs.push(recurring_rx);
+            }
+        }
+
+        Ok((Self { config, servers }, recurring_rxs))
+    }
+
+    pub async fn run(
+        &self,
+        recurring_rxs: Vec<mpsc::UnboundedReceiver<()>>,
+    ) -> somehow::Result<()> {
+        let recurring = self
+            .servers
+            .iter()
+            .zip(recurring_rxs)
+            .map(|(server, recurring_rx)| server.run_recurring(recurring_rx));
+
+        select! {
+            e = web::run(self.config, &self.servers) => e,
+            () = async {
+                future::join_all(recurring).await;
+                // Servers without a repo have nothing to update
+                future::pending().await
+            } => Ok(()),
+        }
+    }
+
+    pub async fn shut_down(self) {
+        future::join_all(self.servers.into_iter().map(Server::shut_down)).await;
+    }
+}
@@ -0,0 +1,156 @@
+//! Find out how the metrics of a run changed compared to earlier commits.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use crate::{primitive::Direction, somehow};
+
+/// Metrics that changed by more than a threshold, sorted by name.
+#[derive(Serialize)]
+pub struct Changes {
+    /// Metrics that got worse.
+    pub regressions: Vec<String>,
+    /// Metrics that got better.
+    pub improvements: Vec<String>,
+    /// Metrics without a direction that changed either way.
+    pub changes: Vec<String>,
+}
+
+fn plural(n: usize, what: &str) -> String {
+    match n {
+        1 => format!("1 {what}"),
+        n => format!("{n} {what}s"),
+    }
+}
+
+impl Changes {
+    /// A few words like `2 regressions, 1 change`.
+    pub fn describe(&self) -> String {
+        let parts = [
+            (self.regressions.len(), "regression"),
+            (self.improvements.len(), "improvement"),
+            (self.changes.len(), "change"),
+        ]
+        .into_iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, what)| plural(n, what))
+        .collect::<Vec<_>>();
+
+        if parts.is_empty() {
+            "no significant changes".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Average value of each metric across the successful runs of the nearest
+/// measured first-parent ancestor of a commit.
+async fn baseline_values(
+    conn: &mut SqliteConnection,
+    hash: &str,
+) -> somehow::Result<Option<HashMap<String, f64>>> {
+    let Some(baseline) = sqlx::query_scalar!(
+        r#"
+        WITH RECURSIVE chain (hash, n) AS (
+            SELECT parent, 1 FROM commit_edges
+            WHERE child = ? AND position = 0
+            UNION ALL
+            SELECT parent, n + 1
+            FROM commit_edges
+            JOIN chain ON child = hash
+            WHERE position = 0
+        )
+        SELECT hash AS "hash!: String" FROM chain
+        WHERE EXISTS (
+            SELECT * FROM runs
+            WHERE runs.hash = chain.hash AND exit_code = 0
+        )
+        ORDER BY n ASC
+        LIMIT 1
+        "#,
+        hash,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let values = sqlx::query!(
+        r#"
+        SELECT metric, AVG(value) AS "value!: f64"
+        FROM run_measurements
+        JOIN runs USING (id)
+        WHERE hash = ? AND exit_code = 0
+        GROUP BY metric
+        "#,
+        baseline,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|r| (r.metric, r.value))
+    .collect();
+
+    Ok(Some(values))
+}
+
+/// Compare a run of a commit against the nearest measured first-parent
+/// ancestor of that commit, or `None` if there is no such ancestor.
+pub async fn since_baseline(
+    conn: &mut SqliteConnection,
+    hash: &str,
+    run_id: &str,
+    threshold: f64,
+) -> somehow::Result<Option<Changes>> {
+    let Some(old) = baseline_values(conn, hash).await? else {
+        return Ok(None);
+    };
+
+    let new = sqlx::query!(
+        r#"
+        SELECT metric, value, direction AS "direction: Direction"
+        FROM run_measurements
+        JOIN metrics ON name = metric
+        WHERE id = ?
+        ORDER BY metric ASC
+        "#,
+        run_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut changes = Changes {
+        regressions: vec![],
+        improvements: vec![],
+        changes: vec![],
+    };
+    for row in new {
+        let Some(&old) = old.get(&row.metric) else {
+            continue;
+        };
+        let significant = if old == 0.0 {
+            row.value != 0.0
+        } else {
+            ((row.value - old) / old).abs() > threshold
+        };
+        if !significant {
+            continue;
+        }
+        let list = match (row.direction, row.value > old) {
+            (Direction::Neutral, _) => &mut changes.changes,
+            (Direction::LessIsBetter, true) | (Direction::MoreIsBetter, false) => {
+                &mut changes.regressions
+            }
+            (Direction::LessIsBetter, false) | (Direction::MoreIsBetter, true) => {
+                &mut changes.improvements
+            }
+        };
+        list.push(row.metric);
+    }
+
+    Ok(Some(changes))
+}
@@ -0,0 +1,124 @@
+//! Deliver statuses and notifications to external targets.
+//!
+//! Pending deliveries are stored in the db until they succeed or run out of
+//! retries, so failed ones can be retried with increasing delays, even across
+//! restarts. Each kind of delivery has its own table and way of being sent,
+//! described by a [`Queue`].
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use reqwest::{Client, Response};
+use time::OffsetDateTime;
+
+use crate::somehow;
+
+/// A delivery that is due.
+pub struct Pending<T> {
+    pub target: String,
+    pub attempts: i64,
+    pub item: T,
+}
+
+/// The db table of one kind of delivery and how to send its entries.
+pub trait Queue {
+    type Item;
+    type Target;
+
+    /// Deliveries whose next attempt is due at `now`, oldest first.
+    async fn due(&self, now: OffsetDateTime) -> somehow::Result<Vec<Pending<Self::Item>>>;
+
+    /// The configured target with this name, if it still exists.
+    fn target(&self, name: &str) -> Option<&Self::Target>;
+
+    /// How often delivering to a target is retried before giving up.
+    fn retries(target: &Self::Target) -> u32;
+
+    /// Describe an item in log messages, e.g. `status for run r-1234`.
+    fn describe(item: &Self::Item) -> String;
+
+    async fn send(
+        &self,
+        target: &Self::Target,
+        client: &Client,
+        item: &Self::Item,
+    ) -> somehow::Result<()>;
+
+    async fn retry_later(
+        &self,
+        pending: &Pending<Self::Item>,
+        attempts: u32,
+        next_attempt: OffsetDateTime,
+    ) -> somehow::Result<()>;
+
+    async fn remove(&self, pending: &Pending<Self::Item>) -> somehow::Result<()>;
+}
+
+/// Wait a bit longer after every failed attempt, but not more than an hour.
+pub fn backoff(attempts: u32) -> Duration {
+    let secs = 30_u64.saturating_mul(1 << attempts.min(16));
+    Duration::from_secs(secs.min(60 * 60))
+}
+
+/// Turn unsuccessful http responses into errors containing the start of the
+/// response body.
+pub async fn check_response(response: Response) -> somehow::Result<()> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let text = response.text().await.unwrap_or_default();
+    let text = text.chars().take(500).collect::<String>();
+    Err(somehow::Error(anyhow!(
+        "Server responded with {status}: {text}"
+    )))
+}
+
+/// Attempt every delivery that is due once.
+pub async fn process<Q: Queue>(queue: &Q) -> somehow::Result<()> {
+    let due = queue.due(OffsetDateTime::now_utc()).await?;
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+
+    for pending in due {
+        let what = Q::describe(&pending.item);
+        let Some(target) = queue.target(&pending.target) else {
+            debug!("Dropping {what} to removed target {}", pending.target);
+            queue.remove(&pending).await?;
+            continue;
+        };
+
+        let attempts = pending.attempts as u32 + 1;
+        match queue.send(target, &client, &pending.item).await {
+            Ok(()) => {
+                info!("Delivered {what} to {}", pending.target);
+            }
+            Err(e) if attempts > Q::retries(target) => {
+                warn!(
+                    "Failed to deliver {what} to {}, giving up after {attempts} attempts:\n{e:?}",
+                    pending.target
+                );
+            }
+            Err(e) => {
+                let delay = backoff(attempts - 1);
+                warn!(
+                    "Failed to deliver {what} to {} (attempt {attempts}), retrying in {}:\n{e:?}",
+                    pending.target,
+                    humantime::format_duration(delay)
+                );
+                let next_attempt = OffsetDateTime::now_utc() + delay;
+                queue.retry_later(&pending, attempts, next_attempt).await?;
+                continue;
+            }
+        }
+
+        queue.remove(&pending).await?;
+    }
+
+    Ok(())
+}
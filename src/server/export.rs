@@ -0,0 +1,485 @@
+//! Export measurements as a long-format table for analysis in other tools.
+
+use std::{mem, pin::pin, sync::Arc};
+
+use anyhow::anyhow;
+use futures::{Stream, TryStreamExt};
+use log::{info, warn};
+use parquet::{
+    data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tokio::{
+    fs::File,
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use crate::{api::ExportRow, args::ExportCommand, primitive::Timestamp, somehow};
+
+use super::{open_db, refs};
+
+const PARQUET_SCHEMA: &str = "
+message export {
+    required binary hash (STRING);
+    required int64 committer_date (TIMESTAMP(MICROS, true));
+    required binary run_id (STRING);
+    required binary worker_name (STRING);
+    required binary metric (STRING);
+    required double value;
+    optional binary unit (STRING);
+}
+";
+
+const PARQUET_ROW_GROUP_SIZE: usize = 100_000;
+
+/// Field names of [`ExportRow`], for csv exports without rows.
+const CSV_HEADER: [&str; 7] = [
+    "hash",
+    "committer_date",
+    "run_id",
+    "worker_name",
+    "metric",
+    "value",
+    "unit",
+];
+
+/// Rows per chunk of csv or jsonl output.
+const CHUNK_ROWS: usize = 1000;
+
+/// Chunks that may be written ahead of whoever is receiving them.
+const CHANNEL_CAPACITY: usize = 4;
+
+type Chunks = mpsc::Sender<somehow::Result<Vec<u8>>>;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One json object per line.
+    Jsonl,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Jsonl => "application/jsonl; charset=utf-8",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// Which measurements to export.
+pub struct ExportFilter {
+    /// Prefixes of the metrics to export. If empty, all metrics are exported.
+    pub metrics: Vec<String>,
+    /// Only export commits reachable from this commit.
+    pub reachable_from: Option<String>,
+    /// Only export commits committed at or after this time.
+    pub since: Option<Timestamp>,
+    /// Only export commits committed at or before this time.
+    pub until: Option<Timestamp>,
+}
+
+/// Measurements of successful runs, oldest commit first.
+fn rows<'a>(
+    db: &'a SqlitePool,
+    filter: &'a ExportFilter,
+) -> impl Stream<Item = somehow::Result<ExportRow>> + 'a {
+    sqlx::query!(
+        r#"
+        WITH RECURSIVE reachable (hash) AS (
+            SELECT ?1 WHERE ?1 IS NOT NULL
+            UNION
+            SELECT parent FROM commit_edges
+            JOIN reachable ON child = hash
+        )
+        SELECT
+            commits.hash,
+            committer_date AS "committer_date: Timestamp",
+            runs.id AS run_id,
+            worker_name,
+            metric,
+            value,
+            run_measurements.unit
+        FROM run_measurements
+        JOIN runs ON runs.id = run_measurements.id
+        JOIN commits ON commits.hash = runs.hash
+        WHERE exit_code = 0
+        AND (?1 IS NULL OR commits.hash IN (SELECT hash FROM reachable))
+        AND (?2 IS NULL OR unixepoch(committer_date) >= unixepoch(?2))
+        AND (?3 IS NULL OR unixepoch(committer_date) <= unixepoch(?3))
+        ORDER BY
+            unixepoch(committer_date) ASC,
+            commits.hash ASC,
+            unixepoch(runs.start) ASC,
+            runs.id ASC,
+            metric ASC
+        "#,
+        filter.reachable_from,
+        filter.since,
+        filter.until,
+    )
+    .fetch(db)
+    .try_filter(|r| {
+        let wanted = filter.metrics.is_empty()
+            || filter
+                .metrics
+                .iter()
+                .any(|prefix| r.metric.starts_with(prefix));
+        async move { wanted }
+    })
+    .map_ok(|r| ExportRow {
+        hash: r.hash,
+        committer_date: r.committer_date,
+        run_id: r.run_id,
+        worker_name: r.worker_name,
+        metric: r.metric,
+        value: r.value,
+        unit: r.unit,
+    })
+    .map_err(somehow::Error::from)
+}
+
+fn csv_chunk(rows: &[ExportRow], headers: bool) -> somehow::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(headers)
+        .from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+fn jsonl_chunk(rows: &[ExportRow]) -> somehow::Result<Vec<u8>> {
+    let mut result = vec![];
+    for row in rows {
+        serde_json::to_writer(&mut result, row)?;
+        result.push(b'\n');
+    }
+    Ok(result)
+}
+
+fn strings(rows: &[ExportRow], f: impl Fn(&ExportRow) -> &str) -> Vec<ByteArray> {
+    rows.iter().map(|r| ByteArray::from(f(r))).collect()
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<Vec<u8>>,
+    rows: &[ExportRow],
+) -> somehow::Result<()> {
+    let mut group = writer.next_row_group()?;
+
+    let mut column = group.next_column()?.expect("column hash");
+    let values = strings(rows, |r| &r.hash);
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, None, None)?;
+    column.close()?;
+
+    let mut column = group.next_column()?.expect("column committer_date");
+    let values = rows
+        .iter()
+        .map(|r| (r.committer_date.0.unix_timestamp_nanos() / 1000) as i64)
+        .collect::<Vec<_>>();
+    column
+        .typed::<Int64Type>()
+        .write_batch(&values, None, None)?;
+    column.close()?;
+
+    let mut column = group.next_column()?.expect("column run_id");
+    let values = strings(rows, |r| &r.run_id);
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, None, None)?;
+    column.close()?;
+
+    let mut column = group.next_column()?.expect("column worker_name");
+    let values = strings(rows, |r| &r.worker_name);
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, None, None)?;
+    column.close()?;
+
+    let mut column = group.next_column()?.expect("column metric");
+    let values = strings(rows, |r| &r.metric);
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, None, None)?;
+    column.close()?;
+
+    let mut column = group.next_column()?.expect("column value");
+    let values = rows.iter().map(|r| r.value).collect::<Vec<_>>();
+    column
+        .typed::<DoubleType>()
+        .write_batch(&values, None, None)?;
+    column.close()?;
+
+    // Missing units are only recorded in the definition levels.
+    let mut column = group.next_column()?.expect("column unit");
+    let values = rows
+        .iter()
+        .filter_map(|r| r.unit.as_deref().map(ByteArray::from))
+        .collect::<Vec<_>>();
+    let levels = rows
+        .iter()
+        .map(|r| r.unit.is_some() as i16)
+        .collect::<Vec<_>>();
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, Some(&levels), None)?;
+    column.close()?;
+
+    group.close()?;
+    Ok(())
+}
+
+/// Send a chunk of output, returning `false` if nobody is listening any more.
+async fn send(tx: &Chunks, chunk: Vec<u8>) -> bool {
+    tx.send(Ok(chunk)).await.is_ok()
+}
+
+async fn write_csv(
+    rows: impl Stream<Item = somehow::Result<ExportRow>>,
+    tx: &Chunks,
+) -> somehow::Result<()> {
+    let mut chunks = pin!(rows.try_chunks(CHUNK_ROWS));
+    let mut headers = true;
+    while let Some(chunk) = chunks.try_next().await.map_err(|e| e.1)? {
+        if !send(tx, csv_chunk(&chunk, headers)?).await {
+            return Ok(());
+        }
+        headers = false;
+    }
+
+    // The header is usually taken from the first row, but even an export
+    // without rows should have one.
+    if headers {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(CSV_HEADER)?;
+        send(tx, writer.into_inner().map_err(|e| e.into_error())?).await;
+    }
+
+    Ok(())
+}
+
+async fn write_jsonl(
+    rows: impl Stream<Item = somehow::Result<ExportRow>>,
+    tx: &Chunks,
+) -> somehow::Result<()> {
+    let mut chunks = pin!(rows.try_chunks(CHUNK_ROWS));
+    while let Some(chunk) = chunks.try_next().await.map_err(|e| e.1)? {
+        if !send(tx, jsonl_chunk(&chunk)?).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn write_parquet(
+    rows: impl Stream<Item = somehow::Result<ExportRow>>,
+    tx: &Chunks,
+) -> somehow::Result<()> {
+    let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(vec![], schema, properties)?;
+
+    let mut groups = pin!(rows.try_chunks(PARQUET_ROW_GROUP_SIZE));
+    while let Some(group) = groups.try_next().await.map_err(|e| e.1)? {
+        write_row_group(&mut writer, &group)?;
+        // The writer keeps track of the file offsets itself, so finished row
+        // groups can be sent off right away.
+        if !send(tx, mem::take(writer.inner_mut())).await {
+            return Ok(());
+        }
+    }
+
+    send(tx, writer.into_inner()?).await;
+    Ok(())
+}
+
+async fn write(
+    db: &SqlitePool,
+    filter: &ExportFilter,
+    format: ExportFormat,
+    tx: &Chunks,
+) -> somehow::Result<()> {
+    let rows = rows(db, filter);
+    match format {
+        ExportFormat::Csv => write_csv(rows, tx).await,
+        ExportFormat::Jsonl => write_jsonl(rows, tx).await,
+        ExportFormat::Parquet => write_parquet(rows, tx).await,
+    }
+}
+
+/// Export measurements in chunks as they are read from the db, so large
+/// exports never have to fit into memory as a whole.
+///
+/// An error ends the export and is sent as the last chunk.
+pub fn stream(
+    db: SqlitePool,
+    filter: ExportFilter,
+    format: ExportFormat,
+) -> mpsc::Receiver<somehow::Result<Vec<u8>>> {
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        if let Err(e) = write(&db, &filter, format, &tx).await {
+            warn!("Error exporting measurements:\n{e:?}");
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+    rx
+}
+
+pub async fn export(command: ExportCommand) -> somehow::Result<()> {
+    let db = open_db(&command.db).await?;
+
+    let reachable_from = match command.r#ref {
+        None => None,
+        Some(name) => match refs::resolve(&db, &name).await? {
+            Some(hash) => Some(hash),
+            None => return Err(somehow::Error(anyhow!("Unknown ref or commit {name}"))),
+        },
+    };
+
+    let filter = ExportFilter {
+        metrics: command.metric,
+        reachable_from,
+        since: command.since,
+        until: command.until,
+    };
+    let mut output: Box<dyn AsyncWrite + Unpin> = match &command.output {
+        Some(path) => Box::new(File::create(path).await?),
+        None => Box::new(tokio::io::stdout()),
+    };
+    let mut chunks = stream(db.clone(), filter, command.format);
+    while let Some(chunk) = chunks.recv().await {
+        output.write_all(&chunk?).await?;
+    }
+    output.flush().await?;
+    db.close().await;
+
+    if let Some(path) = command.output {
+        info!("Exported measurements to {}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bytes::Bytes;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use crate::server::testing::{db_with_run, RUN_ID};
+
+    use super::*;
+
+    /// More than fit into a single chunk of csv or jsonl output.
+    const ROWS: usize = CHUNK_ROWS * 2 + 1;
+
+    async fn db(dir: &Path) -> SqlitePool {
+        let db = db_with_run(dir, 0).await;
+
+        for i in 0..ROWS {
+            let metric = format!("metric/{i:05}");
+            let unit = (i % 2 == 0).then_some("s");
+            sqlx::query("INSERT INTO metrics (name) VALUES (?)")
+                .bind(&metric)
+                .execute(&db)
+                .await
+                .unwrap();
+            sqlx::query(
+                "INSERT INTO run_measurements (id, metric, value, unit) VALUES (?, ?, ?, ?)",
+            )
+            .bind(RUN_ID)
+            .bind(&metric)
+            .bind(i as f64)
+            .bind(unit)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        db
+    }
+
+    async fn export(db: &SqlitePool, format: ExportFormat, metrics: &[&str]) -> Vec<u8> {
+        let filter = ExportFilter {
+            metrics: metrics.iter().map(|m| m.to_string()).collect(),
+            reachable_from: None,
+            since: None,
+            until: None,
+        };
+        let mut chunks = stream(db.clone(), filter, format);
+        let mut result = vec![];
+        while let Some(chunk) = chunks.recv().await {
+            result.extend(chunk.unwrap());
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn csv_has_one_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = db(dir.path()).await;
+
+        let csv = String::from_utf8(export(&db, ExportFormat::Csv, &[]).await).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), ROWS + 1);
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(lines.iter().filter(|l| l.starts_with("hash,")).count(), 1);
+        assert!(lines[1].ends_with(",metric/00000,0.0,s"));
+        assert!(lines[ROWS].ends_with(&format!(",metric/{:05},{}.0,s", ROWS - 1, ROWS - 1)));
+    }
+
+    #[tokio::test]
+    async fn empty_csv_has_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = db(dir.path()).await;
+
+        let csv = export(&db, ExportFormat::Csv, &["does-not-exist"]).await;
+        assert_eq!(String::from_utf8(csv).unwrap(), CSV_HEADER.join(",") + "\n");
+    }
+
+    #[tokio::test]
+    async fn jsonl_has_one_line_per_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = db(dir.path()).await;
+
+        let jsonl = String::from_utf8(export(&db, ExportFormat::Jsonl, &[]).await).unwrap();
+        let rows = jsonl
+            .lines()
+            .map(|l| serde_json::from_str::<ExportRow>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), ROWS);
+        assert_eq!(rows[1].metric, "metric/00001");
+        assert_eq!(rows[1].unit, None);
+    }
+
+    #[tokio::test]
+    async fn parquet_is_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = db(dir.path()).await;
+
+        let parquet = export(&db, ExportFormat::Parquet, &[]).await;
+        let reader = SerializedFileReader::new(Bytes::from(parquet)).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), ROWS as i64);
+    }
+}
@@ -62,6 +62,11 @@ pub fn commit_short(hash: &str, message: &str) -> String {
     format!("{short_hash} ({summary})")
 }
 
+/// The name of a tag without its `refs/tags/` prefix.
+pub fn tag_name(name: &str) -> &str {
+    name.strip_prefix("refs/tags/").unwrap_or(name)
+}
+
 pub fn measurement_value(value: f64) -> String {
     if value.abs() >= 1e6 {
         format!("{value:.3e}")
@@ -72,6 +77,21 @@ pub fn measurement_value(value: f64) -> String {
     }
 }
 
+pub fn size(bytes: i64) -> String {
+    let mut value = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if value.abs() < 1024.0 {
+            return if unit == "B" {
+                format!("{value:.0} {unit}")
+            } else {
+                format!("{value:.1} {unit}")
+            };
+        }
+        value /= 1024.0;
+    }
+    format!("{value:.1} TiB")
+}
+
 pub fn truncate(text: &str, width: usize) -> String {
     let truncate = text.chars().take(width + 1).count() > width;
     if truncate {
@@ -83,3 +103,8 @@ pub fn truncate(text: &str, width: usize) -> String {
         text.to_string()
     }
 }
+
+pub fn worker_version(version: Option<&str>, protocol: u32) -> String {
+    let version = version.unwrap_or("unknown");
+    format!("{version}, protocol {protocol}")
+}
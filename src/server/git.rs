@@ -1,122 +1,153 @@
-//! Execute git binary commands.
+//! Fetch refs from remotes into the repo.
+//!
+//! Fetching uses the git binary by default. Fetching natively via gix, which
+//! needs no git installation, is opt-in.
+
+mod binary;
+mod native;
 
 use std::{
-    error, fmt, io,
+    error, fmt,
+    io::{self, ErrorKind},
     path::Path,
-    process::{Command, Output},
+    process::ExitStatus,
+    time::Duration,
 };
 
-use log::{trace, warn};
-use regex::bytes::RegexBuilder;
+use crate::config::RepoFetcher;
+
+/// How often to log the progress of long-running fetches.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub enum Error {
+    /// The git binary could not be found.
+    NotInstalled,
     Io(io::Error),
-    Command(Command, Output),
+    /// The command ran, but exited unsuccessfully.
+    Failed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// Fetching natively failed.
+    Gix(Box<dyn error::Error + Send + Sync>),
+}
+
+impl Error {
+    fn gix(error: impl error::Error + Send + Sync + 'static) -> Self {
+        Self::Gix(Box::new(error))
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Error::NotInstalled => write!(f, "git binary not found, is git installed?"),
             Error::Io(e) => e.fmt(f),
-            Error::Command(command, output) => {
-                write!(f, "Command exited with {}", output.status)?;
-                write!(f, "COMMAND: {command:?}")?;
-                if !output.stdout.is_empty() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    write!(f, "STDOUT:\n{}", stdout.trim_end())?;
-                }
-                if !output.stderr.is_empty() {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    write!(f, "STDERR:\n{}", stderr.trim_end())?;
+            Error::Failed {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(f, "{command} exited with {status}")?;
+                if !stderr.is_empty() {
+                    write!(f, "\nSTDERR:\n{}", stderr.trim_end())?;
                 }
                 Ok(())
             }
+            Error::Gix(e) => e.fmt(f),
         }
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        // The error itself is already part of the message.
+        match self {
+            Error::Gix(e) => e.source(),
+            _ => None,
+        }
+    }
+}
 
 impl From<io::Error> for Error {
     fn from(value: io::Error) -> Self {
-        Self::Io(value)
+        if value.kind() == ErrorKind::NotFound {
+            Self::NotInstalled
+        } else {
+            Self::Io(value)
+        }
     }
 }
 
-fn run(mut command: Command) -> Result<Output, Error> {
-    let output = command.output()?;
-    if output.status.success() {
-        trace!("Command exited with {}", output.status);
-        trace!("COMMAND: {command:?}");
-        if !output.stdout.is_empty() {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            trace!("STDOUT:\n{}", stdout.trim_end());
-        }
-        if !output.stderr.is_empty() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            trace!("STDERR:\n{}", stderr.trim_end());
-        }
-        Ok(output)
-    } else {
-        Err(Error::Command(command, output))
+pub fn init_bare(fetcher: RepoFetcher, path: &Path) -> Result<(), Error> {
+    match fetcher {
+        RepoFetcher::Gix => native::init_bare(path),
+        RepoFetcher::Git => binary::init_bare(path),
     }
 }
 
-pub fn init_bare(path: &Path) -> Result<(), Error> {
-    let mut command = Command::new("git");
-    command.arg("init").arg("--bare").arg("--").arg(path);
-    run(command)?;
-    Ok(())
+/// Point HEAD at the local counterpart of the remote's HEAD.
+pub fn fetch_head(
+    fetcher: RepoFetcher,
+    path: &Path,
+    url: &str,
+    refspecs: &[String],
+) -> Result<(), Error> {
+    match fetcher {
+        RepoFetcher::Gix => native::fetch_head(path, url, refspecs),
+        RepoFetcher::Git => binary::fetch_head(path, url, refspecs),
+    }
 }
 
-pub fn fetch_head(path: &Path, url: &str) -> Result<(), Error> {
-    let mut command = Command::new("git");
-    command
-        .arg("-C")
-        .arg(path)
-        .arg("ls-remote")
-        .arg("--symref")
-        .arg("--")
-        .arg(url)
-        .arg("HEAD"); // Includes other refs like refs/foo/HEAD
-    let output = run(command)?;
-
-    let regex = RegexBuilder::new(r"^ref: (refs/\S+)\s+HEAD$")
-        .multi_line(true)
-        .build()
-        .unwrap();
-    let Some(captures) = regex.captures(&output.stdout) else {
-        warn!("Did not find HEAD of {url}");
-        return Ok(());
-    };
-    let head = String::from_utf8_lossy(captures.get(1).unwrap().as_bytes());
-
-    let mut command = Command::new("git");
-    command
-        .arg("-C")
-        .arg(path)
-        .arg("symbolic-ref")
-        .arg("--")
-        .arg("HEAD")
-        .arg(&head as &str);
-    run(command)?;
-
-    Ok(())
+/// Fetch refs from a remote and prune local refs the remote no longer has,
+/// logging progress along the way.
+///
+/// Returns a summary of the updated refs.
+pub fn fetch(
+    fetcher: RepoFetcher,
+    path: &Path,
+    url: &str,
+    refspecs: &[String],
+) -> Result<String, Error> {
+    match fetcher {
+        RepoFetcher::Gix => native::fetch(path, url, refspecs),
+        RepoFetcher::Git => binary::fetch(path, url, refspecs),
+    }
 }
 
-pub fn fetch(path: &Path, url: &str, refspecs: &[String]) -> Result<Output, Error> {
-    let mut command = Command::new("git");
-    command
-        .arg("-C")
-        .arg(path)
-        .arg("fetch")
-        .arg("--prune")
-        .arg("--no-tags")
-        .arg("--")
-        .arg(url);
-    for refspec in refspecs {
-        command.arg(refspec);
+/// Split a refspec into its source and destination, ignoring negative refspecs
+/// and refspecs without destination.
+fn parse_refspec(refspec: &str) -> Option<(&str, &str)> {
+    if refspec.starts_with('^') {
+        return None;
     }
-    run(command)
+    let refspec = refspec.strip_prefix('+').unwrap_or(refspec);
+    refspec.split_once(':')
+}
+
+/// Match a ref name against one side of a refspec, returning the part matched
+/// by the `*`, if any.
+fn match_refspec_pattern<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => name.strip_prefix(prefix)?.strip_suffix(suffix),
+        None => (pattern == name).then_some(""),
+    }
+}
+
+/// The local name a remote ref is fetched into, if any.
+pub fn map_ref(refspecs: &[String], name: &str) -> Option<String> {
+    refspecs.iter().find_map(|refspec| {
+        let (src, dst) = parse_refspec(refspec)?;
+        let matched = match_refspec_pattern(src, name)?;
+        Some(dst.replacen('*', matched, 1))
+    })
+}
+
+/// Whether a local ref is the destination of any of the refspecs.
+pub fn is_mapped_ref(refspecs: &[String], name: &str) -> bool {
+    refspecs.iter().any(|refspec| {
+        parse_refspec(refspec).is_some_and(|(_, dst)| match_refspec_pattern(dst, name).is_some())
+    })
 }
@@ -0,0 +1,201 @@
+//! Fetch using the git binary.
+
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Output, Stdio},
+    time::Instant,
+};
+
+use log::{debug, info, trace, warn};
+use regex::bytes::RegexBuilder;
+
+use super::{map_ref, Error, PROGRESS_INTERVAL};
+
+fn describe(command: &Command) -> String {
+    let args = command
+        .get_args()
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>();
+    format!("git {}", args.join(" "))
+}
+
+fn run(mut command: Command) -> Result<Output, Error> {
+    let output = command.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        trace!("Command exited with {}", output.status);
+        trace!("COMMAND: {command:?}");
+        if !output.stdout.is_empty() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            trace!("STDOUT:\n{}", stdout.trim_end());
+        }
+        if !output.stderr.is_empty() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            trace!("STDERR:\n{}", stderr.trim_end());
+        }
+        Ok(output)
+    } else {
+        Err(Error::Failed {
+            command: describe(&command),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+}
+
+/// Logs git's progress output, which consists of lines like
+/// `Receiving objects:  42% (420/1000)` overwriting each other.
+struct Progress {
+    last_logged: Instant,
+}
+
+impl Progress {
+    fn new() -> Self {
+        Self {
+            last_logged: Instant::now(),
+        }
+    }
+
+    fn update(&mut self, line: &str) {
+        if line.is_empty() {
+            return;
+        }
+        trace!("git: {line}");
+        if self.last_logged.elapsed() >= PROGRESS_INTERVAL {
+            self.last_logged = Instant::now();
+            info!("Fetch progress: {line}");
+        }
+    }
+
+    fn done(&mut self, line: &str) {
+        debug!("git: {line}");
+    }
+}
+
+fn is_progress_line(line: &str) -> bool {
+    let line = line.strip_prefix("remote: ").unwrap_or(line);
+    line.starts_with("Enumerating objects")
+        || line.starts_with("Counting objects")
+        || line.starts_with("Compressing objects")
+        || line.starts_with("Receiving objects")
+        || line.starts_with("Resolving deltas")
+        || line.starts_with("Total ")
+}
+
+pub fn init_bare(path: &Path) -> Result<(), Error> {
+    let mut command = Command::new("git");
+    command.arg("init").arg("--bare").arg("--").arg(path);
+    run(command)?;
+    Ok(())
+}
+
+pub fn fetch_head(path: &Path, url: &str, refspecs: &[String]) -> Result<(), Error> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(path)
+        .arg("ls-remote")
+        .arg("--symref")
+        .arg("--")
+        .arg(url)
+        .arg("HEAD"); // Includes other refs like refs/foo/HEAD
+    let output = run(command)?;
+
+    let regex = RegexBuilder::new(r"^ref: (refs/\S+)\s+HEAD$")
+        .multi_line(true)
+        .build()
+        .unwrap();
+    let Some(captures) = regex.captures(&output.stdout) else {
+        warn!("Did not find HEAD of {url}");
+        return Ok(());
+    };
+    let head = String::from_utf8_lossy(captures.get(1).unwrap().as_bytes());
+    let Some(head) = map_ref(refspecs, &head) else {
+        warn!("HEAD of {url} ({head}) is not fetched by any refspec");
+        return Ok(());
+    };
+
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(path)
+        .arg("symbolic-ref")
+        .arg("--")
+        .arg("HEAD")
+        .arg(&head);
+    run(command)?;
+
+    Ok(())
+}
+
+/// Fetch refs from a remote, logging progress along the way.
+///
+/// Returns git's summary of the updated refs.
+pub fn fetch(path: &Path, url: &str, refspecs: &[String]) -> Result<String, Error> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(path)
+        .arg("fetch")
+        .arg("--prune")
+        .arg("--no-tags")
+        .arg("--progress")
+        .arg("--")
+        .arg(url);
+    for refspec in refspecs {
+        command.arg(refspec);
+    }
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+
+    let mut child = command.spawn()?;
+    let mut stderr = child.stderr.take().unwrap();
+
+    // Progress lines end in \r so they overwrite each other in a terminal,
+    // everything else ends in \n.
+    let mut progress = Progress::new();
+    let mut summary = String::new();
+    let mut line = vec![];
+    let mut buf = [0; 4096];
+    loop {
+        let n = stderr.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        for byte in &buf[..n] {
+            match byte {
+                b'\r' => progress.update(String::from_utf8_lossy(&line).trim_end()),
+                b'\n' => {
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end();
+                    if is_progress_line(line) {
+                        progress.done(line);
+                    } else {
+                        summary.push_str(line);
+                        summary.push('\n');
+                    }
+                }
+                _ => {
+                    line.push(*byte);
+                    continue;
+                }
+            }
+            line.clear();
+        }
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        trace!("Command exited with {status}");
+        trace!("COMMAND: {command:?}");
+        Ok(summary)
+    } else {
+        Err(Error::Failed {
+            command: describe(&command),
+            status,
+            stderr: summary,
+        })
+    }
+}
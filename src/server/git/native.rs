@@ -0,0 +1,211 @@
+//! Fetch natively using gix.
+
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use gix::{
+    bstr::ByteSlice,
+    progress::tree::{Item, Root},
+    protocol::handshake::Ref,
+    refs::{
+        transaction::{Change, LogChange, PreviousValue, RefEdit},
+        Target,
+    },
+    remote::{
+        fetch::{refs::update::Mode, Status, Tags},
+        ref_map, Direction,
+    },
+    Remote, Repository,
+};
+use log::{debug, info, warn};
+
+use super::{is_mapped_ref, map_ref, Error, PROGRESS_INTERVAL};
+
+/// How often the progress logger checks whether the fetch has finished.
+const PROGRESS_POLL: Duration = Duration::from_millis(100);
+
+/// Run `f` with a progress tree whose state is logged periodically.
+fn with_progress<T>(f: impl FnOnce(Item) -> T) -> T {
+    let root = Root::new();
+    let item = root.add_child("fetch");
+    let done = AtomicBool::new(false);
+
+    let result = thread::scope(|s| {
+        s.spawn(|| log_progress(&root, &done));
+        let result = f(item);
+        done.store(true, Ordering::Relaxed);
+        result
+    });
+
+    let mut messages = vec![];
+    root.copy_messages(&mut messages);
+    for message in messages {
+        debug!("gix: {}: {}", message.origin, message.message);
+    }
+
+    result
+}
+
+fn log_progress(root: &Root, done: &AtomicBool) {
+    let mut last_logged = Instant::now();
+    let mut tasks = vec![];
+    while !done.load(Ordering::Relaxed) {
+        thread::sleep(PROGRESS_POLL);
+        if last_logged.elapsed() < PROGRESS_INTERVAL {
+            continue;
+        }
+        last_logged = Instant::now();
+
+        root.sorted_snapshot(&mut tasks);
+        let line = tasks
+            .iter()
+            .filter_map(|(_, task)| {
+                let progress = task.progress.as_ref()?;
+                let step = progress.step.load(Ordering::Relaxed);
+                Some(match progress.done_at {
+                    Some(max) => format!("{} {step}/{max}", task.name),
+                    None => format!("{} {step}", task.name),
+                })
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !line.is_empty() {
+            info!("Fetch progress: {line}");
+        }
+    }
+}
+
+fn remote<'repo>(
+    repo: &'repo Repository,
+    url: &str,
+    refspecs: &[String],
+) -> Result<Remote<'repo>, Error> {
+    repo.remote_at(url)
+        .map_err(Error::gix)?
+        .with_fetch_tags(Tags::None)
+        .with_refspecs(refspecs.iter().map(|r| r.as_str()), Direction::Fetch)
+        .map_err(Error::gix)
+}
+
+pub fn init_bare(path: &Path) -> Result<(), Error> {
+    gix::init_bare(path).map_err(Error::gix)?;
+    Ok(())
+}
+
+pub fn fetch_head(path: &Path, url: &str, refspecs: &[String]) -> Result<(), Error> {
+    let repo = gix::open(path).map_err(Error::gix)?;
+    let remote = remote(&repo, url, refspecs)?;
+
+    let head = gix::refspec::parse("HEAD".into(), gix::refspec::parse::Operation::Fetch)
+        .map_err(Error::gix)?
+        .to_owned();
+    let options = ref_map::Options {
+        extra_refspecs: vec![head],
+        ..Default::default()
+    };
+    let ref_map = with_progress(|progress| {
+        remote
+            .connect(Direction::Fetch)
+            .map_err(Error::gix)?
+            .ref_map(progress, options)
+            .map_err(Error::gix)
+    })?;
+
+    let head = ref_map.remote_refs.iter().find_map(|r| match r {
+        Ref::Symbolic {
+            full_ref_name,
+            target,
+            ..
+        }
+        | Ref::Unborn {
+            full_ref_name,
+            target,
+        } if full_ref_name == "HEAD" => Some(target.to_str_lossy().to_string()),
+        _ => None,
+    });
+    let Some(head) = head else {
+        warn!("Did not find HEAD of {url}");
+        return Ok(());
+    };
+    let Some(head) = map_ref(refspecs, &head) else {
+        warn!("HEAD of {url} ({head}) is not fetched by any refspec");
+        return Ok(());
+    };
+
+    repo.edit_reference(RefEdit {
+        change: Change::Update {
+            log: LogChange::default(),
+            expected: PreviousValue::Any,
+            new: Target::Symbolic(head.try_into().map_err(Error::gix)?),
+        },
+        name: "HEAD".try_into().map_err(Error::gix)?,
+        deref: false,
+    })
+    .map_err(Error::gix)?;
+
+    Ok(())
+}
+
+pub fn fetch(path: &Path, url: &str, refspecs: &[String]) -> Result<String, Error> {
+    let repo = gix::open(path).map_err(Error::gix)?;
+    let remote = remote(&repo, url, refspecs)?;
+
+    let interrupt = AtomicBool::new(false);
+    let outcome = with_progress(|mut progress| {
+        remote
+            .connect(Direction::Fetch)
+            .map_err(Error::gix)?
+            .prepare_fetch(progress.add_child("list refs"), ref_map::Options::default())
+            .map_err(Error::gix)?
+            .receive(progress, &interrupt)
+            .map_err(Error::gix)
+    })?;
+
+    let update_refs = match &outcome.status {
+        Status::NoPackReceived { update_refs, .. } | Status::Change { update_refs, .. } => {
+            update_refs
+        }
+    };
+
+    // Mimic the summary the git binary prints
+    let mut summary = String::new();
+    for (update, mapping) in update_refs.updates.iter().zip(&outcome.ref_map.mappings) {
+        if matches!(update.mode, Mode::NoChangeNeeded) {
+            continue;
+        }
+        let Some(local) = &mapping.local else {
+            continue;
+        };
+        summary.push_str(&format!(" [{}] {local}\n", update.mode));
+    }
+
+    // The remote only lists refs it still has, so every other ref in the
+    // refspecs' destinations is stale.
+    let fetched = outcome
+        .ref_map
+        .mappings
+        .iter()
+        .filter_map(|m| m.local.as_ref())
+        .map(|name| name.to_string())
+        .collect::<HashSet<_>>();
+    let references = repo.references().map_err(Error::gix)?;
+    let mut stale = vec![];
+    for reference in references.all().map_err(Error::gix)? {
+        let reference = reference.map_err(Error::Gix)?;
+        let name = reference.name().as_bstr().to_string();
+        if is_mapped_ref(refspecs, &name) && !fetched.contains(&name) {
+            stale.push((name, reference));
+        }
+    }
+    for (name, reference) in stale {
+        reference.delete().map_err(Error::gix)?;
+        summary.push_str(&format!(" [deleted] {name}\n"));
+    }
+
+    Ok(summary)
+}
@@ -0,0 +1,286 @@
+//! Import runs from other sources into a db.
+//!
+//! Imported runs are saved exactly like runs submitted by workers, except that
+//! no statuses or notifications are sent for them.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::anyhow;
+use log::{info, warn};
+use sqlx::{sqlite::SqliteConnectOptions, Acquire, SqlitePool};
+use time::{macros::format_description, PrimitiveDateTime};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    args::ImportCommand,
+    config::ServerConfig,
+    primitive::{Source, Timestamp},
+    shared::{FinishedRun, Measurement},
+    somehow,
+};
+
+use super::{open_db, web::api::worker::save_run};
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+#[derive(Default)]
+struct Stats {
+    imported: usize,
+    skipped: usize,
+}
+
+async fn import_run(
+    config: &ServerConfig,
+    db: &SqlitePool,
+    stats: &mut Stats,
+    run: FinishedRun,
+    worker_name: &str,
+    worker_info: &Option<String>,
+) -> somehow::Result<()> {
+    let known = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT * FROM commits WHERE hash = ?) AS "known!: bool""#,
+        run.hash,
+    )
+    .fetch_one(db)
+    .await?;
+    if !known {
+        warn!("Skipping run {}: Unknown commit {}", run.id, run.hash);
+        stats.skipped += 1;
+        return Ok(());
+    }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT * FROM runs WHERE id = ?) AS "exists!: bool""#,
+        run.id,
+    )
+    .fetch_one(db)
+    .await?;
+    if exists {
+        warn!("Skipping run {}: Run already exists", run.id);
+        stats.skipped += 1;
+        return Ok(());
+    }
+
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+    save_run(conn, run, worker_name, worker_info, config).await?;
+    tx.commit().await?;
+
+    stats.imported += 1;
+    Ok(())
+}
+
+async fn import_json_lines(
+    config: &ServerConfig,
+    db: &SqlitePool,
+    stats: &mut Stats,
+    path: &Path,
+    worker_name: &str,
+) -> somehow::Result<()> {
+    let text = tokio::fs::read_to_string(path).await?;
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let run = match serde_json::from_str::<FinishedRun>(line) {
+            Ok(run) => run,
+            Err(e) => {
+                warn!("Skipping line {}: {e}", i + 1);
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        import_run(config, db, stats, run, worker_name, &None).await?;
+    }
+    Ok(())
+}
+
+/// Velcom stores its times in UTC but omits the time zone offset.
+fn velcom_time(time: &str) -> somehow::Result<Timestamp> {
+    let format = format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second][optional [.[subsecond]]]"
+    );
+    let time = PrimitiveDateTime::parse(&time.replacen('T', " ", 1), format)?;
+    Ok(Timestamp(time.assume_utc()))
+}
+
+async fn velcom_repo(velcom: &SqlitePool, repo: Option<String>) -> somehow::Result<String> {
+    let repos = sqlx::query_scalar::<_, String>(
+        "SELECT DISTINCT repo_id FROM run WHERE commit_hash IS NOT NULL ORDER BY repo_id",
+    )
+    .fetch_all(velcom)
+    .await?;
+
+    match (repo, &repos[..]) {
+        (Some(repo), _) if repos.contains(&repo) => Ok(repo),
+        (Some(repo), _) => Err(somehow::Error(anyhow!(
+            "Velcom db has no runs of repo {repo}"
+        ))),
+        (None, [repo]) => Ok(repo.clone()),
+        (None, []) => Err(somehow::Error(anyhow!("Velcom db has no runs"))),
+        (None, repos) => Err(somehow::Error(anyhow!(
+            "Velcom db has runs of multiple repos, pick one with --velcom-repo: {}",
+            repos.join(", ")
+        ))),
+    }
+}
+
+/// The parts of a velcom run we care about.
+#[derive(sqlx::FromRow)]
+struct VelcomRun {
+    runner_name: String,
+    runner_info: Option<String>,
+    start_time: String,
+    stop_time: String,
+    commit_hash: String,
+    error_type: Option<String>,
+    error: Option<String>,
+}
+
+/// Collect a velcom run in the format workers submit runs in. Returns the
+/// run along with the name and info of the runner that performed it.
+async fn velcom_run(
+    velcom: &SqlitePool,
+    id: &str,
+) -> somehow::Result<(FinishedRun, String, Option<String>)> {
+    let run = sqlx::query_as::<_, VelcomRun>(
+        "
+        SELECT runner_name, runner_info, start_time, stop_time, commit_hash, error_type, error
+        FROM run
+        WHERE id = ?
+        ",
+    )
+    .bind(id)
+    .fetch_one(velcom)
+    .await?;
+
+    let mut output = vec![];
+    let mut measurements = HashMap::new();
+
+    let rows = sqlx::query_as::<_, (String, String, String, Option<String>, Option<String>)>(
+        "SELECT id, benchmark, metric, unit, error FROM measurement WHERE run_id = ?",
+    )
+    .bind(id)
+    .fetch_all(velcom)
+    .await?;
+    for (measurement_id, benchmark, metric, unit, error) in rows {
+        if let Some(error) = error {
+            for line in error.lines() {
+                output.push((Source::Stderr, line.to_string()));
+            }
+            continue;
+        }
+
+        let values = sqlx::query_scalar::<_, f64>(
+            "SELECT value FROM measurement_value WHERE measurement_id = ?",
+        )
+        .bind(&measurement_id)
+        .fetch_all(velcom)
+        .await?;
+        if values.is_empty() {
+            continue;
+        }
+
+        let value = values.iter().sum::<f64>() / values.len() as f64;
+        let measurement = Measurement {
+            value,
+            unit,
+            direction: None,
+        };
+        measurements.insert(format!("{metric}/{benchmark}"), measurement);
+    }
+
+    if let Some(error_type) = &run.error_type {
+        output.push((
+            Source::Stderr,
+            format!("The entire run failed with error of type {error_type}."),
+        ));
+        output.push((Source::Stderr, String::new()));
+        for line in run.error.as_deref().unwrap_or_default().lines() {
+            output.push((Source::Stderr, line.to_string()));
+        }
+    }
+
+    let finished = FinishedRun {
+        id: id.to_string(),
+        hash: run.commit_hash,
+        bench_method: "imported from velcom".to_string(),
+        start: velcom_time(&run.start_time)?,
+        end: Some(velcom_time(&run.stop_time)?),
+        exit_code: if run.error_type.is_some() { -1 } else { 0 },
+        output,
+        phases: vec![],
+        measurements,
+    };
+
+    Ok((finished, run.runner_name, run.runner_info))
+}
+
+async fn import_velcom(
+    config: &ServerConfig,
+    db: &SqlitePool,
+    stats: &mut Stats,
+    path: &Path,
+    repo: Option<String>,
+) -> somehow::Result<()> {
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let velcom = SqlitePool::connect_with(options).await?;
+
+    let repo = velcom_repo(&velcom, repo).await?;
+    info!("Importing runs of velcom repo {repo}");
+
+    let ids = sqlx::query_scalar::<_, String>(
+        "
+        SELECT id FROM run
+        WHERE repo_id = ? AND commit_hash IS NOT NULL
+        ORDER BY start_time ASC
+        ",
+    )
+    .bind(&repo)
+    .fetch_all(&velcom)
+    .await?;
+
+    for (i, id) in ids.iter().enumerate() {
+        info!("Importing run {id} ({}/{})", i + 1, ids.len());
+        let (run, runner_name, runner_info) = match velcom_run(&velcom, id).await {
+            Ok(run) => run,
+            Err(e) => {
+                warn!("Skipping run {id}:\n{e:?}");
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        import_run(config, db, stats, run, &runner_name, &runner_info).await?;
+    }
+
+    velcom.close().await;
+    Ok(())
+}
+
+pub async fn import(config: &ServerConfig, command: ImportCommand) -> somehow::Result<()> {
+    let mut header = vec![];
+    tokio::fs::File::open(&command.file)
+        .await?
+        .take(SQLITE_HEADER.len() as u64)
+        .read_to_end(&mut header)
+        .await?;
+
+    let db = open_db(&command.db).await?;
+    let mut stats = Stats::default();
+
+    if header == SQLITE_HEADER {
+        info!("Importing velcom db {}", command.file.display());
+        import_velcom(config, &db, &mut stats, &command.file, command.velcom_repo).await?;
+    } else {
+        info!("Importing json lines from {}", command.file.display());
+        import_json_lines(config, &db, &mut stats, &command.file, &command.worker).await?;
+    }
+
+    db.close().await;
+
+    info!(
+        "Imported {} runs, skipped {}",
+        stats.imported, stats.skipped
+    );
+    Ok(())
+}
@@ -0,0 +1,515 @@
+//! Send notifications about events to webhooks and via email.
+//!
+//! Notifications are rendered as soon as their event happens and stored in the
+//! db until they have been delivered, so failed deliveries can be retried, even
+//! across restarts.
+
+use std::time::Duration;
+
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use log::{debug, info, warn};
+use reqwest::{header, Client};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{SqliteConnection, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{
+    config::{
+        NotificationTarget, ServerConfig, ServerNotification, ServerSmtp, SmtpTls, WebhookFormat,
+    },
+    primitive::{Event, Timestamp},
+    server::{
+        changes::{self, Changes},
+        delivery::{self, Pending, Queue},
+        format,
+        web::paths::PathRunById,
+        Deliveries,
+    },
+    somehow,
+};
+
+#[derive(Serialize)]
+struct RunDetails {
+    id: String,
+    hash: String,
+    /// Absolute path of the run's page, without scheme and host.
+    path: String,
+    exit_code: i64,
+    /// Compared to the nearest measured first-parent ancestor, if any.
+    changes: Option<Changes>,
+}
+
+/// What gets stored in the db and sent to json webhooks.
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: Event,
+    repo_name: &'a str,
+    time: Timestamp,
+    /// Human-readable summary of the event.
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run: Option<&'a RunDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    worker: Option<&'a str>,
+}
+
+/// The part of a stored payload that's needed for plain text notifications.
+#[derive(Deserialize)]
+struct StoredPayload {
+    message: String,
+}
+
+async fn insert(
+    conn: &mut SqliteConnection,
+    target: &ServerNotification,
+    payload: &Payload<'_>,
+) -> somehow::Result<()> {
+    let event = payload.event.name();
+    let payload = serde_json::to_string(payload)?;
+    let now = OffsetDateTime::now_utc();
+    sqlx::query!(
+        "
+        INSERT INTO notifications (target, event, payload, next_attempt)
+        VALUES (?, ?, ?, ?)
+        ",
+        target.name,
+        event,
+        payload,
+        now,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Queue notifications about a freshly saved run.
+pub async fn run_saved(
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+    run_id: &str,
+    hash: &str,
+    exit_code: i64,
+) -> somehow::Result<()> {
+    if config.notifications.is_empty() {
+        return Ok(());
+    }
+
+    let message = sqlx::query_scalar!("SELECT message FROM commits WHERE hash = ?", hash)
+        .fetch_one(&mut *conn)
+        .await?;
+    let commit = format::commit_short(hash, &message);
+    let repo_name = &config.repo_name;
+    let path = format!(
+        "{}{}",
+        config.web_base,
+        PathRunById {
+            id: run_id.to_string()
+        }
+    );
+
+    for target in &config.notifications {
+        let wants = |event| target.events.contains(&event);
+
+        if exit_code != 0 {
+            if wants(Event::RunFailed) {
+                let run = RunDetails {
+                    id: run_id.to_string(),
+                    hash: hash.to_string(),
+                    path: path.clone(),
+                    exit_code,
+                    changes: None,
+                };
+                let payload = Payload {
+                    event: Event::RunFailed,
+                    repo_name,
+                    time: Timestamp::now(),
+                    message: format!(
+                        "[{repo_name}] Run {run_id} of {commit} failed with exit code {exit_code}"
+                    ),
+                    run: Some(&run),
+                    worker: None,
+                };
+                insert(conn, target, &payload).await?;
+            }
+            continue;
+        }
+
+        if !wants(Event::RunFinished) && !wants(Event::Regression) {
+            continue;
+        }
+
+        let changes = changes::since_baseline(conn, hash, run_id, target.threshold).await?;
+        let summary = match &changes {
+            Some(changes) => changes.describe(),
+            None => "nothing to compare against".to_string(),
+        };
+        let regressions = changes
+            .as_ref()
+            .map(|c| c.regressions.join(", "))
+            .unwrap_or_default();
+        let regressed = changes.as_ref().is_some_and(|c| !c.regressions.is_empty());
+        let run = RunDetails {
+            id: run_id.to_string(),
+            hash: hash.to_string(),
+            path: path.clone(),
+            exit_code,
+            changes,
+        };
+
+        if wants(Event::RunFinished) {
+            let payload = Payload {
+                event: Event::RunFinished,
+                repo_name,
+                time: Timestamp::now(),
+                message: format!("[{repo_name}] Run {run_id} of {commit} finished: {summary}"),
+                run: Some(&run),
+                worker: None,
+            };
+            insert(conn, target, &payload).await?;
+        }
+
+        if wants(Event::Regression) && regressed {
+            let payload = Payload {
+                event: Event::Regression,
+                repo_name,
+                time: Timestamp::now(),
+                message: format!("[{repo_name}] Run {run_id} of {commit} regressed: {regressions}"),
+                run: Some(&run),
+                worker: None,
+            };
+            insert(conn, target, &payload).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Queue notifications about the last entry leaving the queue.
+pub async fn queue_empty(
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+) -> somehow::Result<()> {
+    let repo_name = &config.repo_name;
+    for target in &config.notifications {
+        if !target.events.contains(&Event::QueueEmpty) {
+            continue;
+        }
+        let payload = Payload {
+            event: Event::QueueEmpty,
+            repo_name,
+            time: Timestamp::now(),
+            message: format!("[{repo_name}] The queue is empty"),
+            run: None,
+            worker: None,
+        };
+        insert(conn, target, &payload).await?;
+    }
+    Ok(())
+}
+
+/// Queue notifications about workers that timed out.
+pub async fn workers_disconnected(
+    config: &ServerConfig,
+    db: &SqlitePool,
+    names: &[String],
+) -> somehow::Result<()> {
+    if names.is_empty() || config.notifications.is_empty() {
+        return Ok(());
+    }
+
+    let repo_name = &config.repo_name;
+    let mut conn = db.acquire().await?;
+    for name in names {
+        info!("Worker {name} disconnected");
+        for target in &config.notifications {
+            if !target.events.contains(&Event::WorkerDisconnected) {
+                continue;
+            }
+            let payload = Payload {
+                event: Event::WorkerDisconnected,
+                repo_name,
+                time: Timestamp::now(),
+                message: format!("[{repo_name}] Worker {name} disconnected"),
+                run: None,
+                worker: Some(name),
+            };
+            insert(&mut conn, target, &payload).await?;
+        }
+    }
+    Ok(())
+}
+
+fn smtp_transport(smtp: &ServerSmtp) -> somehow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+    let mut builder = match smtp.tls {
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)?,
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)?,
+        SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host),
+    };
+    if let Some(port) = smtp.port {
+        builder = builder.port(port);
+    }
+    if let Some(username) = &smtp.username {
+        let password = smtp.password.clone().unwrap_or_default();
+        builder = builder.credentials(Credentials::new(username.clone(), password));
+    }
+    Ok(builder.timeout(Some(Duration::from_secs(30))).build())
+}
+
+async fn send(
+    config: &ServerConfig,
+    target: &ServerNotification,
+    client: &Client,
+    payload: &str,
+) -> somehow::Result<()> {
+    let StoredPayload { message } = serde_json::from_str(payload)?;
+
+    match &target.target {
+        NotificationTarget::Webhook {
+            url,
+            format,
+            headers,
+        } => {
+            let body = match format {
+                WebhookFormat::Json => payload.to_string(),
+                WebhookFormat::Slack => json!({ "text": message }).to_string(),
+            };
+            let response = client
+                .post(url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .headers(headers.clone())
+                .body(body)
+                .send()
+                .await?;
+            delivery::check_response(response).await?;
+        }
+
+        NotificationTarget::Email { to } => {
+            let smtp = config
+                .smtp
+                .as_ref()
+                .expect("checked when loading the config");
+            let mut email = Message::builder().from(smtp.from.clone()).subject(&message);
+            for to in to {
+                email = email.to(to.clone());
+            }
+            let email = email
+                .header(ContentType::TEXT_PLAIN)
+                .body(format!("{message}\n"))?;
+            smtp_transport(smtp)?.send(email).await?;
+        }
+    }
+
+    Ok(())
+}
+
+struct Notification {
+    id: i64,
+    event: String,
+    payload: String,
+}
+
+struct Notifications<'a> {
+    config: &'a ServerConfig,
+    db: &'a SqlitePool,
+}
+
+impl Queue for Notifications<'_> {
+    type Item = Notification;
+    type Target = ServerNotification;
+
+    async fn due(&self, now: OffsetDateTime) -> somehow::Result<Vec<Pending<Notification>>> {
+        let due = sqlx::query!(
+            "
+            SELECT id, target, event, payload, attempts FROM notifications
+            WHERE unixepoch(next_attempt) <= unixepoch(?)
+            ORDER BY unixepoch(next_attempt) ASC, id ASC
+            ",
+            now,
+        )
+        .fetch_all(self.db)
+        .await?
+        .into_iter()
+        .map(|r| Pending {
+            target: r.target,
+            attempts: r.attempts,
+            item: Notification {
+                id: r.id,
+                event: r.event,
+                payload: r.payload,
+            },
+        })
+        .collect();
+        Ok(due)
+    }
+
+    fn target(&self, name: &str) -> Option<&ServerNotification> {
+        self.config.notifications.iter().find(|t| t.name == name)
+    }
+
+    fn retries(target: &ServerNotification) -> u32 {
+        target.retries
+    }
+
+    fn describe(notification: &Notification) -> String {
+        format!("{} notification", notification.event)
+    }
+
+    async fn send(
+        &self,
+        target: &ServerNotification,
+        client: &Client,
+        notification: &Notification,
+    ) -> somehow::Result<()> {
+        send(self.config, target, client, &notification.payload).await
+    }
+
+    async fn retry_later(
+        &self,
+        pending: &Pending<Notification>,
+        attempts: u32,
+        next_attempt: OffsetDateTime,
+    ) -> somehow::Result<()> {
+        sqlx::query!(
+            "UPDATE notifications SET attempts = ?, next_attempt = ? WHERE id = ?",
+            attempts,
+            next_attempt,
+            pending.item.id,
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, pending: &Pending<Notification>) -> somehow::Result<()> {
+        sqlx::query!("DELETE FROM notifications WHERE id = ?", pending.item.id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+async fn inner(config: &ServerConfig, db: &SqlitePool) -> somehow::Result<()> {
+    delivery::process(&Notifications { config, db }).await
+}
+
+/// Send all notifications that are due.
+pub async fn deliver(config: &ServerConfig, db: SqlitePool, deliveries: Deliveries) {
+    let _guard = deliveries.notify.lock().await;
+    debug!("Delivering notifications");
+    if let Err(e) = inner(config, &db).await {
+        warn!("Error delivering notifications:\n{e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use axum::http::{Method, StatusCode};
+    use clap::Parser;
+    use serde_json::Value;
+    use sqlx::SqlitePool;
+    use time::OffsetDateTime;
+
+    use crate::{
+        args::{Args, NAME},
+        config::Config,
+        server::testing::{db_with_run, forge, HASH, RUN_ID},
+    };
+
+    fn config(dir: &Path, url: &str) -> Config {
+        let path = dir.join("config.toml");
+        let config = format!(
+            r#"
+            [server.repo]
+            name = "my repo"
+
+            [server.notifications.hook]
+            type = "webhook"
+            url = "{url}/hook"
+            events = ["run_failed"]
+            retries = 1
+            "#
+        );
+        fs::write(&path, config).unwrap();
+
+        let args = Args::parse_from([NAME, "--config", path.to_str().unwrap(), "worker"]);
+        Config::load(&args).unwrap()
+    }
+
+    /// Store the notifications about the failed run.
+    async fn run_failed(config: &Config, db: &SqlitePool) {
+        let mut conn = db.acquire().await.unwrap();
+        super::run_saved(&config.server, &mut conn, RUN_ID, HASH, 1)
+            .await
+            .unwrap();
+    }
+
+    async fn notifications(db: &SqlitePool) -> Vec<(String, i64)> {
+        sqlx::query_as("SELECT event, attempts FROM notifications")
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_webhook() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = forge(StatusCode::OK).await;
+        let config = config(dir.path(), &url);
+        let db = db_with_run(dir.path(), 1).await;
+
+        run_failed(&config, &db).await;
+        assert_eq!(notifications(&db).await, [("run_failed".to_string(), 0)]);
+
+        super::inner(&config.server, &db).await.unwrap();
+        assert!(notifications(&db).await.is_empty());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.uri.path(), "/hook");
+        assert_eq!(request.headers["content-type"], "application/json");
+
+        let body = serde_json::from_str::<Value>(&request.body).unwrap();
+        assert_eq!(body["event"], "run_failed");
+        assert_eq!(body["repo_name"], "my repo");
+        assert_eq!(body["run"]["id"], RUN_ID);
+        assert_eq!(body["run"]["exit_code"], 1);
+    }
+
+    #[tokio::test]
+    async fn retries_then_gives_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = forge(StatusCode::SERVICE_UNAVAILABLE).await;
+        let config = config(dir.path(), &url);
+        let db = db_with_run(dir.path(), 1).await;
+
+        run_failed(&config, &db).await;
+
+        // The first attempt fails and is retried later
+        super::inner(&config.server, &db).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(notifications(&db).await, [("run_failed".to_string(), 1)]);
+
+        // Not due yet, so nothing is sent
+        super::inner(&config.server, &db).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        sqlx::query("UPDATE notifications SET next_attempt = ?")
+            .bind(OffsetDateTime::now_utc())
+            .execute(&db)
+            .await
+            .unwrap();
+
+        // The only retry fails as well, so the notification is dropped
+        super::inner(&config.server, &db).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert!(notifications(&db).await.is_empty());
+    }
+}
@@ -1,18 +1,38 @@
 //! Recurring actions and updates.
 
+mod bisect;
 mod fetch;
 mod queue;
 mod repo;
+mod report;
+mod schedule;
+mod workers;
 
 use tokio::sync::mpsc;
 
-use super::{Repo, Server};
+use super::{notify, status, Repo, Server};
 
 pub(super) async fn run(server: Server, repo: Repo, mut recurring_rx: mpsc::UnboundedReceiver<()>) {
+    let mut reports = report::Processed::new();
     loop {
         fetch::update(server.config, repo.clone()).await;
-        repo::update(&server.db, repo.clone()).await;
+        repo::update(server.config, &server.db, repo.clone()).await;
         queue::update(&server.db).await;
+        schedule::update(server.config, &server.db).await;
+        report::update(server.config, &server.db, repo.clone(), &mut reports).await;
+        bisect::update(server.config, &server.db).await;
+        workers::update(&server).await;
+        // Slow targets shouldn't hold up the other updates
+        tokio::spawn(status::deliver(
+            server.config,
+            server.db.clone(),
+            server.deliveries.clone(),
+        ));
+        tokio::spawn(notify::deliver(
+            server.config,
+            server.db.clone(),
+            server.deliveries.clone(),
+        ));
 
         let _ = tokio::time::timeout(server.config.repo_update, recurring_rx.recv()).await;
         while let Ok(()) = recurring_rx.try_recv() {}
@@ -0,0 +1,316 @@
+//! Find the commits responsible for jumps in metrics.
+//!
+//! Measured commits along the first-parent chain of the tracked refs are
+//! compared pairwise. When a metric jumps between two measured commits, the
+//! unmeasured commit in the middle is added to the queue. Once that commit has
+//! been measured, the next update narrows down the range further, until the
+//! jump is between two adjacent commits. The newer of the two is the culprit
+//! and is recorded in the db.
+//!
+//! Only the most recent `[server.queue] bisect_depth` commits of each chain are
+//! looked at, and the db is only written to once it's clear what to enqueue.
+
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, info, warn};
+use sqlx::{SqliteConnection, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{config::ServerConfig, primitive::QueueKind, somehow};
+
+struct ChainCommit {
+    hash: String,
+    /// The commit has a successful run.
+    measured: bool,
+    /// The commit has any run, successful or not.
+    attempted: bool,
+    /// The commit has a run that was queued to bisect a jump.
+    bisected: bool,
+    queued: bool,
+    /// The commit was already found to cause a jump.
+    culprit: bool,
+}
+
+/// The first `depth` commits of the first-parent chain starting at a commit,
+/// newest commit first.
+async fn first_parent_chain(
+    conn: &mut SqliteConnection,
+    tip: &str,
+    depth: u32,
+) -> somehow::Result<Vec<ChainCommit>> {
+    let chain = sqlx::query!(
+        r#"
+        WITH RECURSIVE chain (hash, n) AS (
+            SELECT ?1, 0
+            UNION ALL
+            SELECT parent, n + 1
+            FROM commit_edges
+            JOIN chain ON child = hash
+            WHERE position = 0 AND n + 1 < ?2
+        )
+        SELECT
+            hash AS "hash!: String",
+            EXISTS (
+                SELECT * FROM runs
+                WHERE runs.hash = chain.hash AND exit_code = 0
+            ) AS "measured!: bool",
+            EXISTS (SELECT * FROM runs WHERE runs.hash = chain.hash) AS "attempted!: bool",
+            EXISTS (
+                SELECT * FROM runs
+                WHERE runs.hash = chain.hash AND kind = ?3
+            ) AS "bisected!: bool",
+            EXISTS (SELECT * FROM queue WHERE queue.hash = chain.hash) AS "queued!: bool",
+            EXISTS (
+                SELECT * FROM bisect_culprits
+                WHERE bisect_culprits.hash = chain.hash
+            ) AS "culprit!: bool"
+        FROM chain
+        ORDER BY n ASC
+        "#,
+        tip,
+        depth,
+        QueueKind::Bisect,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|r| ChainCommit {
+        hash: r.hash,
+        measured: r.measured,
+        attempted: r.attempted,
+        bisected: r.bisected,
+        queued: r.queued,
+        culprit: r.culprit,
+    })
+    .collect();
+
+    Ok(chain)
+}
+
+/// A commit that caused a jump, compared to its first parent.
+struct Culprit {
+    hash: String,
+    parent: String,
+    metric: String,
+    old_value: f64,
+    new_value: f64,
+}
+
+/// A commit to enqueue to narrow down a jump.
+struct Bisection {
+    metric: String,
+    old: String,
+    new: String,
+    candidate: String,
+}
+
+/// Average value of each metric across all successful runs of a commit.
+async fn metric_values(
+    conn: &mut SqliteConnection,
+    hash: &str,
+) -> somehow::Result<HashMap<String, f64>> {
+    let values = sqlx::query!(
+        r#"
+        SELECT metric, AVG(value) AS "value!: f64"
+        FROM run_measurements
+        JOIN runs USING (id)
+        WHERE hash = ? AND exit_code = 0
+        GROUP BY metric
+        "#,
+        hash,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|r| (r.metric, r.value))
+    .collect();
+
+    Ok(values)
+}
+
+/// Find the metrics that changed by more than the threshold, along with their
+/// old and new values.
+fn find_jumps(
+    config: &ServerConfig,
+    threshold: f64,
+    old: &HashMap<String, f64>,
+    new: &HashMap<String, f64>,
+) -> Vec<(String, f64, f64)> {
+    let mut jumps = old
+        .iter()
+        .filter(|(metric, _)| {
+            config.queue_bisect_metrics.is_empty()
+                || config
+                    .queue_bisect_metrics
+                    .iter()
+                    .any(|prefix| metric.starts_with(prefix))
+        })
+        .filter_map(|(metric, old)| Some((metric.clone(), *old, *new.get(metric)?)))
+        .filter(|(_, old, new)| {
+            if *old == 0.0 {
+                *new != 0.0
+            } else {
+                ((new - old) / old).abs() > threshold
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Deterministic output makes for less confusing logs.
+    jumps.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    jumps
+}
+
+async fn inner(config: &ServerConfig, db: &SqlitePool) -> somehow::Result<()> {
+    let Some(threshold) = config.queue_bisect_threshold else {
+        return Ok(());
+    };
+
+    let mut conn = db.acquire().await?;
+
+    let tips = sqlx::query_scalar!("SELECT hash FROM refs WHERE tracked")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    // Tracked refs usually share most of their history.
+    let mut checked = HashSet::new();
+    let mut bisections = vec![];
+    let mut culprits = vec![];
+
+    for tip in tips {
+        let chain = first_parent_chain(&mut conn, &tip, config.queue_bisect_depth).await?;
+        let measured = chain
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.measured)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        for pair in measured.windows(2) {
+            let (new, old) = (&chain[pair[0]], &chain[pair[1]]);
+            let between = &chain[pair[0] + 1..pair[1]];
+
+            // Once bisection has narrowed a jump down to two adjacent
+            // commits, the newer one is the culprit.
+            if between.is_empty() {
+                let relevant = !new.culprit && (new.bisected || old.bisected);
+                if relevant && checked.insert((new.hash.clone(), old.hash.clone())) {
+                    let old_values = metric_values(&mut conn, &old.hash).await?;
+                    let new_values = metric_values(&mut conn, &new.hash).await?;
+                    let jumps = find_jumps(config, threshold, &old_values, &new_values);
+                    for (metric, old_value, new_value) in jumps {
+                        culprits.push(Culprit {
+                            hash: new.hash.clone(),
+                            parent: old.hash.clone(),
+                            metric,
+                            old_value,
+                            new_value,
+                        });
+                    }
+                }
+                continue;
+            }
+
+            // If a commit in between is still queued, we're either already
+            // bisecting or will find out more once it has been measured.
+            if between.iter().any(|c| c.queued) {
+                continue;
+            }
+
+            // Commits that failed to run can't tell us anything.
+            let middle = between.len() / 2;
+            let Some(candidate) = between
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| !c.attempted)
+                .min_by_key(|(i, _)| i.abs_diff(middle))
+                .map(|(_, c)| c)
+            else {
+                continue;
+            };
+
+            if !checked.insert((new.hash.clone(), old.hash.clone())) {
+                continue;
+            }
+
+            let old_values = metric_values(&mut conn, &old.hash).await?;
+            let new_values = metric_values(&mut conn, &new.hash).await?;
+            let Some((metric, _, _)) = find_jumps(config, threshold, &old_values, &new_values)
+                .into_iter()
+                .next()
+            else {
+                continue;
+            };
+
+            bisections.push(Bisection {
+                metric,
+                old: old.hash.clone(),
+                new: new.hash.clone(),
+                candidate: candidate.hash.clone(),
+            });
+        }
+    }
+    drop(conn);
+
+    if bisections.is_empty() && culprits.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = db.begin().await?;
+    let date = OffsetDateTime::now_utc();
+    for bisection in bisections {
+        // The candidate may have been queued since we looked.
+        let result = sqlx::query!(
+            "
+            INSERT OR IGNORE INTO queue (hash, date, priority, kind)
+            VALUES (?, ?, ?, ?)
+            ",
+            bisection.candidate,
+            date,
+            config.queue_bisect_priority,
+            QueueKind::Bisect,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            info!(
+                "Bisecting {} between {} and {}, added {} to the queue",
+                bisection.metric, bisection.old, bisection.new, bisection.candidate
+            );
+        }
+    }
+    for culprit in culprits {
+        let result = sqlx::query!(
+            "
+            INSERT OR IGNORE INTO bisect_culprits
+                (hash, metric, parent, old_value, new_value, found)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ",
+            culprit.hash,
+            culprit.metric,
+            culprit.parent,
+            culprit.old_value,
+            culprit.new_value,
+            date,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            info!(
+                "Found culprit of a jump in {} from {} to {}: {}",
+                culprit.metric, culprit.old_value, culprit.new_value, culprit.hash
+            );
+        }
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(super) async fn update(config: &ServerConfig, db: &SqlitePool) {
+    debug!("Updating bisections");
+    if let Err(e) = inner(config, db).await {
+        warn!("Error updating bisections:\n{e:?}");
+    }
+}
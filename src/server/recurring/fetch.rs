@@ -1,28 +1,39 @@
-//! Update repo refs using the `git` binary.
+//! Update repo refs by fetching from the remotes.
 
 use log::{debug, info, warn};
 
 use crate::{
-    config::ServerConfig,
+    config::{ServerConfig, ServerRemote},
     server::{git, Repo},
     somehow,
 };
 
-async fn inner(repo: Repo, url: &'static str, refspecs: &'static [String]) -> somehow::Result<()> {
-    let output =
-        tokio::task::spawn_blocking(move || git::fetch(repo.0.path(), url, refspecs)).await??;
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    if !stderr.is_empty() {
-        info!("Fetched refs:\n{}", stderr.trim_end());
+async fn inner(
+    config: &'static ServerConfig,
+    repo: Repo,
+    remote: &'static ServerRemote,
+) -> somehow::Result<()> {
+    let summary = tokio::task::spawn_blocking(move || {
+        git::fetch(
+            config.repo_fetcher,
+            repo.0.path(),
+            &remote.url,
+            &remote.refspecs,
+        )
+    })
+    .await??;
+    if !summary.is_empty() {
+        info!("Fetched refs from {}:\n{}", remote.name, summary.trim_end());
     }
     Ok(())
 }
 
 pub(super) async fn update(config: &'static ServerConfig, repo: Repo) {
-    if let Some(url) = &config.repo_fetch_url {
-        debug!("Fetching refs from {url}");
-        if let Err(e) = inner(repo, url, &config.repo_fetch_refspecs).await {
-            warn!("Error fetching refs:\n{e:?}");
+    // One unreachable remote shouldn't keep the others from being fetched.
+    for remote in &config.repo_remotes {
+        debug!("Fetching refs from {} ({})", remote.name, remote.url);
+        if let Err(e) = inner(config, repo.clone(), remote).await {
+            warn!("Error fetching refs from {}:\n{e:?}", remote.name);
         }
     }
 }
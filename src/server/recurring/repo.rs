@@ -9,6 +9,7 @@ use sqlx::{Acquire, SqliteConnection, SqlitePool};
 use time::{OffsetDateTime, UtcOffset};
 
 use crate::{
+    config::ServerConfig,
     primitive::Reachable,
     server::{format, Repo},
     somehow,
@@ -27,10 +28,33 @@ async fn get_all_commit_hashes_from_db(
     Ok(hashes)
 }
 
-fn get_all_refs_from_repo(repo: &Repository) -> somehow::Result<Vec<Reference>> {
+/// Whether a ref name matches any of the glob patterns.
+///
+/// A `*` doesn't match across `/`, but a `**` does.
+pub(super) fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        gix::glob::wildmatch(
+            pattern.as_str().into(),
+            name.into(),
+            gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+        )
+    })
+}
+
+fn get_all_refs_from_repo(
+    config: &ServerConfig,
+    repo: &Repository,
+) -> somehow::Result<Vec<Reference>> {
     let mut references = vec![];
     for reference in repo.references()?.all()? {
         let mut reference = reference.map_err(somehow::Error::from_box)?;
+
+        // Ignored refs are treated as if they didn't exist at all, so the
+        // commits only reachable from them aren't imported either.
+        if matches_any(&config.repo_ignore, &reference.name().as_bstr().to_string()) {
+            continue;
+        }
+
         reference.peel_to_id_in_place()?;
 
         // Some repos *cough*linuxkernel*cough* have refs that don't point to
@@ -61,10 +85,11 @@ fn get_new_commits_from_repo(
 }
 
 fn get_all_refs_and_new_commits_from_repo(
+    config: &ServerConfig,
     repo: &Repository,
     old: &HashSet<ObjectId>,
 ) -> somehow::Result<(Vec<Reference>, Vec<ObjectId>)> {
-    let refs = get_all_refs_from_repo(repo)?;
+    let refs = get_all_refs_from_repo(config, repo)?;
     let new = get_new_commits_from_repo(repo, &refs, old)?;
     Ok((refs, new))
 }
@@ -128,14 +153,19 @@ async fn insert_new_commit_edges(
     for (i, hash) in new.iter().enumerate() {
         let commit = hash.attach(repo).object()?.try_into_commit()?;
         let child = commit.id.to_string();
-        for parent in commit.parent_ids() {
+        for (position, parent) in commit.parent_ids().enumerate() {
             let parent = parent.to_string();
+            let position = position as u32;
             // Commits *cough*linuxkernel*cough* may list the same parent
             // multiple times, so we just ignore duplicates during insert.
             sqlx::query!(
-                "INSERT OR IGNORE INTO commit_edges (parent, child) VALUES (?, ?)",
+                "
+                INSERT OR IGNORE INTO commit_edges (parent, child, position)
+                VALUES (?, ?, ?)
+                ",
                 parent,
                 child,
+                position,
             )
             .execute(&mut *conn)
             .await?;
@@ -149,6 +179,51 @@ async fn insert_new_commit_edges(
     Ok(())
 }
 
+/// Fill in the parent positions of edges inserted before they were recorded.
+async fn fill_in_edge_positions(
+    conn: &mut SqliteConnection,
+    repo: &Repository,
+) -> somehow::Result<()> {
+    let children =
+        sqlx::query_scalar!("SELECT DISTINCT child FROM commit_edges WHERE position IS NULL")
+            .fetch_all(&mut *conn)
+            .await?;
+    if children.is_empty() {
+        return Ok(());
+    }
+
+    info!("Filling in parent positions of {} commits", children.len());
+    for (i, child) in children.iter().enumerate() {
+        let commit = child
+            .parse::<ObjectId>()?
+            .attach(repo)
+            .object()?
+            .try_into_commit()?;
+        for (position, parent) in commit.parent_ids().enumerate() {
+            let parent = parent.to_string();
+            let position = position as u32;
+            // If a parent is listed multiple times, its first position counts.
+            sqlx::query!(
+                "
+                UPDATE commit_edges SET position = ?
+                WHERE child = ? AND parent = ? AND position IS NULL
+                ",
+                position,
+                child,
+                parent,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        // So the user has something to look at while updating big repos
+        if (i + 1) % 100000 == 0 {
+            info!("Filling in parent positions: {}/{}", i + 1, children.len());
+        }
+    }
+    Ok(())
+}
+
 async fn mark_all_commits_as_old(conn: &mut SqliteConnection) -> somehow::Result<()> {
     sqlx::query!("UPDATE commits SET new = 0")
         .execute(conn)
@@ -156,42 +231,55 @@ async fn mark_all_commits_as_old(conn: &mut SqliteConnection) -> somehow::Result
     Ok(())
 }
 
-async fn update_refs(conn: &mut SqliteConnection, refs: Vec<Reference>) -> somehow::Result<()> {
+async fn update_refs(
+    conn: &mut SqliteConnection,
+    config: &ServerConfig,
+    refs: Vec<Reference>,
+) -> somehow::Result<()> {
     // Remove refs that no longer exist
     let existing = refs
         .iter()
         .map(|r| r.name.to_string())
         .collect::<HashSet<_>>();
-    let current = sqlx::query!("SELECT name FROM refs")
+    let current = sqlx::query_scalar!("SELECT name FROM refs")
         .fetch_all(&mut *conn)
-        .await?;
-    for reference in current {
-        if !existing.contains(&reference.name) {
-            sqlx::query!("DELETE FROM refs WHERE name = ?", reference.name)
+        .await?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    for name in &current {
+        if !existing.contains(name) {
+            sqlx::query!("DELETE FROM refs WHERE name = ?", name)
                 .execute(&mut *conn)
                 .await?;
         }
     }
 
-    // Add new refs and update existing refs
+    // Add new refs and update existing refs. Whether a ref is tracked is only
+    // decided when it first appears so it can still be changed manually.
     for reference in refs {
         let name = reference.name.to_string();
         let Some(hash) = reference.peeled else {
             continue;
         };
         let hash = hash.to_string();
+        let track = matches_any(&config.repo_track, &name);
 
         sqlx::query!(
             "
-            INSERT INTO refs (name, hash) VALUES (?, ?)
+            INSERT INTO refs (name, hash, tracked) VALUES (?, ?, ?)
             ON CONFLICT (name) DO UPDATE
             SET hash = excluded.hash
             ",
             name,
             hash,
+            track,
         )
         .execute(&mut *conn)
         .await?;
+
+        if track && !current.contains(&name) {
+            info!("Tracking new ref {name}");
+        }
     }
 
     Ok(())
@@ -242,7 +330,70 @@ async fn update_commit_tracked_status(conn: &mut SqliteConnection) -> somehow::R
     Ok(())
 }
 
-pub async fn inner(db: &SqlitePool, repo: Repo) -> somehow::Result<()> {
+async fn update_unreachable_commits(
+    conn: &mut SqliteConnection,
+    config: &ServerConfig,
+) -> somehow::Result<()> {
+    let now = OffsetDateTime::now_utc();
+    sqlx::query!(
+        "
+        UPDATE commits
+        SET unreachable_since = CASE
+            WHEN reachable != ?           THEN NULL
+            WHEN unreachable_since IS NULL THEN ?
+            ELSE unreachable_since
+        END
+        ",
+        Reachable::Unreachable,
+        now,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Nobody is interested in benchmarking commits that were force-pushed away.
+    let dequeued = sqlx::query!(
+        "
+        DELETE FROM queue
+        WHERE hash IN (SELECT hash FROM commits WHERE reachable = ?)
+        ",
+        Reachable::Unreachable,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if dequeued > 0 {
+        info!("Removed {dequeued} unreachable commits from the queue");
+    }
+
+    // Commits with runs are kept so their measurements aren't lost.
+    if let Some(prune) = config.repo_prune_unreachable {
+        let cutoff = now - prune;
+        let pruned = sqlx::query!(
+            "
+            DELETE FROM commits
+            WHERE reachable = ?
+            AND unixepoch(unreachable_since) < unixepoch(?)
+            AND NOT EXISTS (SELECT * FROM runs WHERE runs.hash = commits.hash)
+            ",
+            Reachable::Unreachable,
+            cutoff,
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+        if pruned > 0 {
+            info!("Pruned {pruned} unreachable commits");
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn inner(
+    config: &'static ServerConfig,
+    db: &SqlitePool,
+    repo: Repo,
+) -> somehow::Result<()> {
     let thread_local_repo = repo.0.to_thread_local();
     let mut tx = db.begin().await?;
     let conn = tx.acquire().await?;
@@ -258,7 +409,7 @@ pub async fn inner(db: &SqlitePool, repo: Repo) -> somehow::Result<()> {
     // This can take a while for larger repos. Running it via spawn_blocking
     // keeps it from blocking the entire tokio worker.
     let (refs, new) = tokio::task::spawn_blocking(move || {
-        get_all_refs_and_new_commits_from_repo(&repo.0.to_thread_local(), &old)
+        get_all_refs_and_new_commits_from_repo(config, &repo.0.to_thread_local(), &old)
     })
     .await??;
     if new.is_empty() {
@@ -278,16 +429,18 @@ pub async fn inner(db: &SqlitePool, repo: Repo) -> somehow::Result<()> {
     // commit and so on).
     insert_new_commits(conn, &thread_local_repo, &new).await?;
     insert_new_commit_edges(conn, &thread_local_repo, &new).await?;
+    fill_in_edge_positions(conn, &thread_local_repo).await?;
     if repo_is_new {
         mark_all_commits_as_old(conn).await?;
     }
 
-    update_refs(conn, refs).await?;
-    if repo_is_new {
+    update_refs(conn, config, refs).await?;
+    if repo_is_new && config.repo_track.is_empty() {
         track_main_branch(conn, &thread_local_repo).await?;
     }
     update_commit_tracked_status(conn).await?;
     debug!("Updated tracked refs");
+    update_unreachable_commits(conn, config).await?;
 
     tx.commit().await?;
     if repo_is_new {
@@ -296,9 +449,9 @@ pub async fn inner(db: &SqlitePool, repo: Repo) -> somehow::Result<()> {
     Ok(())
 }
 
-pub(super) async fn update(db: &SqlitePool, repo: Repo) {
+pub(super) async fn update(config: &'static ServerConfig, db: &SqlitePool, repo: Repo) {
     debug!("Updating repo data");
-    if let Err(e) = inner(db, repo).await {
+    if let Err(e) = inner(config, db, repo).await {
         warn!("Error updating repo data:\n{e:?}");
     }
 }
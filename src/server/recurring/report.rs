@@ -0,0 +1,103 @@
+//! Add the commits needed for reports to the queue.
+//!
+//! A report compares the tip of a ref against its merge base with the ref it
+//! was branched off from. Both are added to the queue unless they already have
+//! runs.
+//!
+//! Finding the merge base walks the history, so it is only done again once a
+//! ref has moved.
+
+use std::collections::HashMap;
+
+use log::{debug, info, warn};
+use sqlx::SqlitePool;
+use time::OffsetDateTime;
+
+use crate::{
+    config::ServerConfig,
+    primitive::QueueKind,
+    server::{
+        report::{self, Baseline},
+        Repo,
+    },
+    somehow,
+};
+
+use super::repo::matches_any;
+
+/// The hash each report ref was at when it was last processed, by ref name.
+pub(super) type Processed = HashMap<String, String>;
+
+async fn inner(
+    config: &ServerConfig,
+    db: &SqlitePool,
+    repo: Repo,
+    processed: &mut Processed,
+) -> somehow::Result<()> {
+    if config.reports_refs.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = db.acquire().await?;
+
+    let refs = sqlx::query!("SELECT name, hash FROM refs ORDER BY name ASC")
+        .fetch_all(&mut *conn)
+        .await?;
+    processed.retain(|name, _| refs.iter().any(|r| r.name == *name));
+
+    let now = OffsetDateTime::now_utc();
+    for r#ref in refs {
+        if !matches_any(&config.reports_refs, &r#ref.name) {
+            continue;
+        }
+        if processed.get(&r#ref.name) == Some(&r#ref.hash) {
+            continue;
+        }
+
+        let Some(Baseline { hash: base, .. }) =
+            report::baseline(config, &mut conn, repo.clone(), &r#ref.name, &r#ref.hash).await?
+        else {
+            debug!("Found no baseline for report on {}", r#ref.name);
+            processed.insert(r#ref.name, r#ref.hash);
+            continue;
+        };
+
+        for hash in [&r#ref.hash, &base] {
+            let added = sqlx::query!(
+                "
+                INSERT OR IGNORE INTO queue (hash, date, priority, kind)
+                SELECT ?, ?, ?, ?
+                WHERE NOT EXISTS (SELECT * FROM runs WHERE hash = ?)
+                ",
+                hash,
+                now,
+                config.reports_priority,
+                QueueKind::Report,
+                hash,
+            )
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+            if added > 0 {
+                info!("Added {hash} to the queue for report on {}", r#ref.name);
+            }
+        }
+
+        processed.insert(r#ref.name, r#ref.hash);
+    }
+
+    Ok(())
+}
+
+pub(super) async fn update(
+    config: &ServerConfig,
+    db: &SqlitePool,
+    repo: Repo,
+    processed: &mut Processed,
+) {
+    debug!("Updating reports");
+    if let Err(e) = inner(config, db, repo, processed).await {
+        warn!("Error updating reports:\n{e:?}");
+    }
+}
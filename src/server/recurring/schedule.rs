@@ -0,0 +1,88 @@
+//! Add the tips of all tracked refs to the queue on a schedule.
+//!
+//! This helps detect changes in metrics that are caused by the environment
+//! (e.g. a noisy worker) instead of the code.
+
+use log::{debug, info, warn};
+use sqlx::{Acquire, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{config::ServerConfig, primitive::QueueKind, somehow};
+
+async fn inner(config: &ServerConfig, db: &SqlitePool) -> somehow::Result<()> {
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    let now = OffsetDateTime::now_utc();
+    for (name, schedule) in &config.schedules {
+        let last = sqlx::query_scalar!(
+            r#"SELECT last AS "last: OffsetDateTime" FROM schedules WHERE name = ?"#,
+            name,
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        // New schedules only start counting from now on, otherwise they'd
+        // trigger immediately.
+        let Some(last) = last else {
+            sqlx::query!(
+                "INSERT INTO schedules (name, last) VALUES (?, ?)",
+                name,
+                now,
+            )
+            .execute(&mut *conn)
+            .await?;
+            debug!("Started schedule {name} ({})", schedule.cron);
+            continue;
+        };
+
+        let due = schedule
+            .cron
+            .next_after(last)
+            .is_some_and(|next| next <= now);
+        if !due {
+            continue;
+        }
+
+        sqlx::query!("UPDATE schedules SET last = ? WHERE name = ?", now, name)
+            .execute(&mut *conn)
+            .await?;
+
+        let tips = sqlx::query_scalar!("SELECT DISTINCT hash FROM refs WHERE tracked")
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for hash in tips {
+            let added = sqlx::query!(
+                "
+                INSERT OR IGNORE INTO queue (hash, date, priority, kind)
+                VALUES (?, ?, ?, ?)
+                ",
+                hash,
+                now,
+                schedule.priority,
+                QueueKind::Scheduled,
+            )
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+            if added > 0 {
+                info!(
+                    "Schedule {name} ({}) added {hash} to the queue",
+                    schedule.cron
+                );
+            }
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub(super) async fn update(config: &ServerConfig, db: &SqlitePool) {
+    debug!("Updating schedules");
+    if let Err(e) = inner(config, db).await {
+        warn!("Error updating schedules:\n{e:?}");
+    }
+}
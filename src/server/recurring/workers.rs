@@ -0,0 +1,23 @@
+//! Notice workers that stopped reporting in.
+//!
+//! Workers are usually cleaned up whenever another worker reports in, which
+//! doesn't happen if the last worker disconnects.
+
+use log::{debug, warn};
+
+use crate::{
+    server::{notify, Server},
+    somehow,
+};
+
+async fn inner(server: &Server) -> somehow::Result<()> {
+    let disconnected = server.workers.lock().unwrap().clean().take_disconnected();
+    notify::workers_disconnected(server.config, &server.db, &disconnected).await
+}
+
+pub(super) async fn update(server: &Server) {
+    debug!("Updating workers");
+    if let Err(e) = inner(server).await {
+        warn!("Error updating workers:\n{e:?}");
+    }
+}
@@ -0,0 +1,43 @@
+//! Find commits by the names users give them.
+
+use sqlx::SqlitePool;
+
+use crate::somehow;
+
+/// Resolve a ref name, a ref name without its `refs/...` prefix, or an
+/// unambiguous prefix of a commit hash.
+pub async fn resolve(db: &SqlitePool, name: &str) -> somehow::Result<Option<String>> {
+    let hash = sqlx::query_scalar!(
+        "
+        SELECT hash FROM refs
+        WHERE name = ?1
+        OR name = 'refs/heads/' || ?1
+        OR name = 'refs/tags/' || ?1
+        OR name = 'refs/remotes/' || ?1
+        ORDER BY name = ?1 DESC, name ASC
+        LIMIT 1
+        ",
+        name,
+    )
+    .fetch_optional(db)
+    .await?;
+    if hash.is_some() {
+        return Ok(hash);
+    }
+
+    // Don't let a lone character match half the repo
+    if name.len() < 4 || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+
+    let hashes = sqlx::query_scalar!(
+        "SELECT hash FROM commits WHERE hash LIKE ? || '%' LIMIT 2",
+        name,
+    )
+    .fetch_all(db)
+    .await?;
+    match &hashes[..] {
+        [hash] => Ok(Some(hash.clone())),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,191 @@
+//! Find the commit a ref should be compared against in a report.
+
+use std::collections::{BinaryHeap, HashMap};
+
+use gix::{prelude::ObjectIdExt, ObjectId, Repository};
+use sqlx::SqliteConnection;
+
+use crate::{config::ServerConfig, somehow};
+
+use super::Repo;
+
+/// The merge base of a ref with the ref it is compared against.
+pub struct Baseline {
+    pub r#ref: String,
+    pub hash: String,
+}
+
+fn commit_time(repo: &Repository, id: ObjectId) -> somehow::Result<i64> {
+    let commit = id.attach(repo).object()?.try_into_commit()?;
+    Ok(commit.time()?.seconds)
+}
+
+/// The newest common ancestor of two commits, like `git merge-base`.
+///
+/// Both histories are walked newest commit first, marking each commit with the
+/// side it was reached from. The first commit reached from both sides is the
+/// most recently committed merge base.
+fn merge_base(
+    repo: &Repository,
+    a: ObjectId,
+    b: ObjectId,
+) -> somehow::Result<Option<(ObjectId, i64)>> {
+    const FROM_A: u8 = 0b01;
+    const FROM_B: u8 = 0b10;
+
+    let mut flags = HashMap::from([(a, FROM_A)]);
+    *flags.entry(b).or_default() |= FROM_B;
+
+    let mut queue = BinaryHeap::from([(commit_time(repo, a)?, a)]);
+    if b != a {
+        queue.push((commit_time(repo, b)?, b));
+    }
+
+    while let Some((time, id)) = queue.pop() {
+        let side = flags[&id];
+        if side == FROM_A | FROM_B {
+            return Ok(Some((id, time)));
+        }
+
+        let commit = id.attach(repo).object()?.try_into_commit()?;
+        for parent in commit.parent_ids() {
+            let parent = parent.detach();
+            let parent_side = flags.entry(parent).or_default();
+            if *parent_side & side == side {
+                continue;
+            }
+            *parent_side |= side;
+            queue.push((commit_time(repo, parent)?, parent));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the baseline of a ref whose tip is at `hash`.
+///
+/// Compares against the configured base ref if there is one. Otherwise, the
+/// tracked branch with the most recent merge base is used, since that's
+/// usually the one the ref was branched off from.
+pub async fn baseline(
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+    repo: Repo,
+    name: &str,
+    hash: &str,
+) -> somehow::Result<Option<Baseline>> {
+    let candidates = if let Some(base) = &config.reports_base {
+        sqlx::query!("SELECT name, hash FROM refs WHERE name = ?", base)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|r| (r.name, r.hash))
+            .collect::<Vec<_>>()
+    } else {
+        sqlx::query!(
+            "
+            SELECT name, hash FROM refs
+            WHERE tracked AND name != ? AND name NOT LIKE 'refs/tags/%'
+            ORDER BY name ASC
+            ",
+            name,
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| (r.name, r.hash))
+        .collect::<Vec<_>>()
+    };
+
+    let hash = ObjectId::from_hex(hash.as_bytes())?;
+
+    // Walking the history can take a while for larger repos. Running it via
+    // spawn_blocking keeps it from blocking the entire tokio worker.
+    tokio::task::spawn_blocking(move || {
+        let repo = repo.0.to_thread_local();
+        let mut best: Option<(Baseline, i64)> = None;
+        for (base_name, base_hash) in candidates {
+            let base_hash = ObjectId::from_hex(base_hash.as_bytes())?;
+            let Some((merge_base, time)) = merge_base(&repo, base_hash, hash)? else {
+                continue;
+            };
+            if best.as_ref().is_some_and(|(_, best)| *best >= time) {
+                continue;
+            }
+            let baseline = Baseline {
+                r#ref: base_name,
+                hash: merge_base.to_string(),
+            };
+            best = Some((baseline, time));
+        }
+        Ok(best.map(|(baseline, _)| baseline))
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use super::*;
+
+    fn git(dir: &Path, time: i64, args: &[&str]) -> String {
+        let date = format!("@{time} +0000");
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Commit one second after the previous commit, returning the new hash.
+    fn commit(dir: &Path, time: &mut i64, args: &[&str]) -> ObjectId {
+        *time += 1;
+        git(dir, *time, args);
+        let hash = git(dir, *time, &["rev-parse", "HEAD"]);
+        ObjectId::from_hex(hash.trim().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn finds_newest_merge_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let mut t = 1_700_000_000;
+        let empty = ["commit", "--allow-empty", "-q", "-m", "commit"];
+
+        git(dir, t, &["init", "-q", "-b", "main"]);
+        let a = commit(dir, &mut t, &empty);
+        let b = commit(dir, &mut t, &empty);
+        git(dir, t, &["checkout", "-q", "-b", "feature"]);
+        let d = commit(dir, &mut t, &empty);
+        git(dir, t, &["checkout", "-q", "main"]);
+        let c = commit(dir, &mut t, &empty);
+        git(dir, t, &["checkout", "-q", "feature"]);
+        let m = commit(
+            dir,
+            &mut t,
+            &["merge", "-q", "--no-ff", "-m", "merge", "main"],
+        );
+        let e = commit(dir, &mut t, &empty);
+        git(dir, t, &["checkout", "-q", "--orphan", "unrelated"]);
+        let x = commit(dir, &mut t, &empty);
+
+        let repo = gix::open(dir).unwrap();
+        let base = |a, b| merge_base(&repo, a, b).unwrap().map(|(id, _)| id);
+
+        assert_eq!(base(a, a), Some(a));
+        assert_eq!(base(a, b), Some(a));
+        assert_eq!(base(b, a), Some(a));
+        assert_eq!(base(c, d), Some(b));
+        assert_eq!(base(d, c), Some(b));
+        assert_eq!(base(c, m), Some(c));
+        assert_eq!(base(c, e), Some(c));
+        assert_eq!(base(d, e), Some(d));
+        assert_eq!(base(e, x), None);
+    }
+}
@@ -0,0 +1,404 @@
+//! Post commit statuses to git forges when runs finish.
+//!
+//! Each configured status target gets a delivery per finished run. Deliveries
+//! are stored in the db so failed ones can be retried, even across restarts.
+//!
+//! The url and body of a target are templates. These placeholders are
+//! replaced, percent-encoded in the url and json-escaped in the body:
+//!
+//! - `{hash}`: Hash of the commit the run belongs to
+//! - `{run_id}`: Id of the run
+//! - `{run_path}`: Absolute path of the run's page, without scheme and host
+//! - `{repo_name}`: Name of the repo
+//! - `{state}`: One of the target's states, e.g. `success`
+//! - `{description}`: Short summary, e.g. `benchmarks: 2 regressions`
+
+use log::{debug, warn};
+use reqwest::{header, Client};
+use sqlx::{SqliteConnection, SqlitePool};
+use time::OffsetDateTime;
+
+use crate::{
+    config::{ServerConfig, ServerStatus},
+    server::{
+        changes,
+        delivery::{self, Pending, Queue},
+        web::paths::PathRunById,
+        Deliveries,
+    },
+    somehow,
+};
+
+/// Schedule a status delivery to every target for a freshly saved run.
+pub async fn enqueue(
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+    run_id: &str,
+) -> somehow::Result<()> {
+    let now = OffsetDateTime::now_utc();
+    for target in &config.statuses {
+        sqlx::query!(
+            "
+            INSERT OR IGNORE INTO status_deliveries (id, target, next_attempt)
+            VALUES (?, ?, ?)
+            ",
+            run_id,
+            target.name,
+            now,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+struct Summary {
+    state: String,
+    description: String,
+}
+
+/// Compare a run against its baseline and sum the result up in a few words.
+async fn summarize(
+    target: &ServerStatus,
+    db: &SqlitePool,
+    hash: &str,
+    run_id: &str,
+    exit_code: i64,
+) -> somehow::Result<Summary> {
+    if exit_code != 0 {
+        return Ok(Summary {
+            state: target.state_error.clone(),
+            description: "benchmarks: run failed".to_string(),
+        });
+    }
+
+    let mut conn = db.acquire().await?;
+    let Some(changes) = changes::since_baseline(&mut conn, hash, run_id, target.threshold).await?
+    else {
+        return Ok(Summary {
+            state: target.state_success.clone(),
+            description: "benchmarks: nothing to compare against".to_string(),
+        });
+    };
+
+    let state = if changes.regressions.is_empty() {
+        target.state_success.clone()
+    } else {
+        target.state_failure.clone()
+    };
+
+    Ok(Summary {
+        state,
+        description: format!("benchmarks: {}", changes.describe()),
+    })
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut result = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    result
+}
+
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("strings can always be serialized");
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Replace `{name}` placeholders. Braces that don't form a known placeholder
+/// are left alone so json bodies don't need any escaping.
+fn render(template: &str, vars: &[(&str, &str)], escape: fn(&str) -> String) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest[1..]
+            .find('}')
+            .and_then(|end| vars.iter().find(|(k, _)| *k == &rest[1..end + 1]));
+        match value {
+            Some((name, value)) => {
+                result.push_str(&escape(value));
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+async fn send(
+    config: &ServerConfig,
+    target: &ServerStatus,
+    client: &Client,
+    db: &SqlitePool,
+    run_id: &str,
+) -> somehow::Result<()> {
+    let run = sqlx::query!("SELECT hash, exit_code FROM runs WHERE id = ?", run_id)
+        .fetch_one(db)
+        .await?;
+    let summary = summarize(target, db, &run.hash, run_id, run.exit_code).await?;
+
+    let run_path = format!(
+        "{}{}",
+        config.web_base,
+        PathRunById {
+            id: run_id.to_string()
+        }
+    );
+    let vars = [
+        ("hash", run.hash.as_str()),
+        ("run_id", run_id),
+        ("run_path", &run_path),
+        ("repo_name", &config.repo_name),
+        ("state", &summary.state),
+        ("description", &summary.description),
+    ];
+
+    let url = render(&target.url, &vars, percent_encode);
+    let mut request = client
+        .request(target.method.clone(), url)
+        .headers(target.headers.clone());
+    if let Some(body) = &target.body {
+        if !target.headers.contains_key(header::CONTENT_TYPE) {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+        }
+        request = request.body(render(body, &vars, json_escape));
+    }
+
+    delivery::check_response(request.send().await?).await?;
+
+    debug!(
+        "Posted {:?} for run {run_id} to {}",
+        summary.description, target.name
+    );
+    Ok(())
+}
+
+struct Statuses<'a> {
+    config: &'a ServerConfig,
+    db: &'a SqlitePool,
+}
+
+impl Queue for Statuses<'_> {
+    /// The id of the run whose status is delivered.
+    type Item = String;
+    type Target = ServerStatus;
+
+    async fn due(&self, now: OffsetDateTime) -> somehow::Result<Vec<Pending<String>>> {
+        let due = sqlx::query!(
+            "
+            SELECT id, target, attempts FROM status_deliveries
+            WHERE unixepoch(next_attempt) <= unixepoch(?)
+            ORDER BY unixepoch(next_attempt) ASC, id ASC, target ASC
+            ",
+            now,
+        )
+        .fetch_all(self.db)
+        .await?
+        .into_iter()
+        .map(|r| Pending {
+            target: r.target,
+            attempts: r.attempts,
+            item: r.id,
+        })
+        .collect();
+        Ok(due)
+    }
+
+    fn target(&self, name: &str) -> Option<&ServerStatus> {
+        self.config.statuses.iter().find(|t| t.name == name)
+    }
+
+    fn retries(target: &ServerStatus) -> u32 {
+        target.retries
+    }
+
+    fn describe(run_id: &String) -> String {
+        format!("status for run {run_id}")
+    }
+
+    async fn send(
+        &self,
+        target: &ServerStatus,
+        client: &Client,
+        run_id: &String,
+    ) -> somehow::Result<()> {
+        send(self.config, target, client, self.db, run_id).await
+    }
+
+    async fn retry_later(
+        &self,
+        pending: &Pending<String>,
+        attempts: u32,
+        next_attempt: OffsetDateTime,
+    ) -> somehow::Result<()> {
+        sqlx::query!(
+            "
+            UPDATE status_deliveries
+            SET attempts = ?, next_attempt = ?
+            WHERE id = ? AND target = ?
+            ",
+            attempts,
+            next_attempt,
+            pending.item,
+            pending.target,
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, pending: &Pending<String>) -> somehow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM status_deliveries WHERE id = ? AND target = ?",
+            pending.item,
+            pending.target,
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+}
+
+async fn inner(config: &ServerConfig, db: &SqlitePool) -> somehow::Result<()> {
+    delivery::process(&Statuses { config, db }).await
+}
+
+/// Post all statuses that are due.
+pub async fn deliver(config: &ServerConfig, db: SqlitePool, deliveries: Deliveries) {
+    let _guard = deliveries.status.lock().await;
+    debug!("Delivering statuses");
+    if let Err(e) = inner(config, &db).await {
+        warn!("Error delivering statuses:\n{e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use axum::http::{Method, StatusCode};
+    use clap::Parser;
+    use serde_json::{json, Value};
+    use sqlx::SqlitePool;
+    use time::OffsetDateTime;
+
+    use crate::{
+        args::{Args, NAME},
+        config::Config,
+        server::{
+            delivery,
+            testing::{db_with_run, forge, HASH, RUN_ID},
+        },
+    };
+
+    fn config(dir: &Path, url: &str) -> Config {
+        let path = dir.join("config.toml");
+        let config = format!(
+            r#"
+            [server.web]
+            base = "/bench/"
+
+            [server.repo]
+            name = "my repo"
+
+            [server.statuses.forge]
+            url = "{url}/repos/{{repo_name}}/statuses/{{hash}}"
+            headers = {{ Authorization = "token secret" }}
+            body = '{{"state": "{{state}}", "target_url": "https://example.com{{run_path}}", "description": "{{description}}"}}'
+            retries = 3
+            "#
+        );
+        fs::write(&path, config).unwrap();
+
+        let args = Args::parse_from([NAME, "--config", path.to_str().unwrap(), "worker"]);
+        Config::load(&args).unwrap()
+    }
+
+    async fn deliveries(db: &SqlitePool) -> Vec<(String, i64, OffsetDateTime)> {
+        sqlx::query_as("SELECT target, attempts, next_attempt FROM status_deliveries")
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_rendered_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = forge(StatusCode::CREATED).await;
+        let config = config(dir.path(), &url);
+        let db = db_with_run(dir.path(), 1).await;
+
+        let mut conn = db.acquire().await.unwrap();
+        super::enqueue(&config.server, &mut conn, RUN_ID)
+            .await
+            .unwrap();
+        drop(conn);
+        assert_eq!(deliveries(&db).await.len(), 1);
+
+        super::inner(&config.server, &db).await.unwrap();
+
+        // Successful deliveries are done with
+        assert!(deliveries(&db).await.is_empty());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(
+            request.uri.path(),
+            format!("/repos/my%20repo/statuses/{HASH}")
+        );
+        assert_eq!(request.headers["authorization"], "token secret");
+        assert_eq!(request.headers["content-type"], "application/json");
+
+        let body = serde_json::from_str::<Value>(&request.body).unwrap();
+        let expected = json!({
+            "state": "error",
+            "target_url": format!("https://example.com/bench/run/{RUN_ID}"),
+            "description": "benchmarks: run failed",
+        });
+        assert_eq!(body, expected);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_delivery() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = forge(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let config = config(dir.path(), &url);
+        let db = db_with_run(dir.path(), 1).await;
+
+        let mut conn = db.acquire().await.unwrap();
+        super::enqueue(&config.server, &mut conn, RUN_ID)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let before = OffsetDateTime::now_utc();
+        super::inner(&config.server, &db).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let deliveries = deliveries(&db).await;
+        assert_eq!(deliveries.len(), 1);
+        let (target, attempts, next_attempt) = &deliveries[0];
+        assert_eq!(target, "forge");
+        assert_eq!(*attempts, 1);
+        assert!(*next_attempt >= before + delivery::backoff(0));
+
+        // Not due yet, so nothing is sent
+        super::inner(&config.server, &db).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,98 @@
+//! Fixtures shared by the server's tests.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    Router,
+};
+use sqlx::SqlitePool;
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
+
+pub const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+pub const RUN_ID: &str = "r-test";
+
+/// Open a db in `dir` containing the commit [`HASH`] and a run [`RUN_ID`]
+/// of it that exited with `exit_code`.
+pub async fn db_with_run(dir: &Path, exit_code: i64) -> SqlitePool {
+    let db = super::open_db(&dir.join("db.sqlite")).await.unwrap();
+    let now = OffsetDateTime::now_utc();
+
+    sqlx::query(
+        "
+        INSERT INTO commits (hash, author, author_date, committer, committer_date, message)
+        VALUES (?, 'author', ?, 'committer', ?, 'message')
+        ",
+    )
+    .bind(HASH)
+    .bind(now)
+    .bind(now)
+    .execute(&db)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "
+        INSERT INTO runs (id, hash, bench_method, worker_name, start, end, exit_code)
+        VALUES (?, ?, 'internal', 'worker', ?, ?, ?)
+        ",
+    )
+    .bind(RUN_ID)
+    .bind(HASH)
+    .bind(now)
+    .bind(now)
+    .bind(exit_code)
+    .execute(&db)
+    .await
+    .unwrap();
+
+    db
+}
+
+pub struct Request {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+#[derive(Clone)]
+struct Forge {
+    status: StatusCode,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+async fn record(
+    State(forge): State<Forge>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    forge.requests.lock().unwrap().push(Request {
+        method,
+        uri,
+        headers,
+        body,
+    });
+    forge.status
+}
+
+/// Serve a fake forge or webhook responding to everything with `status`.
+pub async fn forge(status: StatusCode) -> (String, Arc<Mutex<Vec<Request>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec![]));
+    let forge = Forge {
+        status,
+        requests: requests.clone(),
+    };
+    let app = Router::new().fallback(record).with_state(forge);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, requests)
+}
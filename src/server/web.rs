@@ -1,5 +1,5 @@
 mod admin;
-mod api;
+pub mod api;
 mod components;
 mod page;
 mod pages;
@@ -12,7 +12,7 @@ use axum_extra::routing::RouterExt;
 use log::info;
 use tokio::net::TcpListener;
 
-use crate::somehow;
+use crate::{config::ServerConfig, somehow};
 
 use self::{
     admin::{
@@ -22,16 +22,30 @@ use self::{
         },
         refs::{post_admin_refs_track, post_admin_refs_untrack, post_admin_repo_update},
     },
-    api::worker::{
-        get_api_worker_bench_repo_by_hash_tree_tar_gz, get_api_worker_repo_by_hash_tree_tar_gz,
-        post_api_worker_status,
+    api::{
+        hooks::post_api_hooks_push,
+        openapi::get_api_openapi_json,
+        v1::{
+            delete_api_v1_queue_by_hash, get_api_v1_commit_by_hash, get_api_v1_export,
+            get_api_v1_metrics, get_api_v1_queue, get_api_v1_run_by_id, get_api_v1_runs,
+            patch_api_v1_queue_by_hash, post_api_v1_queue,
+        },
+        worker::{
+            get_api_worker_bench_repo_by_hash_tree_tar_gz, get_api_worker_projects,
+            get_api_worker_repo_by_hash_tree_tar_gz, post_api_worker_artifact,
+            post_api_worker_status,
+        },
     },
     pages::{
         commit::get_commit_by_hash,
         graph::{get_graph, get_graph_commits, get_graph_measurements, get_graph_metrics},
         index::get_index,
+        orphans::get_orphans,
+        projects::get_projects,
         queue::{get_queue, get_queue_delete, get_queue_inner},
-        run::get_run_by_id,
+        range::get_range,
+        report::get_report,
+        run::{get_run_artifact, get_run_by_id},
         test::get_test,
         worker::get_worker_by_name,
     },
@@ -39,14 +53,26 @@ use self::{
 
 use super::Server;
 
-pub async fn run(server: Server) -> somehow::Result<()> {
+fn router(server: Server) -> Router {
     // TODO Add text body to body-less status codes
 
     let post_api_worker_status = Router::new()
         .typed_post(post_api_worker_status)
         .layer(DefaultBodyLimit::max(server.config.worker_upload));
 
-    let app = Router::new()
+    let post_api_worker_artifact = Router::new()
+        .typed_post(post_api_worker_artifact)
+        .layer(DefaultBodyLimit::max(server.config.worker_artifact_upload));
+
+    Router::new()
+        .typed_delete(delete_api_v1_queue_by_hash)
+        .typed_get(get_api_openapi_json)
+        .typed_get(get_api_v1_commit_by_hash)
+        .typed_get(get_api_v1_export)
+        .typed_get(get_api_v1_metrics)
+        .typed_get(get_api_v1_queue)
+        .typed_get(get_api_v1_run_by_id)
+        .typed_get(get_api_v1_runs)
         .typed_get(get_api_worker_bench_repo_by_hash_tree_tar_gz)
         .typed_get(get_api_worker_repo_by_hash_tree_tar_gz)
         .typed_get(get_commit_by_hash)
@@ -55,9 +81,13 @@ pub async fn run(server: Server) -> somehow::Result<()> {
         .typed_get(get_graph_measurements)
         .typed_get(get_graph_metrics)
         .typed_get(get_index)
+        .typed_get(get_orphans)
         .typed_get(get_queue)
         .typed_get(get_queue_delete)
         .typed_get(get_queue_inner)
+        .typed_get(get_range)
+        .typed_get(get_report)
+        .typed_get(get_run_artifact)
         .typed_get(get_run_by_id)
         .typed_get(get_test)
         .typed_get(get_worker_by_name)
@@ -69,11 +99,39 @@ pub async fn run(server: Server) -> somehow::Result<()> {
         .typed_post(post_admin_refs_track)
         .typed_post(post_admin_refs_untrack)
         .typed_post(post_admin_repo_update)
+        .typed_patch(patch_api_v1_queue_by_hash)
+        .typed_post(post_api_hooks_push)
+        .typed_post(post_api_v1_queue)
+        .merge(post_api_worker_artifact)
         .merge(post_api_worker_status)
         .fallback(get(r#static::static_handler))
-        .with_state(server.clone());
+        .with_state(server)
+}
+
+/// Each project's pages are served below `/p/<name>/`, next to a list of all
+/// projects and the endpoint workers use to discover them.
+fn projects_router(config: &'static ServerConfig, servers: &[Server]) -> Router {
+    let mut app = Router::new()
+        .typed_get(get_api_worker_projects)
+        .typed_get(get_projects)
+        .fallback(get(r#static::static_handler))
+        .with_state(config);
+
+    for (project, server) in config.projects.iter().zip(servers) {
+        app = app.nest(&format!("/p/{}/", project.name), router(server.clone()));
+    }
+
+    app
+}
+
+pub async fn run(config: &'static ServerConfig, servers: &[Server]) -> somehow::Result<()> {
+    let app = if config.projects.is_empty() {
+        router(servers[0].clone())
+    } else {
+        projects_router(config, servers)
+    };
 
-    let addr = &server.config.web_address;
+    let addr = &config.web_address;
     info!("Launching web server at http://{}", addr);
     let listener = TcpListener::bind(addr).await?;
     axum::serve(listener, app).await?;
@@ -1,59 +1,297 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    hash::Hash,
+};
+
 use axum::{
     extract::State,
-    response::{IntoResponse, Redirect},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
     Form,
 };
 use log::info;
 use serde::Deserialize;
-use sqlx::SqlitePool;
-use time::OffsetDateTime;
+use sqlx::{Acquire, SqliteConnection, SqlitePool};
+use time::{OffsetDateTime, UtcOffset};
 
 use crate::{
     config::ServerConfig,
     primitive::Reachable,
-    server::web::{
-        paths::{
-            PathAdminQueueAdd, PathAdminQueueAddBatch, PathAdminQueueDecrease,
-            PathAdminQueueDelete, PathAdminQueueIncrease, PathQueue,
+    server::{
+        web::{
+            paths::{
+                PathAdminQueueAdd, PathAdminQueueAddBatch, PathAdminQueueDecrease,
+                PathAdminQueueDelete, PathAdminQueueIncrease, PathQueue,
+            },
+            server_config_ext::ServerConfigExt,
         },
-        server_config_ext::ServerConfigExt,
+        BenchRepo,
     },
     somehow,
 };
 
+/// The bench repo revision and benchmarks a queue entry should be run with.
+///
+/// `None` means the bench repo's HEAD and all benchmarks respectively.
+pub struct BenchTarget {
+    pub bench_hash: Option<String>,
+    pub benchmarks: Option<String>,
+}
+
+impl BenchTarget {
+    pub fn resolve(
+        bench_repo: &Option<BenchRepo>,
+        bench_hash: &str,
+        benchmarks: &str,
+    ) -> Result<Self, &'static str> {
+        let bench_hash = match bench_hash.trim() {
+            "" => None,
+            rev => {
+                let Some(bench_repo) = bench_repo else {
+                    return Err("no bench repo");
+                };
+                let repo = bench_repo.0.to_thread_local();
+                let Ok(id) = repo.rev_parse_single(rev) else {
+                    return Err("unknown bench repo revision");
+                };
+                Some(id.to_string())
+            }
+        };
+
+        let benchmarks = benchmarks
+            .split(',')
+            .map(|b| b.trim())
+            .filter(|b| !b.is_empty())
+            .collect::<Vec<_>>();
+        let benchmarks = (!benchmarks.is_empty()).then(|| benchmarks.join(","));
+
+        Ok(Self {
+            bench_hash,
+            benchmarks,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut result = String::new();
+        if let Some(bench_hash) = &self.bench_hash {
+            result.push_str(&format!(", bench repo at {bench_hash}"));
+        }
+        if let Some(benchmarks) = &self.benchmarks {
+            result.push_str(&format!(", benchmarks {benchmarks}"));
+        }
+        result
+    }
+}
+
 #[derive(Deserialize)]
 pub struct FormAdminQueueAdd {
     hash: String,
     #[serde(default)]
     priority: i32,
+    #[serde(default)]
+    bench_hash: String,
+    #[serde(default)]
+    benchmarks: String,
 }
 
 pub async fn post_admin_queue_add(
     _path: PathAdminQueueAdd,
     State(config): State<&'static ServerConfig>,
     State(db): State<SqlitePool>,
+    State(bench_repo): State<Option<BenchRepo>>,
     Form(form): Form<FormAdminQueueAdd>,
-) -> somehow::Result<impl IntoResponse> {
+) -> somehow::Result<Response> {
+    let target = match BenchTarget::resolve(&bench_repo, &form.bench_hash, &form.benchmarks) {
+        Ok(target) => target,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+
+    // Explicitly adding a commit again overwrites what it should be run with.
     let date = OffsetDateTime::now_utc();
     sqlx::query!(
         "
-        INSERT INTO queue (hash, date, priority) VALUES (?, ?, ?)
+        INSERT INTO queue (hash, date, priority, bench_hash, benchmarks)
+        VALUES (?, ?, ?, ?, ?)
         ON CONFLICT (hash) DO UPDATE
-        SET priority = excluded.priority WHERE priority < excluded.priority
+        SET
+            priority = max(priority, excluded.priority),
+            bench_hash = excluded.bench_hash,
+            benchmarks = excluded.benchmarks
         ",
         form.hash,
         date,
         form.priority,
+        target.bench_hash,
+        target.benchmarks,
     )
     .execute(&db)
     .await?;
 
     info!(
-        "Admin added {} to queue with priority {}",
-        form.hash, form.priority,
+        "Admin added {} to queue with priority {}{}",
+        form.hash,
+        form.priority,
+        target.describe(),
     );
 
-    Ok(Redirect::to(config.path(PathQueue {}).as_ref()))
+    Ok(Redirect::to(config.path(PathQueue {}).as_ref()).into_response())
+}
+
+/// How to pick the commits of a batch.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStrategy {
+    /// The most recent commits.
+    #[default]
+    Recent,
+    /// Commits evenly spaced across the entire history.
+    Even,
+    /// The commits in the middle of the largest gaps between commits that
+    /// already have runs, repeatedly subdividing the history.
+    Gaps,
+    /// The most recent commit of each day.
+    Daily,
+    /// The most recent commit of each week.
+    Weekly,
+}
+
+struct BatchCommit {
+    hash: String,
+    date: OffsetDateTime,
+    /// The commit already has runs or is in the queue.
+    covered: bool,
+}
+
+/// Tracked commits, most recent commit first.
+async fn get_batch_commits(conn: &mut SqliteConnection) -> somehow::Result<Vec<BatchCommit>> {
+    let commits = sqlx::query!(
+        r#"
+        SELECT
+            hash,
+            committer_date AS "committer_date: OffsetDateTime",
+            (
+                EXISTS (SELECT * FROM runs WHERE runs.hash = commits.hash)
+                OR EXISTS (SELECT * FROM queue WHERE queue.hash = commits.hash)
+            ) AS "covered!: bool"
+        FROM commits
+        WHERE reachable = ?
+        ORDER BY unixepoch(committer_date) DESC, hash ASC
+        "#,
+        Reachable::FromTrackedRef,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|r| BatchCommit {
+        hash: r.hash,
+        date: r.committer_date,
+        covered: r.covered,
+    })
+    .collect();
+
+    Ok(commits)
+}
+
+fn pick_recent(commits: &[BatchCommit], amount: usize) -> Vec<usize> {
+    (0..commits.len())
+        .filter(|i| !commits[*i].covered)
+        .take(amount)
+        .collect()
+}
+
+fn pick_even(commits: &[BatchCommit], amount: usize) -> Vec<usize> {
+    let candidates = (0..commits.len())
+        .filter(|i| !commits[*i].covered)
+        .collect::<Vec<_>>();
+
+    if candidates.len() <= amount {
+        return candidates;
+    }
+
+    // Pick from the middle of each of the equally sized sections.
+    (0..amount)
+        .map(|i| candidates[(2 * i + 1) * candidates.len() / (2 * amount)])
+        .collect()
+}
+
+fn pick_gaps(commits: &[BatchCommit], amount: usize) -> Vec<usize> {
+    let mut picked = vec![];
+    if commits.is_empty() {
+        return picked;
+    }
+
+    // Both ends of the history should be measured before subdividing it.
+    let last = commits.len() - 1;
+    for i in [0, last] {
+        if !commits[i].covered && !picked.contains(&i) {
+            picked.push(i);
+        }
+    }
+
+    let mut covered = (0..commits.len())
+        .filter(|i| commits[*i].covered || picked.contains(i))
+        .collect::<Vec<_>>();
+    covered.sort_unstable();
+
+    // Largest gap first, ties broken in favour of more recent commits
+    let mut gaps = covered
+        .windows(2)
+        .map(|w| (w[1] - w[0], Reverse(w[0]), w[1]))
+        .filter(|(len, _, _)| *len > 1)
+        .collect::<BinaryHeap<_>>();
+
+    picked.truncate(amount);
+    while picked.len() < amount {
+        let Some((_, Reverse(lo), hi)) = gaps.pop() else {
+            break;
+        };
+        let mid = lo + (hi - lo) / 2;
+        picked.push(mid);
+        for (lo, hi) in [(lo, mid), (mid, hi)] {
+            if hi - lo > 1 {
+                gaps.push((hi - lo, Reverse(lo), hi));
+            }
+        }
+    }
+
+    picked
+}
+
+fn pick_per_period<P: Eq + Hash>(
+    commits: &[BatchCommit],
+    amount: usize,
+    period: impl Fn(OffsetDateTime) -> P,
+) -> Vec<usize> {
+    let mut periods = HashMap::<_, Vec<usize>>::new();
+    for (i, commit) in commits.iter().enumerate() {
+        periods.entry(period(commit.date)).or_default().push(i);
+    }
+
+    let mut picked = periods
+        .into_values()
+        .filter(|is| is.iter().all(|i| !commits[*i].covered))
+        // Commits are sorted by date, so this is the most recent one.
+        .map(|is| is[0])
+        .collect::<Vec<_>>();
+    picked.sort_unstable();
+    picked.truncate(amount);
+    picked
+}
+
+fn pick_batch(commits: &[BatchCommit], strategy: BatchStrategy, amount: usize) -> Vec<usize> {
+    match strategy {
+        BatchStrategy::Recent => pick_recent(commits, amount),
+        BatchStrategy::Even => pick_even(commits, amount),
+        BatchStrategy::Gaps => pick_gaps(commits, amount),
+        BatchStrategy::Daily => pick_per_period(commits, amount, |date| {
+            date.to_offset(UtcOffset::UTC).date()
+        }),
+        BatchStrategy::Weekly => pick_per_period(commits, amount, |date| {
+            let (year, week, _) = date.to_offset(UtcOffset::UTC).to_iso_week_date();
+            (year, week)
+        }),
+    }
 }
 
 #[derive(Deserialize)]
@@ -61,42 +299,63 @@ pub struct FormAdminQueueAddBatch {
     amount: u32,
     #[serde(default)]
     priority: i32,
+    #[serde(default)]
+    strategy: BatchStrategy,
+    #[serde(default)]
+    bench_hash: String,
+    #[serde(default)]
+    benchmarks: String,
 }
 
 pub async fn post_admin_queue_add_batch(
     _path: PathAdminQueueAddBatch,
     State(config): State<&'static ServerConfig>,
     State(db): State<SqlitePool>,
+    State(bench_repo): State<Option<BenchRepo>>,
     Form(form): Form<FormAdminQueueAddBatch>,
-) -> somehow::Result<impl IntoResponse> {
+) -> somehow::Result<Response> {
+    let target = match BenchTarget::resolve(&bench_repo, &form.bench_hash, &form.benchmarks) {
+        Ok(target) => target,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    let commits = get_batch_commits(&mut *conn).await?;
+    let picked = pick_batch(&commits, form.strategy, form.amount as usize);
+
     let date = OffsetDateTime::now_utc();
-    let added = sqlx::query!(
-        "
-        INSERT OR IGNORE INTO queue (hash, date, priority)
-        SELECT hash, ?, ?
-        FROM commits
-        LEFT JOIN runs USING (hash)
-        WHERE reachable = ? AND id IS NULL
-        ORDER BY unixepoch(committer_date) DESC
-        LIMIT ?
-        ",
-        date,
-        form.priority,
-        Reachable::FromTrackedRef,
-        form.amount,
-    )
-    .execute(&db)
-    .await?
-    .rows_affected();
+    let mut added = 0;
+    for i in picked {
+        added += sqlx::query!(
+            "
+            INSERT OR IGNORE INTO queue (hash, date, priority, bench_hash, benchmarks)
+            VALUES (?, ?, ?, ?, ?)
+            ",
+            commits[i].hash,
+            date,
+            form.priority,
+            target.bench_hash,
+            target.benchmarks,
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
 
     if added > 0 {
         info!(
-            "Admin batch-added {added} commits to queue with priority {}",
+            "Admin batch-added {added} commits to queue with priority {} ({:?}){}",
             form.priority,
+            form.strategy,
+            target.describe(),
         );
     }
 
-    Ok(Redirect::to(config.path(PathQueue {}).as_ref()))
+    Ok(Redirect::to(config.path(PathQueue {}).as_ref()).into_response())
 }
 
 #[derive(Deserialize)]
@@ -1 +1,4 @@
+pub mod hooks;
+pub mod openapi;
+pub mod v1;
 pub mod worker;
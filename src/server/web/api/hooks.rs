@@ -0,0 +1,98 @@
+//! Receive push notifications from git forges.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc;
+
+use crate::{config::ServerConfig, server::web::paths::PathApiHooksPush};
+
+/// The parts of a push payload we care about. GitHub, GitLab and Gitea all use
+/// the same field name here.
+#[derive(Deserialize)]
+struct PushPayload {
+    r#ref: Option<String>,
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Check a hex-encoded HMAC-SHA256 signature of the body.
+fn signature_matches(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = signature.trim();
+    let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Figure out which forge sent the hook and whether it knew the secret.
+///
+/// GitLab doesn't sign its payloads and sends the secret token as-is instead.
+fn verify(secret: &str, headers: &HeaderMap, body: &[u8]) -> Option<&'static str> {
+    if let Some(token) = header(headers, "X-Gitlab-Token") {
+        let valid: bool = token.as_bytes().ct_eq(secret.as_bytes()).into();
+        return valid.then_some("GitLab");
+    }
+
+    let signatures = [
+        ("X-Gitea-Signature", "Gitea"),
+        ("X-Hub-Signature-256", "GitHub"),
+        ("X-Tablejohn-Signature", "generic"),
+    ];
+    for (name, forge) in signatures {
+        if let Some(signature) = header(headers, name) {
+            return signature_matches(secret, body, signature).then_some(forge);
+        }
+    }
+
+    None
+}
+
+pub async fn post_api_hooks_push(
+    _path: PathApiHooksPush,
+    State(config): State<&'static ServerConfig>,
+    State(recurring_tx): State<Arc<mpsc::UnboundedSender<()>>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(secret) = &config.hooks_secret else {
+        return (StatusCode::NOT_FOUND, "push hooks are not configured").into_response();
+    };
+
+    let Some(forge) = verify(secret, &headers, &body) else {
+        warn!("Rejected push hook with missing or invalid signature");
+        return (StatusCode::UNAUTHORIZED, "invalid signature").into_response();
+    };
+
+    // Form-encoded or otherwise unusual payloads still trigger an update, we
+    // just can't tell which ref was pushed.
+    match serde_json::from_slice::<PushPayload>(&body) {
+        Ok(PushPayload { r#ref: Some(r#ref) }) => info!("Received {forge} push hook for {ref}"),
+        Ok(PushPayload { r#ref: None }) => info!("Received {forge} push hook"),
+        Err(e) => {
+            debug!("Failed to parse push hook payload: {e}");
+            info!("Received {forge} push hook");
+        }
+    }
+
+    let _ = recurring_tx.send(());
+
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,773 @@
+//! OpenAPI description of the worker and public json APIs.
+//!
+//! The schemas are generated from the same types that are (de)serialized by
+//! the endpoints, so they can't drift apart. Only the list of endpoints is
+//! maintained by hand.
+
+use axum::{extract::State, Json};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    api::{
+        self, Commit, ExportRow, Metric, Page, QueueAdd, QueueEntry, QueueUpdate, Run, RunSummary,
+    },
+    args::{NAME, VERSION},
+    config::ServerConfig,
+    server::web::paths::PathApiOpenapiJson,
+    shared::{ProjectsResponse, ServerResponse, WorkerRequest},
+};
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value {
+    json!(gen.subschema_for::<T>())
+}
+
+fn content(schema: Value) -> Value {
+    json!({ "content": { "application/json": { "schema": schema } } })
+}
+
+fn response(description: &str, schema: Value) -> Value {
+    let mut response = content(schema);
+    response["description"] = json!(description);
+    response
+}
+
+fn binary(description: &str, mime: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { mime: { "schema": { "type": "string", "format": "binary" } } },
+    })
+}
+
+fn status(description: &str) -> Value {
+    json!({ "description": description })
+}
+
+fn path_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+fn query_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "description": description,
+        "schema": schema,
+    })
+}
+
+fn page_params() -> Vec<Value> {
+    vec![
+        query_param(
+            "cursor",
+            "The `next` cursor of the previous page",
+            json!({ "type": "string" }),
+        ),
+        query_param(
+            "limit",
+            "Page size, 100 by default and at most 1000",
+            json!({ "type": "integer", "minimum": 1, "maximum": 1000 }),
+        ),
+    ]
+}
+
+fn worker_paths(gen: &mut SchemaGenerator) -> Map<String, Value> {
+    let worker = json!([{ "worker": [] }]);
+    let mut paths = Map::new();
+
+    paths.insert(
+        "/api/worker/status".to_string(),
+        json!({
+            "post": {
+                "tags": ["worker"],
+                "summary": "Report the worker's status, request and submit runs",
+                "security": worker,
+                "requestBody": content(schema::<WorkerRequest>(gen)),
+                "responses": {
+                    "200": response("What the worker should do next", schema::<ServerResponse>(gen)),
+                    "401": status("Invalid credentials, or the worker's secret changed"),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/worker/projects".to_string(),
+        json!({
+            "get": {
+                "tags": ["worker"],
+                "summary": "List the projects hosted by the server",
+                "description": "Only available at the server's root if it hosts multiple projects. The other worker endpoints of a project are below `/p/<name>`.",
+                "security": worker,
+                "responses": {
+                    "200": response("The projects", schema::<ProjectsResponse>(gen)),
+                    "401": status("Invalid credentials"),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/worker/repo/{hash}/tree.tar.gz".to_string(),
+        json!({
+            "get": {
+                "tags": ["worker"],
+                "summary": "Download the repo's worktree at a commit",
+                "security": worker,
+                "parameters": [path_param("hash", "Full commit hash")],
+                "responses": {
+                    "200": binary("The worktree", "application/gzip"),
+                    "401": status("Invalid credentials"),
+                    "404": status("No such commit"),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/worker/bench_repo/{hash}/tree.tar.gz".to_string(),
+        json!({
+            "get": {
+                "tags": ["worker"],
+                "summary": "Download the bench repo's worktree at a commit",
+                "security": worker,
+                "parameters": [path_param("hash", "Full commit hash")],
+                "responses": {
+                    "200": binary("The worktree", "application/gzip"),
+                    "401": status("Invalid credentials"),
+                    "404": status("No bench repo or no such commit"),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/worker/artifact/{id}/{name}".to_string(),
+        json!({
+            "post": {
+                "tags": ["worker"],
+                "summary": "Upload an artifact of a finished run",
+                "security": worker,
+                "parameters": [
+                    path_param("id", "Id of the run"),
+                    path_param("name", "Name of the artifact, may contain slashes"),
+                ],
+                "requestBody": binary("The artifact", "application/octet-stream"),
+                "responses": {
+                    "204": status("Artifact saved"),
+                    "400": status("Invalid artifact name"),
+                    "401": status("Invalid credentials"),
+                    "403": status("The run belongs to another worker"),
+                    "404": status("No such run"),
+                    "413": status("The artifact is too large"),
+                },
+            },
+        }),
+    );
+
+    paths
+}
+
+fn public_paths(gen: &mut SchemaGenerator) -> Map<String, Value> {
+    let token = json!([{ "token": [] }]);
+    let error = schema::<api::Error>(gen);
+    let mut paths = Map::new();
+
+    paths.insert(
+        "/api/v1/commits/{hash}".to_string(),
+        json!({
+            "get": {
+                "tags": ["public"],
+                "summary": "Commit info with parents, children, refs and runs",
+                "parameters": [path_param("hash", "Full commit hash")],
+                "responses": {
+                    "200": response("The commit", schema::<Commit>(gen)),
+                    "404": response("No such commit", error.clone()),
+                },
+            },
+        }),
+    );
+
+    let mut run_params = vec![
+        query_param(
+            "hash",
+            "Only runs of this commit",
+            json!({ "type": "string" }),
+        ),
+        query_param(
+            "worker",
+            "Only runs performed by this worker",
+            json!({ "type": "string" }),
+        ),
+        query_param(
+            "since",
+            "Only runs started at or after this time",
+            json!({ "type": "string", "format": "date-time" }),
+        ),
+    ];
+    run_params.extend(page_params());
+    paths.insert(
+        "/api/v1/runs".to_string(),
+        json!({
+            "get": {
+                "tags": ["public"],
+                "summary": "Runs, newest first",
+                "parameters": run_params,
+                "responses": {
+                    "200": response("One page of runs", schema::<Page<RunSummary>>(gen)),
+                    "400": response("Invalid cursor", error.clone()),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/v1/runs/{id}".to_string(),
+        json!({
+            "get": {
+                "tags": ["public"],
+                "summary": "Run info with phases, measurements and artifact names",
+                "parameters": [path_param("id", "Id of the run")],
+                "responses": {
+                    "200": response("The run", schema::<Run>(gen)),
+                    "404": response("No such run", error.clone()),
+                },
+            },
+        }),
+    );
+
+    let export_params = vec![
+        query_param(
+            "format",
+            "Format of the table, csv by default",
+            json!({ "type": "string", "enum": ["csv", "jsonl", "parquet"] }),
+        ),
+        query_param(
+            "metric",
+            "Only metrics starting with this prefix, may be repeated",
+            json!({ "type": "string" }),
+        ),
+        query_param(
+            "ref",
+            "Only commits reachable from this ref or commit",
+            json!({ "type": "string" }),
+        ),
+        query_param(
+            "since",
+            "Only commits committed at or after this time",
+            json!({ "type": "string", "format": "date-time" }),
+        ),
+        query_param(
+            "until",
+            "Only commits committed at or before this time",
+            json!({ "type": "string", "format": "date-time" }),
+        ),
+    ];
+    let row = schema::<ExportRow>(gen);
+    paths.insert(
+        "/api/v1/export".to_string(),
+        json!({
+            "get": {
+                "tags": ["public"],
+                "summary": "Measurements of successful runs as a long-format table",
+                "description": "One row per measurement, oldest commit first. In csv and json lines, each row has the shape of `ExportRow`.",
+                "parameters": export_params,
+                "responses": {
+                    "200": {
+                        "description": "The table",
+                        "content": {
+                            "text/csv": { "schema": { "type": "string" } },
+                            "application/jsonl": { "schema": row },
+                            "application/vnd.apache.parquet": { "schema": { "type": "string", "format": "binary" } },
+                        },
+                    },
+                    "404": response("No such ref", error.clone()),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/v1/metrics".to_string(),
+        json!({
+            "get": {
+                "tags": ["public"],
+                "summary": "Metrics, sorted by name",
+                "parameters": page_params(),
+                "responses": {
+                    "200": response("One page of metrics", schema::<Page<Metric>>(gen)),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/v1/queue".to_string(),
+        json!({
+            "get": {
+                "tags": ["public"],
+                "summary": "Queue entries in the order they're handed out to workers",
+                "parameters": page_params(),
+                "responses": {
+                    "200": response("One page of queue entries", schema::<Page<QueueEntry>>(gen)),
+                    "400": response("Invalid cursor", error.clone()),
+                },
+            },
+            "post": {
+                "tags": ["public"],
+                "summary": "Add commits to the queue",
+                "description": "Commits may be hashes, unambiguous hash prefixes or ref names. Either all commits are added or none.",
+                "security": token,
+                "requestBody": content(schema::<QueueAdd>(gen)),
+                "responses": {
+                    "200": response("The queue entries of the commits", schema::<Vec<QueueEntry>>(gen)),
+                    "400": response("Invalid bench repo revision or repetitions", error.clone()),
+                    "401": response("Invalid api token", error.clone()),
+                    "422": response("Invalid body or unknown commit", error.clone()),
+                },
+            },
+        }),
+    );
+
+    paths.insert(
+        "/api/v1/queue/{commit}".to_string(),
+        json!({
+            "patch": {
+                "tags": ["public"],
+                "summary": "Change the priority of a queue entry",
+                "security": token,
+                "parameters": [path_param("commit", "Commit hash, hash prefix or ref name")],
+                "requestBody": content(schema::<QueueUpdate>(gen)),
+                "responses": {
+                    "200": response("The changed queue entry", schema::<QueueEntry>(gen)),
+                    "401": response("Invalid api token", error.clone()),
+                    "404": response("Unknown commit or commit not in queue", error.clone()),
+                    "422": response("Invalid body", error.clone()),
+                },
+            },
+            "delete": {
+                "tags": ["public"],
+                "summary": "Remove a commit from the queue",
+                "security": token,
+                "parameters": [path_param("commit", "Commit hash, hash prefix or ref name")],
+                "responses": {
+                    "200": response("The removed queue entry", schema::<QueueEntry>(gen)),
+                    "401": response("Invalid api token", error.clone()),
+                    "404": response("Unknown commit or commit not in queue", error),
+                },
+            },
+        }),
+    );
+
+    paths
+}
+
+pub fn document(config: &ServerConfig) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let mut paths = worker_paths(&mut gen);
+    paths.extend(public_paths(&mut gen));
+
+    let server = match config.web_base.as_str() {
+        "" => "/",
+        base => base,
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": NAME,
+            "version": VERSION,
+            "description": "The worker protocol and the public json API. See DESIGN.md for details.",
+        },
+        "servers": [{ "url": server }],
+        "tags": [
+            { "name": "worker", "description": "Used by workers to coordinate with the server" },
+            { "name": "public", "description": "Scripting against the db" },
+        ],
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+            "securitySchemes": {
+                "worker": {
+                    "type": "http",
+                    "scheme": "basic",
+                    "description": "Worker name as username, the server's worker token as password",
+                },
+                "token": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "One of the tokens in `[server.api] tokens`",
+                },
+            },
+        },
+    })
+}
+
+pub async fn get_api_openapi_json(
+    _path: PathApiOpenapiJson,
+    State(config): State<&'static ServerConfig>,
+) -> Json<Value> {
+    Json(document(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use clap::Parser;
+    use serde::Serialize;
+    use serde_json::{json, Map, Value};
+    use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+    use crate::{
+        api::{self, ExportRow, QueueEntry, RunSummary},
+        args::{Args, NAME},
+        config::Config,
+        primitive::{Direction, QueueKind, Source, Timestamp},
+        shared::{
+            BenchMethod, FinishedRun, Measurement, Phase, Run, ServerResponse, UnfinishedRun,
+            WorkerRequest, WorkerStatus,
+        },
+    };
+
+    /// Check `value` against the subset of OpenAPI 3.0 schemas that schemars
+    /// generates. Unlike plain OpenAPI, objects may only contain documented
+    /// properties, so fields missing from a schema are caught too.
+    fn validate(
+        schemas: &Map<String, Value>,
+        schema: &Value,
+        value: &Value,
+        path: &str,
+    ) -> Result<(), String> {
+        let schema = schema.as_object().expect("schema is an object");
+        let nullable = schema.get("nullable") == Some(&json!(true));
+
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            let name = reference
+                .strip_prefix("#/components/schemas/")
+                .expect("reference to a component");
+            let target = schemas.get(name).expect("referenced schema exists");
+            if value.is_null() && nullable {
+                return Ok(());
+            }
+            return validate(schemas, target, value, path);
+        }
+
+        if value.is_null() {
+            return match nullable {
+                true => Ok(()),
+                false => Err(format!("{path}: null is not allowed")),
+            };
+        }
+
+        if let Some(one_of) = schema.get("oneOf").and_then(|s| s.as_array()) {
+            let matching = one_of
+                .iter()
+                .filter(|s| validate(schemas, s, value, path).is_ok())
+                .count();
+            if matching != 1 {
+                return Err(format!("{path}: {matching} of oneOf match {value}"));
+            }
+        }
+
+        if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+            if !values.contains(value) {
+                return Err(format!("{path}: {value} not in {values:?}"));
+            }
+        }
+
+        let Some(ty) = schema.get("type").and_then(|t| t.as_str()) else {
+            return Ok(());
+        };
+        match (ty, value) {
+            ("string", Value::String(string)) => {
+                let date_time = schema.get("format") == Some(&json!("date-time"));
+                if date_time && OffsetDateTime::parse(string, &Rfc3339).is_err() {
+                    return Err(format!("{path}: {value} is not a date-time"));
+                }
+            }
+            ("integer", Value::Number(number)) if !number.is_f64() => {
+                let minimum = schema.get("minimum").and_then(|m| m.as_f64());
+                if minimum.is_some_and(|minimum| number.as_f64().unwrap() < minimum) {
+                    return Err(format!("{path}: {value} is below the minimum"));
+                }
+            }
+            ("number", Value::Number(_)) | ("boolean", Value::Bool(_)) => {}
+            ("array", Value::Array(items)) => {
+                let len = items.len() as u64;
+                if schema
+                    .get("minItems")
+                    .is_some_and(|min| len < min.as_u64().unwrap())
+                {
+                    return Err(format!("{path}: too few items"));
+                }
+                if schema
+                    .get("maxItems")
+                    .is_some_and(|max| len > max.as_u64().unwrap())
+                {
+                    return Err(format!("{path}: too many items"));
+                }
+                let item_schema = schema.get("items").expect("array schema has items");
+                for (i, item) in items.iter().enumerate() {
+                    validate(schemas, item_schema, item, &format!("{path}[{i}]"))?;
+                }
+            }
+            ("object", Value::Object(object)) => {
+                let empty = Map::new();
+                let properties = match schema.get("properties") {
+                    Some(properties) => properties.as_object().expect("properties object"),
+                    None => &empty,
+                };
+                let required = schema.get("required").and_then(|r| r.as_array());
+                for name in required.into_iter().flatten() {
+                    let name = name.as_str().unwrap();
+                    if !object.contains_key(name) {
+                        return Err(format!("{path}: missing {name}"));
+                    }
+                }
+                for (name, property) in object {
+                    let path = format!("{path}.{name}");
+                    match (properties.get(name), schema.get("additionalProperties")) {
+                        (Some(schema), _) | (None, Some(schema)) => {
+                            validate(schemas, schema, property, &path)?
+                        }
+                        (None, None) => return Err(format!("{path}: undocumented property")),
+                    }
+                }
+            }
+            (ty, value) => return Err(format!("{path}: {value} is not of type {ty}")),
+        }
+
+        Ok(())
+    }
+
+    fn check(document: &Value, name: &str, sample: impl Serialize) {
+        let schemas = document["components"]["schemas"]
+            .as_object()
+            .expect("document has schemas");
+        let schema = schemas.get(name).expect("schema exists");
+        let value = serde_json::to_value(sample).unwrap();
+        if let Err(e) = validate(schemas, schema, &value, name) {
+            panic!("{e}\nin {value:#}");
+        }
+    }
+
+    fn document() -> Value {
+        let args = Args::parse_from([NAME, "--config", "/nonexistent/config.toml", "worker"]);
+        let config = Config::load(&args).unwrap();
+        super::document(&config.server)
+    }
+
+    fn timestamp() -> Timestamp {
+        Timestamp(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+    }
+
+    fn run_summary() -> RunSummary {
+        RunSummary {
+            id: "r-abc".to_string(),
+            hash: "a".repeat(40),
+            bench_method: "internal".to_string(),
+            worker_name: "worker".to_string(),
+            start: timestamp(),
+            end: timestamp(),
+            exit_code: 0,
+            kind: QueueKind::Bisect,
+        }
+    }
+
+    fn finished_run() -> FinishedRun {
+        let measurement = Measurement {
+            value: 1.5,
+            unit: Some("s".to_string()),
+            direction: Some(Direction::LessIsBetter),
+        };
+        let bare = Measurement {
+            value: 2.0,
+            unit: None,
+            direction: None,
+        };
+
+        FinishedRun {
+            id: "r-abc".to_string(),
+            hash: "a".repeat(40),
+            bench_method: BenchMethod::Internal.to_string(),
+            start: timestamp(),
+            end: Some(timestamp()),
+            exit_code: 1,
+            output: vec![
+                (Source::Internal, "starting".to_string()),
+                (Source::Stdout, "out".to_string()),
+                (Source::Stderr, "err".to_string()),
+            ],
+            phases: vec![Phase {
+                name: "build".to_string(),
+                start: timestamp(),
+                end: timestamp(),
+                exit_code: 1,
+                output_start: 0,
+                output_end: 3,
+            }],
+            measurements: HashMap::from([
+                ("time".to_string(), measurement),
+                ("size".to_string(), bare),
+            ]),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_undocumented_properties() {
+        let document = document();
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        let run = serde_json::to_value(api::Run {
+            summary: run_summary(),
+            worker_info: None,
+            phases: vec![],
+            measurements: BTreeMap::new(),
+            artifacts: vec![],
+        })
+        .unwrap();
+        assert!(validate(schemas, &schemas["RunSummary"], &run, "RunSummary").is_err());
+    }
+
+    #[test]
+    fn worker_request() {
+        let document = document();
+
+        let idle = WorkerRequest {
+            version: None,
+            protocol: 0,
+            info: None,
+            secret: "secret".to_string(),
+            status: WorkerStatus::Idle,
+            request_run: false,
+            submit_run: None,
+        };
+        check(&document, "WorkerRequest", idle);
+
+        let working = WorkerRequest {
+            version: Some("1.0.0".to_string()),
+            protocol: 1,
+            info: Some("info".to_string()),
+            secret: "secret".to_string(),
+            status: WorkerStatus::Working(UnfinishedRun {
+                id: "r-abc".to_string(),
+                hash: "a".repeat(40),
+                bench_method: "internal".to_string(),
+                start: timestamp(),
+                last_output: vec![(Source::Stdout, "line".to_string())],
+            }),
+            request_run: true,
+            submit_run: Some(finished_run()),
+        };
+        check(&document, "WorkerRequest", working);
+    }
+
+    #[test]
+    fn server_response() {
+        let document = document();
+
+        let empty = ServerResponse {
+            run: None,
+            abort_run: false,
+        };
+        check(&document, "ServerResponse", empty);
+
+        for bench_method in [
+            BenchMethod::Internal,
+            BenchMethod::Repo {
+                hash: "b".repeat(40),
+                benchmarks: vec![],
+            },
+            BenchMethod::Repo {
+                hash: "b".repeat(40),
+                benchmarks: vec!["fib".to_string()],
+            },
+        ] {
+            let response = ServerResponse {
+                run: Some(Run {
+                    id: "r-abc".to_string(),
+                    hash: "a".repeat(40),
+                    bench_method,
+                    start: timestamp(),
+                }),
+                abort_run: true,
+            };
+            check(&document, "ServerResponse", response);
+        }
+    }
+
+    #[test]
+    fn finished_run_with_output() {
+        check(&document(), "FinishedRun", finished_run());
+    }
+
+    #[test]
+    fn queue_entry() {
+        let document = document();
+
+        for (bench_hash, benchmarks) in [(None, vec![]), (Some("b".repeat(40)), vec!["fib"])] {
+            let entry = QueueEntry {
+                hash: "a".repeat(40),
+                date: timestamp(),
+                priority: -3,
+                kind: QueueKind::Scheduled,
+                failures: 1,
+                bench_hash,
+                benchmarks: benchmarks.into_iter().map(|b| b.to_string()).collect(),
+                repetitions: 2,
+            };
+            check(&document, "QueueEntry", entry);
+        }
+    }
+
+    #[test]
+    fn run_with_flattened_summary() {
+        let run = api::Run {
+            summary: run_summary(),
+            worker_info: None,
+            phases: vec![api::Phase {
+                name: "build".to_string(),
+                start: timestamp(),
+                end: timestamp(),
+                exit_code: 0,
+            }],
+            measurements: BTreeMap::from([(
+                "time".to_string(),
+                api::Measurement {
+                    value: 1.5,
+                    unit: None,
+                },
+            )]),
+            artifacts: vec!["flamegraph.svg".to_string()],
+        };
+        check(&document(), "Run", run);
+    }
+
+    #[test]
+    fn export_row() {
+        let document = document();
+
+        for unit in [None, Some("s".to_string())] {
+            let row = ExportRow {
+                hash: "a".repeat(40),
+                committer_date: timestamp(),
+                run_id: "r-abc".to_string(),
+                worker_name: "worker".to_string(),
+                metric: "time".to_string(),
+                value: 1.5,
+                unit,
+            };
+            check(&document, "ExportRow", row);
+        }
+    }
+}
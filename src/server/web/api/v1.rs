@@ -0,0 +1,740 @@
+//! Public json API for scripting against the db.
+//!
+//! Everything can be read without authentication. Changing the queue requires
+//! one of the configured api tokens.
+
+mod auth;
+
+use axum::{
+    body::Body,
+    extract::{rejection::JsonRejection, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::{
+    extract::Query,
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use futures::TryStreamExt;
+use log::info;
+use serde::Deserialize;
+use sqlx::{Acquire, SqliteConnection, SqlitePool};
+use time::OffsetDateTime;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    api::{
+        self, Commit, Measurement, Metric, Page, Phase, QueueAdd, QueueEntry, QueueUpdate, Run,
+        RunSummary,
+    },
+    config::ServerConfig,
+    primitive::{Direction, QueueKind, Reachable, Timestamp},
+    server::{
+        export::{self, ExportFilter, ExportFormat},
+        refs,
+        web::{
+            admin::queue::BenchTarget,
+            paths::{
+                PathApiV1CommitByHash, PathApiV1Export, PathApiV1Metrics, PathApiV1Queue,
+                PathApiV1QueueByHash, PathApiV1RunById, PathApiV1Runs,
+            },
+        },
+        BenchRepo,
+    },
+    somehow,
+};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+fn error(status: StatusCode, error: impl ToString) -> Response {
+    let error = api::Error {
+        error: error.to_string(),
+    };
+    (status, Json(error)).into_response()
+}
+
+fn invalid_cursor() -> Response {
+    error(StatusCode::BAD_REQUEST, "invalid cursor")
+}
+
+fn limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// Turn one row more than the limit into a page and a cursor.
+fn page<T>(mut items: Vec<T>, limit: u32, cursor: impl FnOnce(&T) -> String) -> Page<T> {
+    let next = if items.len() > limit as usize {
+        items.truncate(limit as usize);
+        items.last().map(cursor)
+    } else {
+        None
+    };
+    Page { items, next }
+}
+
+#[derive(Deserialize)]
+pub struct QueryPage {
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct QueryRuns {
+    /// Only runs of this commit.
+    hash: Option<String>,
+    /// Only runs performed by this worker.
+    worker: Option<String>,
+    /// Only runs started at or after this time.
+    since: Option<Timestamp>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct QueryExport {
+    #[serde(default)]
+    format: ExportFormat,
+    /// Only metrics starting with one of these prefixes.
+    #[serde(default)]
+    metric: Vec<String>,
+    /// Only commits reachable from this ref or commit.
+    r#ref: Option<String>,
+    /// Only commits committed at or after this time.
+    since: Option<Timestamp>,
+    /// Only commits committed at or before this time.
+    until: Option<Timestamp>,
+}
+
+async fn run_summaries(db: &SqlitePool, hash: &str) -> somehow::Result<Vec<RunSummary>> {
+    let runs = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            hash,
+            bench_method,
+            worker_name,
+            start AS "start: Timestamp",
+            end AS "end: Timestamp",
+            exit_code,
+            kind AS "kind: QueueKind"
+        FROM runs
+        WHERE hash = ?
+        ORDER BY unixepoch(start) ASC, id ASC
+        "#,
+        hash,
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| RunSummary {
+        id: r.id,
+        hash: r.hash,
+        bench_method: r.bench_method,
+        worker_name: r.worker_name,
+        start: r.start,
+        end: r.end,
+        exit_code: r.exit_code,
+        kind: r.kind,
+    })
+    .collect();
+
+    Ok(runs)
+}
+
+pub async fn get_api_v1_commit_by_hash(
+    path: PathApiV1CommitByHash,
+    State(db): State<SqlitePool>,
+) -> somehow::Result<Response> {
+    let Some(commit) = sqlx::query!(
+        r#"
+        SELECT
+            hash,
+            author,
+            author_date AS "author_date: Timestamp",
+            committer,
+            committer_date AS "committer_date: Timestamp",
+            message,
+            reachable AS "reachable: Reachable"
+        FROM commits
+        WHERE hash = ?
+        "#,
+        path.hash,
+    )
+    .fetch_optional(&db)
+    .await?
+    else {
+        return Ok(error(StatusCode::NOT_FOUND, "commit not found"));
+    };
+
+    let parents = sqlx::query_scalar!(
+        "SELECT parent FROM commit_edges WHERE child = ? ORDER BY position ASC, parent ASC",
+        commit.hash,
+    )
+    .fetch_all(&db)
+    .await?;
+
+    let children = sqlx::query_scalar!(
+        "SELECT child FROM commit_edges WHERE parent = ? ORDER BY child ASC",
+        commit.hash,
+    )
+    .fetch_all(&db)
+    .await?;
+
+    let refs = sqlx::query_scalar!(
+        "SELECT name FROM refs WHERE hash = ? ORDER BY name ASC",
+        commit.hash,
+    )
+    .fetch_all(&db)
+    .await?;
+
+    let runs = run_summaries(&db, &commit.hash).await?;
+
+    Ok(Json(Commit {
+        hash: commit.hash,
+        author: commit.author,
+        author_date: commit.author_date,
+        committer: commit.committer,
+        committer_date: commit.committer_date,
+        message: commit.message,
+        reachable: commit.reachable,
+        parents,
+        children,
+        refs,
+        runs,
+    })
+    .into_response())
+}
+
+pub async fn get_api_v1_run_by_id(
+    path: PathApiV1RunById,
+    State(db): State<SqlitePool>,
+) -> somehow::Result<Response> {
+    let Some(run) = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            hash,
+            bench_method,
+            worker_name,
+            worker_info,
+            start AS "start: Timestamp",
+            end AS "end: Timestamp",
+            exit_code,
+            kind AS "kind: QueueKind"
+        FROM runs
+        WHERE id = ?
+        "#,
+        path.id,
+    )
+    .fetch_optional(&db)
+    .await?
+    else {
+        return Ok(error(StatusCode::NOT_FOUND, "run not found"));
+    };
+
+    let phases = sqlx::query!(
+        r#"
+        SELECT
+            name,
+            start AS "start: Timestamp",
+            end AS "end: Timestamp",
+            exit_code
+        FROM run_phases
+        WHERE id = ?
+        ORDER BY idx ASC
+        "#,
+        run.id,
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|r| Phase {
+        name: r.name,
+        start: r.start,
+        end: r.end,
+        exit_code: r.exit_code,
+    })
+    .collect();
+
+    let measurements = sqlx::query!(
+        "SELECT metric, value, unit FROM run_measurements WHERE id = ?",
+        run.id,
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|r| {
+        let measurement = Measurement {
+            value: r.value,
+            unit: r.unit,
+        };
+        (r.metric, measurement)
+    })
+    .collect();
+
+    let artifacts = sqlx::query_scalar!(
+        "SELECT name FROM run_artifacts WHERE id = ? ORDER BY name ASC",
+        run.id,
+    )
+    .fetch_all(&db)
+    .await?;
+
+    Ok(Json(Run {
+        summary: RunSummary {
+            id: run.id,
+            hash: run.hash,
+            bench_method: run.bench_method,
+            worker_name: run.worker_name,
+            start: run.start,
+            end: run.end,
+            exit_code: run.exit_code,
+            kind: run.kind,
+        },
+        worker_info: run.worker_info,
+        phases,
+        measurements,
+        artifacts,
+    })
+    .into_response())
+}
+
+/// Runs, newest first.
+pub async fn get_api_v1_runs(
+    _path: PathApiV1Runs,
+    State(db): State<SqlitePool>,
+    Query(query): Query<QueryRuns>,
+) -> somehow::Result<Response> {
+    // Runs are sorted by start time in seconds, then by id.
+    let (after_start, after_id) = match &query.cursor {
+        None => (None, None),
+        Some(cursor) => {
+            let Some((start, id)) = cursor.split_once(':') else {
+                return Ok(invalid_cursor());
+            };
+            let Ok(start) = start.parse::<i64>() else {
+                return Ok(invalid_cursor());
+            };
+            (Some(start), Some(id.to_string()))
+        }
+    };
+
+    let since = query.since.map(|t| t.0);
+    let limit = limit(query.limit);
+    let fetch = limit + 1;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            hash,
+            bench_method,
+            worker_name,
+            start AS "start: Timestamp",
+            end AS "end: Timestamp",
+            exit_code,
+            kind AS "kind: QueueKind",
+            unixepoch(start) AS "start_secs!: i64"
+        FROM runs
+        WHERE (?1 IS NULL OR hash = ?1)
+        AND (?2 IS NULL OR worker_name = ?2)
+        AND (?3 IS NULL OR unixepoch(start) >= unixepoch(?3))
+        AND (
+            ?4 IS NULL
+            OR unixepoch(start) < ?4
+            OR (unixepoch(start) = ?4 AND id < ?5)
+        )
+        ORDER BY unixepoch(start) DESC, id DESC
+        LIMIT ?6
+        "#,
+        query.hash,
+        query.worker,
+        since,
+        after_start,
+        after_id,
+        fetch,
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|r| {
+        let summary = RunSummary {
+            id: r.id,
+            hash: r.hash,
+            bench_method: r.bench_method,
+            worker_name: r.worker_name,
+            start: r.start,
+            end: r.end,
+            exit_code: r.exit_code,
+            kind: r.kind,
+        };
+        (r.start_secs, summary)
+    })
+    .collect::<Vec<_>>();
+
+    let page = page(rows, limit, |(secs, run)| format!("{secs}:{}", run.id));
+    Ok(Json(Page {
+        items: page.items.into_iter().map(|(_, run)| run).collect(),
+        next: page.next,
+    })
+    .into_response())
+}
+
+/// All metrics, sorted by name.
+pub async fn get_api_v1_metrics(
+    _path: PathApiV1Metrics,
+    State(db): State<SqlitePool>,
+    Query(query): Query<QueryPage>,
+) -> somehow::Result<Response> {
+    let limit = limit(query.limit);
+    let fetch = limit + 1;
+
+    let metrics = sqlx::query!(
+        r#"
+        SELECT name, unit, direction AS "direction: Direction"
+        FROM metrics
+        WHERE ?1 IS NULL OR name > ?1
+        ORDER BY name ASC
+        LIMIT ?2
+        "#,
+        query.cursor,
+        fetch,
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|r| Metric {
+        name: r.name,
+        unit: r.unit,
+        direction: r.direction,
+    })
+    .collect();
+
+    Ok(Json(page(metrics, limit, |m| m.name.clone())).into_response())
+}
+
+/// The queue, in the order commits are handed out to workers.
+pub async fn get_api_v1_queue(
+    _path: PathApiV1Queue,
+    State(db): State<SqlitePool>,
+    Query(query): Query<QueryPage>,
+) -> somehow::Result<Response> {
+    // Entries are sorted by priority, then by date in seconds, then by hash.
+    let (after_priority, after_date, after_hash) = match &query.cursor {
+        None => (None, None, None),
+        Some(cursor) => {
+            let mut parts = cursor.splitn(3, ':');
+            let priority = parts.next().and_then(|p| p.parse::<i64>().ok());
+            let date = parts.next().and_then(|d| d.parse::<i64>().ok());
+            let (Some(priority), Some(date), Some(hash)) = (priority, date, parts.next()) else {
+                return Ok(invalid_cursor());
+            };
+            (Some(priority), Some(date), Some(hash.to_string()))
+        }
+    };
+
+    let limit = limit(query.limit);
+    let fetch = limit + 1;
+
+    let entries = sqlx::query!(
+        r#"
+        SELECT
+            hash,
+            date AS "date: Timestamp",
+            priority,
+            kind AS "kind: QueueKind",
+            failures,
+            bench_hash,
+            benchmarks,
+            repetitions,
+            unixepoch(date) AS "date_secs!: i64"
+        FROM queue
+        WHERE ?1 IS NULL
+        OR priority < ?1
+        OR (priority = ?1 AND unixepoch(date) < ?2)
+        OR (priority = ?1 AND unixepoch(date) = ?2 AND hash > ?3)
+        ORDER BY priority DESC, unixepoch(date) DESC, hash ASC
+        LIMIT ?4
+        "#,
+        after_priority,
+        after_date,
+        after_hash,
+        fetch,
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|r| {
+        let entry = QueueEntry {
+            hash: r.hash,
+            date: r.date,
+            priority: r.priority,
+            kind: r.kind,
+            failures: r.failures,
+            bench_hash: r.bench_hash,
+            benchmarks: r
+                .benchmarks
+                .map(|b| b.split(',').map(|b| b.to_string()).collect())
+                .unwrap_or_default(),
+            repetitions: r.repetitions,
+        };
+        (r.date_secs, entry)
+    })
+    .collect::<Vec<_>>();
+
+    let page = page(entries, limit, |(secs, entry)| {
+        format!("{}:{secs}:{}", entry.priority, entry.hash)
+    });
+    Ok(Json(Page {
+        items: page.items.into_iter().map(|(_, entry)| entry).collect(),
+        next: page.next,
+    })
+    .into_response())
+}
+
+async fn fetch_queue_entry(
+    conn: &mut SqliteConnection,
+    hash: &str,
+) -> somehow::Result<Option<QueueEntry>> {
+    let entry = sqlx::query!(
+        r#"
+        SELECT
+            hash,
+            date AS "date: Timestamp",
+            priority,
+            kind AS "kind: QueueKind",
+            failures,
+            bench_hash,
+            benchmarks,
+            repetitions
+        FROM queue
+        WHERE hash = ?
+        "#,
+        hash,
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|r| QueueEntry {
+        hash: r.hash,
+        date: r.date,
+        priority: r.priority,
+        kind: r.kind,
+        failures: r.failures,
+        bench_hash: r.bench_hash,
+        benchmarks: r
+            .benchmarks
+            .map(|b| b.split(',').map(|b| b.to_string()).collect())
+            .unwrap_or_default(),
+        repetitions: r.repetitions,
+    });
+
+    Ok(entry)
+}
+
+/// Add commits to the queue and respond with their queue entries.
+///
+/// Either all commits are added or, if any of them can't be resolved, none.
+pub async fn post_api_v1_queue(
+    _path: PathApiV1Queue,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+    State(bench_repo): State<Option<BenchRepo>>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    body: Result<Json<QueueAdd>, JsonRejection>,
+) -> somehow::Result<Response> {
+    if !auth::is_authorized(config, auth) {
+        return Ok(auth::unauthorized());
+    }
+    let add = match body {
+        Ok(Json(add)) => add,
+        Err(e) => return Ok(error(e.status(), e.body_text())),
+    };
+    if add.repetitions == 0 {
+        return Ok(error(
+            StatusCode::BAD_REQUEST,
+            "repetitions must be at least 1",
+        ));
+    }
+
+    let bench_hash = add.bench_hash.unwrap_or_default();
+    let benchmarks = add.benchmarks.join(",");
+    let target = match BenchTarget::resolve(&bench_repo, &bench_hash, &benchmarks) {
+        Ok(target) => target,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+    };
+
+    let mut hashes = vec![];
+    for name in &add.commits {
+        let Some(hash) = refs::resolve(&db, name).await? else {
+            return Ok(error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("unknown commit {name:?}"),
+            ));
+        };
+        if !hashes.contains(&hash) {
+            hashes.push(hash);
+        }
+    }
+
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    // Explicitly adding a commit again overwrites what it should be run with.
+    let date = OffsetDateTime::now_utc();
+    for hash in &hashes {
+        sqlx::query!(
+            "
+            INSERT INTO queue (hash, date, priority, bench_hash, benchmarks, repetitions)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (hash) DO UPDATE
+            SET
+                priority = max(priority, excluded.priority),
+                bench_hash = excluded.bench_hash,
+                benchmarks = excluded.benchmarks,
+                repetitions = max(repetitions, excluded.repetitions)
+            ",
+            hash,
+            date,
+            add.priority,
+            target.bench_hash,
+            target.benchmarks,
+            add.repetitions,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let mut entries = vec![];
+    for hash in &hashes {
+        if let Some(entry) = fetch_queue_entry(&mut *conn, hash).await? {
+            entries.push(entry);
+        }
+    }
+
+    tx.commit().await?;
+
+    for hash in &hashes {
+        info!(
+            "Api added {hash} to queue with priority {} and {} repetitions{}",
+            add.priority,
+            add.repetitions,
+            target.describe(),
+        );
+    }
+
+    Ok(Json(entries).into_response())
+}
+
+/// Change the priority of a queue entry and respond with the changed entry.
+pub async fn patch_api_v1_queue_by_hash(
+    path: PathApiV1QueueByHash,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    body: Result<Json<QueueUpdate>, JsonRejection>,
+) -> somehow::Result<Response> {
+    if !auth::is_authorized(config, auth) {
+        return Ok(auth::unauthorized());
+    }
+    let update = match body {
+        Ok(Json(update)) => update,
+        Err(e) => return Ok(error(e.status(), e.body_text())),
+    };
+    let Some(hash) = refs::resolve(&db, &path.hash).await? else {
+        return Ok(error(StatusCode::NOT_FOUND, "commit not found"));
+    };
+
+    let mut conn = db.acquire().await?;
+    let updated = sqlx::query!(
+        "UPDATE queue SET priority = ? WHERE hash = ?",
+        update.priority,
+        hash,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    let Some(entry) = fetch_queue_entry(&mut conn, &hash)
+        .await?
+        .filter(|_| updated > 0)
+    else {
+        return Ok(error(StatusCode::NOT_FOUND, "commit not in queue"));
+    };
+
+    info!("Api set queue priority of {hash} to {}", update.priority);
+
+    Ok(Json(entry).into_response())
+}
+
+/// Remove a commit from the queue and respond with its former queue entry.
+pub async fn delete_api_v1_queue_by_hash(
+    path: PathApiV1QueueByHash,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> somehow::Result<Response> {
+    if !auth::is_authorized(config, auth) {
+        return Ok(auth::unauthorized());
+    }
+    let Some(hash) = refs::resolve(&db, &path.hash).await? else {
+        return Ok(error(StatusCode::NOT_FOUND, "commit not found"));
+    };
+
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    let Some(entry) = fetch_queue_entry(&mut *conn, &hash).await? else {
+        return Ok(error(StatusCode::NOT_FOUND, "commit not in queue"));
+    };
+    sqlx::query!("DELETE FROM queue WHERE hash = ?", hash)
+        .execute(&mut *conn)
+        .await?;
+
+    tx.commit().await?;
+
+    info!("Api deleted {hash} from queue");
+
+    Ok(Json(entry).into_response())
+}
+
+/// Measurements of successful runs as a long-format table.
+pub async fn get_api_v1_export(
+    _path: PathApiV1Export,
+    State(db): State<SqlitePool>,
+    Query(query): Query<QueryExport>,
+) -> somehow::Result<Response> {
+    let reachable_from = match &query.r#ref {
+        None => None,
+        Some(name) => match refs::resolve(&db, name).await? {
+            Some(hash) => Some(hash),
+            None => return Ok(error(StatusCode::NOT_FOUND, "ref not found")),
+        },
+    };
+
+    let filter = ExportFilter {
+        metrics: query.metric,
+        reachable_from,
+        since: query.since,
+        until: query.until,
+    };
+    let chunks = export::stream(db, filter, query.format);
+    let body = Body::from_stream(ReceiverStream::new(chunks).map_err(|e| e.0));
+
+    let disposition = format!(
+        "attachment; filename=\"export.{}\"",
+        query.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                query.format.content_type().to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
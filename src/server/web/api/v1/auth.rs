@@ -0,0 +1,43 @@
+//! Verify api token bearer authentication headers.
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use subtle::ConstantTimeEq;
+
+use crate::{api, config::ServerConfig};
+
+fn is_token_valid(token: &str, config: &'static ServerConfig) -> bool {
+    config
+        .api_tokens
+        .iter()
+        .any(|t| bool::from(t.as_bytes().ct_eq(token.as_bytes())))
+}
+
+pub fn is_authorized(
+    config: &'static ServerConfig,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> bool {
+    auth.is_some_and(|auth| is_token_valid(auth.token(), config))
+}
+
+pub fn unauthorized() -> Response {
+    let error = api::Error {
+        error: "invalid api token".to_string(),
+    };
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Bearer realm=\"api\""),
+        )],
+        Json(error),
+    )
+        .into_response()
+}
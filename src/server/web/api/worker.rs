@@ -1,10 +1,10 @@
 mod auth;
-mod stream;
+pub mod stream;
 
 use std::sync::{Arc, Mutex};
 
 use axum::{
-    body::Body,
+    body::{Body, Bytes},
     extract::State,
     http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
@@ -15,36 +15,53 @@ use axum_extra::{
     TypedHeader,
 };
 use gix::{ObjectId, ThreadSafeRepository};
-use log::{debug, info};
-use sqlx::{Acquire, SqlitePool};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use sqlx::{Acquire, SqliteConnection, SqlitePool};
 use time::OffsetDateTime;
 
 use crate::{
+    args::VERSION,
     config::ServerConfig,
-    primitive::Timestamp,
+    primitive::{QueueKind, Timestamp},
     server::{
+        notify, status,
         web::paths::{
-            PathApiWorkerBenchRepoByHashTreeTarGz, PathApiWorkerRepoByHashTreeTarGz,
-            PathApiWorkerStatus,
+            PathApiWorkerArtifact, PathApiWorkerBenchRepoByHashTreeTarGz, PathApiWorkerProjects,
+            PathApiWorkerRepoByHashTreeTarGz, PathApiWorkerStatus,
         },
         workers::{WorkerInfo, Workers},
-        BenchRepo, Repo,
+        BenchRepo, Deliveries, Repo,
+    },
+    shared::{
+        BenchMethod, FinishedRun, ProjectsResponse, ServerResponse, WorkerRequest,
+        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
     },
-    shared::{BenchMethod, FinishedRun, ServerResponse, WorkerRequest},
     somehow,
 };
 
-async fn save_work(
+/// Store a finished run and update the queue accordingly.
+///
+/// Returns how many entries were removed from the queue.
+pub async fn save_run(
+    conn: &mut SqliteConnection,
     run: FinishedRun,
     worker_name: &str,
     worker_info: &Option<String>,
-    db: &SqlitePool,
-) -> somehow::Result<()> {
-    let mut tx = db.begin().await?;
-    let conn = tx.acquire().await?;
-
+    config: &ServerConfig,
+) -> somehow::Result<u64> {
     let end = run.end.map(|t| t.0).unwrap_or_else(OffsetDateTime::now_utc);
 
+    // The commit may have been removed from the queue while the run was in
+    // progress, in which case we no longer know why it was run.
+    let kind = sqlx::query_scalar!(
+        r#"SELECT kind AS "kind: QueueKind" FROM queue WHERE hash = ?"#,
+        run.hash,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .unwrap_or(QueueKind::Normal);
+
     sqlx::query!(
         "
         INSERT INTO runs (
@@ -55,9 +72,10 @@ async fn save_work(
             worker_info,
             start,
             end,
-            exit_code
+            exit_code,
+            kind
         )
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
         ",
         run.id,
         run.hash,
@@ -67,6 +85,7 @@ async fn save_work(
         run.start.0,
         end,
         run.exit_code,
+        kind,
     )
     .execute(&mut *conn)
     .await?;
@@ -78,13 +97,18 @@ async fn save_work(
         .await?;
 
     for (metric, measurement) in run.measurements {
+        // A plain INSERT OR REPLACE would delete the existing row first,
+        // cascading to the measurements of all previous runs.
         sqlx::query!(
             "
-            INSERT OR REPLACE INTO metrics (name, unit)
-            VALUES (?, ?)
+            INSERT INTO metrics (name, unit, direction) VALUES (?1, ?2, COALESCE(?3, 0))
+            ON CONFLICT (name) DO UPDATE
+            SET unit = excluded.unit,
+                direction = COALESCE(?3, direction)
             ",
             metric,
             measurement.unit,
+            measurement.direction,
         )
         .execute(&mut *conn)
         .await?;
@@ -130,23 +154,160 @@ async fn save_work(
         .await?;
     }
 
-    // The thing has been done :D
-    sqlx::query!("DELETE FROM queue WHERE hash = ?", run.hash)
+    for (idx, phase) in run.phases.into_iter().enumerate() {
+        let idx = idx as u32;
+        let output_start = phase.output_start as u32;
+        let output_end = phase.output_end as u32;
+        sqlx::query!(
+            "
+            INSERT INTO run_phases (
+                id,
+                idx,
+                name,
+                start,
+                end,
+                exit_code,
+                output_start,
+                output_end
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ",
+            run.id,
+            idx,
+            phase.name,
+            phase.start.0,
+            phase.end.0,
+            phase.exit_code,
+            output_start,
+            output_end,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let removed = if run.exit_code == 0 {
+        // The thing has been done :D Unless it should be done again.
+        let repeated = sqlx::query!(
+            "
+            UPDATE queue SET repetitions = repetitions - 1
+            WHERE hash = ? AND repetitions > 1
+            ",
+            run.hash,
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+
+        if repeated > 0 {
+            info!("Run {} finished, keeping {} in queue", run.id, run.hash);
+            0
+        } else {
+            sqlx::query!("DELETE FROM queue WHERE hash = ?", run.hash)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected()
+        }
+    } else {
+        // The thing has not been done D: Maybe it'll work next time?
+        sqlx::query!(
+            "UPDATE queue SET failures = failures + 1 WHERE hash = ?",
+            run.hash,
+        )
         .execute(&mut *conn)
         .await?;
 
+        let removed = sqlx::query!(
+            "DELETE FROM queue WHERE hash = ? AND failures > ?",
+            run.hash,
+            config.queue_retries,
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+
+        if removed == 0 {
+            info!("Run {} failed, keeping {} in queue", run.id, run.hash);
+        }
+        removed
+    };
+
+    Ok(removed)
+}
+
+async fn save_work(
+    run: FinishedRun,
+    worker_name: &str,
+    worker_info: &Option<String>,
+    config: &ServerConfig,
+    db: &SqlitePool,
+) -> somehow::Result<()> {
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    let id = run.id.clone();
+    let hash = run.hash.clone();
+    let exit_code = run.exit_code;
+    let removed = save_run(conn, run, worker_name, worker_info, config).await?;
+
+    status::enqueue(config, conn, &id).await?;
+    notify::run_saved(config, conn, &id, &hash, exit_code.into()).await?;
+
+    if removed > 0 {
+        let queued = sqlx::query_scalar!("SELECT COUNT(*) FROM queue")
+            .fetch_one(&mut *conn)
+            .await?;
+        if queued == 0 {
+            notify::queue_empty(config, conn).await?;
+        }
+    }
+
     tx.commit().await?;
     Ok(())
 }
 
+/// The parts of a [`WorkerRequest`] that must be understood before the rest of
+/// it can be.
+#[derive(Deserialize)]
+struct ProtocolProbe {
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    protocol: u32,
+}
+
+/// Explain why a worker's request can't be understood, if it can't.
+fn protocol_error(name: &str, body: &[u8]) -> Option<String> {
+    // Requests that aren't even json objects are left for serde to complain
+    // about.
+    let probe = serde_json::from_slice::<ProtocolProbe>(body).ok()?;
+    if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&probe.protocol) {
+        return None;
+    }
+
+    let version = probe.version.as_deref().unwrap_or("unknown");
+    let upgrade = if probe.protocol > PROTOCOL_VERSION {
+        "server"
+    } else {
+        "worker"
+    };
+    Some(format!(
+        "worker {name} (version {version}) speaks protocol {}, \
+        but server (version {VERSION}) only understands protocols \
+        {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}, please upgrade the {upgrade}",
+        probe.protocol,
+    ))
+}
+
+#[allow(clippy::too_many_arguments)] // One per extractor
 pub async fn post_api_worker_status(
     _path: PathApiWorkerStatus,
     State(config): State<&'static ServerConfig>,
     State(db): State<SqlitePool>,
     State(bench_repo): State<Option<BenchRepo>>,
     State(workers): State<Arc<Mutex<Workers>>>,
+    State(deliveries): State<Deliveries>,
     auth: Option<TypedHeader<Authorization<Basic>>>,
-    Json(request): Json<WorkerRequest>,
+    body: Bytes,
 ) -> somehow::Result<Response> {
     let name = match auth::authenticate(config, auth) {
         Ok(name) => name,
@@ -154,48 +315,91 @@ pub async fn post_api_worker_status(
     };
     debug!("Received status update from {name}");
 
+    // Check the protocol version first so incompatible workers get a helpful
+    // error instead of whatever serde makes of their request.
+    if let Some(error) = protocol_error(&name, &body) {
+        warn!("Rejected worker {name}: {error}");
+        return Ok((StatusCode::BAD_REQUEST, error).into_response());
+    }
+    let request = match serde_json::from_slice::<WorkerRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Rejected invalid status update from {name}: {e}");
+            let error = format!("invalid status update: {e}");
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, error).into_response());
+        }
+    };
+
     if let Some(run) = request.submit_run {
         info!("Received run {} for {} from {name}", run.id, run.hash);
-        save_work(run, &name, &request.info, &db).await?;
+        save_work(run, &name, &request.info, config, &db).await?;
+        tokio::spawn(status::deliver(config, db.clone(), deliveries.clone()));
+        tokio::spawn(notify::deliver(config, db.clone(), deliveries.clone()));
     }
 
     // Fetch queue
-    let queue = sqlx::query_scalar!(
+    let entries = sqlx::query!(
         "\
-        SELECT hash FROM queue \
+        SELECT hash, bench_hash, benchmarks FROM queue \
         ORDER BY priority DESC, unixepoch(date) DESC, hash ASC \
         "
     )
     .fetch_all(&db)
     .await?;
+    let queue = entries.iter().map(|r| r.hash.clone()).collect::<Vec<_>>();
 
     // Fetch bench method
-    let bench_method = match bench_repo {
-        Some(bench_repo) => BenchMethod::Repo {
-            hash: bench_repo.0.to_thread_local().head_id()?.to_string(),
-        },
-        None => BenchMethod::Internal,
+    let bench_head = match &bench_repo {
+        Some(bench_repo) => Some(bench_repo.0.to_thread_local().head_id()?.to_string()),
+        None => None,
+    };
+    let bench_method = |hash: &str| {
+        let Some(bench_head) = bench_head else {
+            return BenchMethod::Internal;
+        };
+        let entry = entries.iter().find(|r| r.hash == hash);
+        BenchMethod::Repo {
+            hash: entry
+                .and_then(|r| r.bench_hash.clone())
+                .unwrap_or(bench_head),
+            benchmarks: entry
+                .and_then(|r| r.benchmarks.as_deref())
+                .map(|b| b.split(',').map(|b| b.to_string()).collect())
+                .unwrap_or_default(),
+        }
     };
 
     // Update internal state
-    let (work, abort_work) = {
+    let (work, abort_work, disconnected) = {
         let mut guard = workers.lock().unwrap();
         guard.clean();
         if !guard.verify_secret(&name, &request.secret) {
             return Ok((StatusCode::UNAUTHORIZED, "invalid secret").into_response());
         }
+        let disconnected = guard.take_disconnected();
         guard.update(
             name.clone(),
-            WorkerInfo::new(request.secret, Timestamp::now(), request.status),
+            WorkerInfo::new(
+                request.secret,
+                Timestamp::now(),
+                request.status,
+                request.version,
+                request.protocol,
+            ),
         );
         let work = match request.request_run {
             true => guard.find_and_reserve_run(&name, &queue, bench_method),
             false => None,
         };
         let abort_work = guard.should_abort_work(&name, &queue);
-        (work, abort_work)
+        (work, abort_work, disconnected)
     };
 
+    if !disconnected.is_empty() {
+        notify::workers_disconnected(config, &db, &disconnected).await?;
+        tokio::spawn(notify::deliver(config, db.clone(), deliveries));
+    }
+
     Ok(Json(ServerResponse {
         run: work,
         abort_run: abort_work,
@@ -203,6 +407,100 @@ pub async fn post_api_worker_status(
     .into_response())
 }
 
+fn is_artifact_name_valid(name: &str) -> bool {
+    // Artifact names are relative paths inside the worker's artifacts dir. They
+    // are only ever used as keys, but we still don't want any funny business.
+    !name.is_empty()
+        && name
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
+
+pub async fn post_api_worker_artifact(
+    path: PathApiWorkerArtifact,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    body: Bytes,
+) -> somehow::Result<Response> {
+    let name = match auth::authenticate(config, auth) {
+        Ok(name) => name,
+        Err(response) => return Ok(response),
+    };
+    debug!(
+        "Worker {name} is uploading artifact {} for run {}",
+        path.name, path.id
+    );
+
+    if !is_artifact_name_valid(&path.name) {
+        return Ok((StatusCode::BAD_REQUEST, "invalid artifact name").into_response());
+    }
+
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    let Some(worker_name) =
+        sqlx::query_scalar!("SELECT worker_name FROM runs WHERE id = ?", path.id)
+            .fetch_optional(&mut *conn)
+            .await?
+    else {
+        return Ok((StatusCode::NOT_FOUND, "run not found").into_response());
+    };
+
+    if worker_name != name {
+        return Ok((StatusCode::FORBIDDEN, "run belongs to another worker").into_response());
+    }
+
+    // Replacing an artifact shouldn't count its previous size towards the limit.
+    let used = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(SUM(size), 0) AS "size: i64"
+        FROM run_artifacts
+        WHERE id = ? AND name != ?
+        "#,
+        path.id,
+        path.name,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let size = body.len() as i64;
+    if used + size > config.worker_artifact_total as i64 {
+        warn!(
+            "Rejected artifact {} for run {} from {name}: total artifact size limit exceeded",
+            path.name, path.id
+        );
+        return Ok((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "total artifact size limit exceeded",
+        )
+            .into_response());
+    }
+
+    let data = &body[..];
+    sqlx::query!(
+        "
+        INSERT OR REPLACE INTO run_artifacts (id, name, size, data)
+        VALUES (?, ?, ?, ?)
+        ",
+        path.id,
+        path.name,
+        size,
+        data,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    tx.commit().await?;
+
+    info!(
+        "Received artifact {} ({size} bytes) for run {} from {name}",
+        path.name, path.id
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
 fn stream_response(repo: Arc<ThreadSafeRepository>, id: ObjectId) -> impl IntoResponse {
     (
         [
@@ -219,6 +517,21 @@ fn stream_response(repo: Arc<ThreadSafeRepository>, id: ObjectId) -> impl IntoRe
     )
 }
 
+pub async fn get_api_worker_projects(
+    _path: PathApiWorkerProjects,
+    State(config): State<&'static ServerConfig>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+) -> somehow::Result<Response> {
+    let name = match auth::authenticate(config, auth) {
+        Ok(name) => name,
+        Err(response) => return Ok(response),
+    };
+    debug!("Worker {name} is listing projects");
+
+    let projects = config.projects.iter().map(|p| p.name.clone()).collect();
+    Ok(Json(ProjectsResponse { projects }).into_response())
+}
+
 pub async fn get_api_worker_repo_by_hash_tree_tar_gz(
     path: PathApiWorkerRepoByHashTreeTarGz,
     State(config): State<&'static ServerConfig>,
@@ -258,3 +571,63 @@ pub async fn get_api_worker_bench_repo_by_hash_tree_tar_gz(
     let id = path.hash.parse::<ObjectId>()?;
     Ok(stream_response(bench_repo.0, id).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        body::{self, Bytes},
+        extract::State,
+        http::StatusCode,
+    };
+    use axum_extra::{headers::Authorization, TypedHeader};
+    use clap::Parser;
+    use serde_json::json;
+
+    use crate::{
+        args::{Args, NAME},
+        config::Config,
+        server::{open_db, web::paths::PathApiWorkerStatus, workers::Workers, Deliveries},
+    };
+
+    #[tokio::test]
+    async fn rejects_unversioned_workers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let args = Args::parse_from([NAME, "--config", path.to_str().unwrap(), "worker"]);
+        let config = &Box::leak(Box::new(Config::load(&args).unwrap())).server;
+        let db = open_db(&dir.path().join("db.sqlite")).await.unwrap();
+
+        // A status update from before workers reported their protocol
+        let body = json!({
+            "info": null,
+            "secret": "secret",
+            "status": { "type": "idle" },
+            "request_run": true,
+            "submit_run": null,
+        });
+        let auth = Authorization::basic("old-worker", &config.worker_token);
+
+        let response = super::post_api_worker_status(
+            PathApiWorkerStatus {},
+            State(config),
+            State(db),
+            State(None),
+            State(Arc::new(Mutex::new(Workers::new(config)))),
+            State(Deliveries::default()),
+            Some(TypedHeader(auth)),
+            Bytes::from(body.to_string()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("worker old-worker (version unknown) speaks protocol 0,"));
+        assert!(body.ends_with("please upgrade the worker"));
+    }
+}
@@ -2,7 +2,7 @@ use maud::{html, Markup};
 
 use crate::{
     config::ServerConfig,
-    primitive::{Reachable, Timestamp},
+    primitive::{QueueKind, Reachable, Timestamp},
     server::format,
 };
 
@@ -62,13 +62,30 @@ pub fn link_run_short(config: &ServerConfig, id: String, hash: &str, message: &s
     }
 }
 
-/// Link to a run by its start time.
-pub fn link_run_date(config: &ServerConfig, id: String, start: Timestamp) -> Markup {
+/// Link to a run by its start time, flagging it if it failed or was scheduled.
+pub fn link_run_date(
+    config: &ServerConfig,
+    id: String,
+    start: Timestamp,
+    exit_code: i64,
+    kind: QueueKind,
+) -> Markup {
     let start = format::time(start);
     let path = config.path(PathRunById { id });
+    let failed = exit_code != 0;
 
     html! {
-        a href=(path) { "Run from " (start) }
+        a href=(path) .run-failed[failed] { "Run from " (start) }
+        @if kind == QueueKind::Scheduled {
+            " " span .run-scheduled title="This run was added to the queue by a schedule." {
+                "(scheduled)"
+            }
+        }
+        @if failed {
+            " " span .run-failed title="This run did not complete successfully." {
+                "(failed, exit code " (exit_code) ")"
+            }
+        }
     }
 }
 
@@ -73,8 +73,11 @@ impl Page {
                             img src=(self.config.path(LOGO_SVG)) alt="";
                             (self.config.repo_name)
                         }
-                        a .current[self.tab == Some(Tab::Graph)] href=(self.config.path(PathGraph {})) { "graph" }
-                        a .current[self.tab == Some(Tab::Queue)] href=(self.config.path(PathQueue {})) { "queue" }
+                        // A server hosting multiple projects only has a list of projects
+                        @if self.config.projects.is_empty() {
+                            a .current[self.tab == Some(Tab::Graph)] href=(self.config.path(PathGraph {})) { "graph" }
+                            a .current[self.tab == Some(Tab::Queue)] href=(self.config.path(PathQueue {})) { "queue" }
+                        }
                     }
                     @for body in self.bodies { (body) }
                 }
@@ -1,7 +1,11 @@
 pub mod commit;
 pub mod graph;
 pub mod index;
+pub mod orphans;
+pub mod projects;
 pub mod queue;
+pub mod range;
+pub mod report;
 pub mod run;
 pub mod test;
 pub mod worker;
@@ -9,7 +9,7 @@ use sqlx::SqlitePool;
 
 use crate::{
     config::ServerConfig,
-    primitive::{Reachable, Timestamp},
+    primitive::{QueueKind, Reachable, Timestamp},
     server::{
         format,
         web::{
@@ -18,6 +18,7 @@ use crate::{
             paths::{PathAdminQueueAdd, PathCommitByHash},
             server_config_ext::ServerConfigExt,
         },
+        BenchRepo,
     },
     somehow,
 };
@@ -26,6 +27,7 @@ pub async fn get_commit_by_hash(
     path: PathCommitByHash,
     State(config): State<&'static ServerConfig>,
     State(db): State<SqlitePool>,
+    State(bench_repo): State<Option<BenchRepo>>,
 ) -> somehow::Result<Response> {
     let Some(commit) = sqlx::query!(
         r#"
@@ -36,7 +38,8 @@ pub async fn get_commit_by_hash(
             committer,
             committer_date AS "committer_date: Timestamp",
             message,
-            reachable AS "reachable: Reachable"
+            reachable AS "reachable: Reachable",
+            unreachable_since AS "unreachable_since: Timestamp"
         FROM commits
         WHERE hash = ?
         "#,
@@ -84,17 +87,42 @@ pub async fn get_commit_by_hash(
     .try_collect::<Vec<_>>()
     .await?;
 
+    let tags = sqlx::query_scalar!(
+        "
+        SELECT name FROM refs
+        WHERE hash = ? AND name LIKE 'refs/tags/%'
+        ORDER BY name ASC
+        ",
+        path.hash,
+    )
+    .fetch_all(&db)
+    .await?;
+
+    let culprits = sqlx::query!(
+        "
+        SELECT metric, old_value, new_value FROM bisect_culprits
+        WHERE hash = ?
+        ORDER BY metric ASC
+        ",
+        path.hash,
+    )
+    .fetch_all(&db)
+    .await?;
+
     let runs = sqlx::query!(
         r#"
         SELECT
             id,
-            start AS "start: Timestamp"
+            start AS "start: Timestamp",
+            exit_code,
+            kind AS "kind: QueueKind"
         FROM runs WHERE hash = ?
+        ORDER BY unixepoch(start) ASC
         "#,
         path.hash,
     )
     .fetch(&db)
-    .map_ok(|r| components::link_run_date(config, r.id, r.start))
+    .map_ok(|r| components::link_run_date(config, r.id, r.start, r.exit_code, r.kind))
     .try_collect::<Vec<_>>()
     .await?;
 
@@ -119,6 +147,25 @@ pub async fn get_commit_by_hash(
                     dt { "CommitDate:" }
                     dd { (format::time(commit.committer_date)) }
 
+                    @if let Some(since) = commit.unreachable_since {
+                        dt { "Orphaned:" }
+                        dd .commit-orphaned { "since " (format::time(since)) }
+                    }
+
+                    @for culprit in &culprits {
+                        dt { "Culprit:" }
+                        dd title="Bisection narrowed a jump in this metric down to this commit." {
+                            (culprit.metric) " jumped from "
+                            (format::measurement_value(culprit.old_value)) " to "
+                            (format::measurement_value(culprit.new_value))
+                        }
+                    }
+
+                    @for tag in &tags {
+                        dt { "Tag:" }
+                        dd { (format::tag_name(tag)) }
+                    }
+
                     @for commit in parents {
                         dt { "Parent:" }
                         dd { (commit) }
@@ -151,6 +198,17 @@ pub async fn get_commit_by_hash(
                 label for="priority" { "priority" } " of "
                 input id="priority" name="priority" type="number" value="10" min="-2147483648" max="2147483647";
                 "."
+                @if bench_repo.is_some() {
+                    br;
+                    label {
+                        "Bench repo revision: "
+                        input name="bench_hash" type="text" placeholder="HEAD";
+                    } " "
+                    label {
+                        "Benchmarks: "
+                        input name="benchmarks" type="text" placeholder="all, or comma-separated";
+                    }
+                }
             }
         })
         .build();
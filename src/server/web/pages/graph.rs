@@ -76,6 +76,7 @@ struct CommitsResponse {
     committer_date_by_hash: Vec<i64>,
     summary_by_hash: Vec<String>,
     child_parent_index_pairs: Vec<(usize, usize)>,
+    tag_index_pairs: Vec<(usize, String)>,
 }
 
 pub async fn get_graph_commits(
@@ -90,6 +91,7 @@ pub async fn get_graph_commits(
     let mut committer_date_by_hash = vec![];
     let mut summary_by_hash = vec![];
     let mut child_parent_index_pairs = vec![];
+    let mut tag_index_pairs = vec![];
 
     // Fetch main commit info
     let mut rows = sqlx::query!(
@@ -145,6 +147,25 @@ pub async fn get_graph_commits(
     }
     drop(rows);
 
+    // Fetch tags
+    let mut rows = sqlx::query!(
+        "
+        SELECT hash, name
+        FROM refs
+        JOIN commits USING (hash)
+        WHERE reachable = ? AND name LIKE 'refs/tags/%'
+        ORDER BY hash ASC, name ASC
+        ",
+        Reachable::FromTrackedRef,
+    )
+    .fetch(&mut *conn);
+    while let Some(row) = rows.try_next().await? {
+        // The commit is tracked and must thus be in our map.
+        let index = *index_of_hash.get(&row.hash).unwrap();
+        tag_index_pairs.push((index, format::tag_name(&row.name).to_string()));
+    }
+    drop(rows);
+
     Ok(Json(CommitsResponse {
         graph_id: 0, // TODO Implement
         hash_by_hash,
@@ -152,6 +173,7 @@ pub async fn get_graph_commits(
         committer_date_by_hash,
         summary_by_hash,
         child_parent_index_pairs,
+        tag_index_pairs,
     }))
 }
 
@@ -159,6 +181,9 @@ pub async fn get_graph_commits(
 pub struct QueryGraphMeasurements {
     #[serde(default)]
     metric: Vec<String>,
+    /// Include measurements from runs that didn't exit successfully.
+    #[serde(default)]
+    failed: bool,
 }
 
 #[derive(Serialize)]
@@ -166,7 +191,7 @@ pub struct QueryGraphMeasurements {
 struct MeasurementsResponse {
     graph_id: i64,
     data_id: i64,
-    measurements: HashMap<String, Vec<f64>>,
+    measurements: HashMap<String, Vec<Option<f64>>>,
 }
 
 pub async fn get_graph_measurements(
@@ -174,9 +199,55 @@ pub async fn get_graph_measurements(
     State(db): State<SqlitePool>,
     Query(form): Query<QueryGraphMeasurements>,
 ) -> somehow::Result<impl IntoResponse> {
+    let mut tx = db.begin().await?;
+    let conn = tx.acquire().await?;
+
+    // Same order as the hashes in the commits response
+    let hashes = sqlx::query_scalar!(
+        "SELECT hash FROM commits WHERE reachable = ? ORDER BY hash ASC",
+        Reachable::FromTrackedRef,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let index_of_hash = hashes
+        .into_iter()
+        .enumerate()
+        .map(|(idx, hash)| (hash, idx))
+        .collect::<HashMap<_, _>>();
+
+    let mut measurements = HashMap::new();
+    for metric in form.metric {
+        let mut values = vec![None; index_of_hash.len()];
+
+        let mut rows = sqlx::query!(
+            r#"
+            SELECT
+                hash,
+                AVG(value) AS "value!: f64"
+            FROM run_measurements
+            JOIN runs USING (id)
+            WHERE metric = ? AND (? OR exit_code = 0)
+            GROUP BY hash
+            "#,
+            metric,
+            form.failed,
+        )
+        .fetch(&mut *conn);
+        while let Some(row) = rows.try_next().await? {
+            // Measurements of untracked commits aren't part of the graph.
+            if let Some(idx) = index_of_hash.get(&row.hash) {
+                values[*idx] = Some(row.value);
+            }
+        }
+        drop(rows);
+
+        measurements.insert(metric, values);
+    }
+
     Ok(Json(MeasurementsResponse {
-        graph_id: 0,                  // TODO Implement
-        data_id: 0,                   // TODO Implement
-        measurements: HashMap::new(), // TODO Implement
+        graph_id: 0, // TODO Implement
+        data_id: 0,  // TODO Implement
+        measurements,
     }))
 }
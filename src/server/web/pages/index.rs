@@ -6,11 +6,17 @@ use sqlx::SqlitePool;
 use crate::{
     config::ServerConfig,
     primitive::Reachable,
-    server::web::{
-        components,
-        page::{Page, Tab},
-        paths::{PathAdminRefsTrack, PathAdminRefsUntrack, PathAdminRefsUpdate, PathIndex},
-        server_config_ext::ServerConfigExt,
+    server::{
+        format, git,
+        web::{
+            components,
+            page::{Page, Tab},
+            paths::{
+                PathAdminRefsTrack, PathAdminRefsUntrack, PathAdminRefsUpdate, PathIndex,
+                PathOrphans,
+            },
+            server_config_ext::{AbsPath, ServerConfigExt},
+        },
     },
     somehow,
 };
@@ -19,6 +25,78 @@ struct Ref {
     name: String,
     commit: Markup,
     tracked: bool,
+    remote: Option<&'static str>,
+}
+
+fn remote_of(config: &'static ServerConfig, name: &str) -> Option<&'static str> {
+    config
+        .repo_remotes
+        .iter()
+        .find(|remote| git::is_mapped_ref(&remote.refspecs, name))
+        .map(|remote| remote.name.as_str())
+}
+
+/// Group refs by the remote they are fetched from, in the order the remotes
+/// are configured. Refs that don't belong to any remote come first.
+fn group_by_remote(
+    config: &'static ServerConfig,
+    refs: Vec<Ref>,
+) -> Vec<(Option<&'static str>, Vec<Ref>)> {
+    let mut groups = vec![(None, vec![])];
+    for remote in &config.repo_remotes {
+        groups.push((Some(remote.name.as_str()), vec![]));
+    }
+
+    for reference in refs {
+        let group = groups
+            .iter_mut()
+            .find(|(remote, _)| *remote == reference.remote)
+            .expect("every remote has a group");
+        group.1.push(reference);
+    }
+
+    groups.retain(|(_, refs)| !refs.is_empty());
+    groups
+}
+
+fn refs_list(
+    config: &'static ServerConfig,
+    refs: Vec<Ref>,
+    action: AbsPath,
+    button: &str,
+    tags: bool,
+) -> Markup {
+    let headings = config.repo_remotes.len() > 1;
+    html! {
+        form method="post" action=(action) {
+            @for (remote, refs) in group_by_remote(config, refs) {
+                @if headings {
+                    p .refs-remote {
+                        @if let Some(remote) = remote {
+                            "Remote " (remote)
+                        } @else {
+                            "Other"
+                        }
+                    }
+                }
+                dl {
+                    @for r#ref in refs {
+                        dt {
+                            @if tags {
+                                (format::tag_name(&r#ref.name))
+                            } @else {
+                                (r#ref.name)
+                            }
+                            " ["
+                            button .linkish name="ref" value=(r#ref.name) { (button) }
+                            "]"
+                        }
+                        dd { (r#ref.commit) }
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub async fn get_index(
@@ -44,15 +122,32 @@ pub async fn get_index(
         name: r.name.clone(),
         commit: components::link_commit(config, r.hash, &r.message, r.reachable),
         tracked: r.tracked != 0,
+        remote: remote_of(config, &r.name),
     })
     .try_collect::<Vec<_>>()
     .await?;
 
+    let orphaned_runs = sqlx::query_scalar!(
+        "
+        SELECT COUNT(*) FROM runs
+        JOIN commits USING (hash)
+        WHERE reachable = ?
+        ",
+        Reachable::Unreachable,
+    )
+    .fetch_one(&db)
+    .await?;
+
+    // Tags usually outnumber branches and are rarely tracked, so untracked
+    // tags get their own list.
     let mut tracked_refs = vec![];
     let mut untracked_refs = vec![];
+    let mut untracked_tags = vec![];
     for reference in refs {
         if reference.tracked {
             tracked_refs.push(reference);
+        } else if reference.name.starts_with("refs/tags/") {
+            untracked_tags.push(reference);
         } else {
             untracked_refs.push(reference);
         }
@@ -65,37 +160,25 @@ pub async fn get_index(
             h2 { "Refs" }
             details .refs-list open {
                 summary { "Tracked (" (tracked_refs.len()) ")" }
-                form method="post" action=(config.path(PathAdminRefsUntrack {})) {
-                    dl {
-                        @for r#ref in tracked_refs {
-                            dt {
-                                (r#ref.name) " ["
-                                button .linkish name="ref" value=(r#ref.name) { "untrack" }
-                                "]"
-                            }
-                            dd { (r#ref.commit) }
-                        }
-                    }
-                }
+                (refs_list(config, tracked_refs, config.path(PathAdminRefsUntrack {}), "untrack", false))
             }
             details .refs-list {
                 summary { "Untracked (" (untracked_refs.len()) ")" }
-                form method="post" action=(config.path(PathAdminRefsTrack {})) {
-                    dl {
-                        @for r#ref in untracked_refs {
-                            dt {
-                                (r#ref.name) " ["
-                                button .linkish name="ref" value=(r#ref.name) { "track" }
-                                "]"
-                            }
-                            dd { (r#ref.commit) }
-                        }
-                    }
-                }
+                (refs_list(config, untracked_refs, config.path(PathAdminRefsTrack {}), "track", false))
+            }
+            details .refs-list {
+                summary { "Tags (" (untracked_tags.len()) ")" }
+                (refs_list(config, untracked_tags, config.path(PathAdminRefsTrack {}), "track", true))
             }
             form method="post" action=(config.path(PathAdminRefsUpdate {})) {
                 button { "Update" }
             }
+            @if orphaned_runs > 0 {
+                p {
+                    a href=(config.path(PathOrphans {})) { (orphaned_runs) " orphaned runs" }
+                    " belong to commits that can't be reached from any ref."
+                }
+            }
         })
         .build();
 
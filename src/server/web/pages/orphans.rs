@@ -0,0 +1,98 @@
+use axum::{extract::State, response::IntoResponse};
+use futures::TryStreamExt;
+use maud::{html, Markup};
+use sqlx::SqlitePool;
+
+use crate::{
+    config::ServerConfig,
+    primitive::{QueueKind, Reachable, Timestamp},
+    server::{
+        format,
+        web::{components, page::Page, paths::PathOrphans},
+    },
+    somehow,
+};
+
+struct Orphan {
+    commit: Markup,
+    since: Option<String>,
+    runs: Vec<Markup>,
+}
+
+/// Runs of commits that can no longer be reached from any ref, usually because
+/// a branch was force-pushed or deleted.
+pub async fn get_orphans(
+    _path: PathOrphans,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+) -> somehow::Result<impl IntoResponse> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            hash,
+            message,
+            unreachable_since AS "unreachable_since: Timestamp",
+            id,
+            start AS "start: Timestamp",
+            exit_code,
+            kind AS "kind: QueueKind"
+        FROM runs
+        JOIN commits USING (hash)
+        WHERE reachable = ?
+        ORDER BY unixepoch(unreachable_since) DESC, hash ASC, unixepoch(start) ASC
+        "#,
+        Reachable::Unreachable,
+    )
+    .fetch(&db)
+    .try_collect::<Vec<_>>()
+    .await?;
+
+    let mut orphans: Vec<(String, Orphan)> = vec![];
+    for row in rows {
+        let run = components::link_run_date(config, row.id, row.start, row.exit_code, row.kind);
+        match orphans.last_mut() {
+            Some((hash, orphan)) if *hash == row.hash => orphan.runs.push(run),
+            _ => orphans.push((
+                row.hash.clone(),
+                Orphan {
+                    commit: components::link_commit(
+                        config,
+                        row.hash,
+                        &row.message,
+                        Reachable::Unreachable,
+                    ),
+                    since: row.unreachable_since.map(format::time),
+                    runs: vec![run],
+                },
+            )),
+        }
+    }
+
+    let html = Page::new(config)
+        .title("orphaned runs")
+        .body(html! {
+            h2 { "Orphaned runs" }
+            p {
+                "These runs belong to commits that can't be reached from any ref anymore, "
+                "for example because a branch was force-pushed. "
+                "Their commits are kept so the runs aren't lost."
+            }
+            @if orphans.is_empty() {
+                p { "There aren't any orphaned runs." }
+            }
+            @for (_, orphan) in orphans {
+                h3 { (orphan.commit) }
+                @if let Some(since) = orphan.since {
+                    p { "Orphaned since " (since) "." }
+                }
+                ul {
+                    @for run in orphan.runs {
+                        li { (run) }
+                    }
+                }
+            }
+        })
+        .build();
+
+    Ok(html)
+}
@@ -0,0 +1,35 @@
+use axum::{extract::State, response::IntoResponse};
+use maud::html;
+
+use crate::{
+    config::ServerConfig,
+    server::web::{
+        page::{Page, Tab},
+        paths::PathIndex,
+        server_config_ext::ServerConfigExt,
+    },
+};
+
+/// The index of a server hosting multiple projects.
+pub async fn get_projects(
+    _path: PathIndex,
+    State(config): State<&'static ServerConfig>,
+) -> impl IntoResponse {
+    Page::new(config)
+        .title("projects")
+        .tab(Tab::Index)
+        .body(html! {
+            h2 { "Projects" }
+            ul {
+                @for project in &config.projects {
+                    li {
+                        a href=(project.config.path(PathIndex {})) { (project.config.repo_name) }
+                        @if project.config.repo_name != project.name {
+                            " (" (project.name) ")"
+                        }
+                    }
+                }
+            }
+        })
+        .build()
+}
@@ -14,7 +14,7 @@ use sqlx::SqlitePool;
 
 use crate::{
     config::ServerConfig,
-    primitive::{Reachable, Timestamp},
+    primitive::{QueueKind, Reachable, Timestamp},
     server::{
         format,
         web::{
@@ -28,6 +28,7 @@ use crate::{
             server_config_ext::{AbsPath, ServerConfigExt},
         },
         workers::{WorkerInfo, Workers},
+        BenchRepo,
     },
     shared::WorkerStatus,
     somehow,
@@ -41,6 +42,7 @@ enum Status {
 
 struct Worker {
     link: Markup,
+    version: String,
     status: Status,
 }
 
@@ -52,6 +54,10 @@ struct Task {
     commit: Markup,
     since: String,
     priority: i64,
+    failures: i64,
+    kind: QueueKind,
+    bench_hash: Option<String>,
+    benchmarks: Option<String>,
     workers: Vec<Markup>,
     odd: bool,
 }
@@ -94,6 +100,7 @@ async fn get_workers(
 
         result.push(Worker {
             link: components::link_worker(config, name.clone()),
+            version: format::worker_version(info.version.as_deref(), info.protocol),
             status,
         })
     }
@@ -123,7 +130,11 @@ async fn get_queue_data(
             message,
             reachable AS "reachable: Reachable",
             date AS "date: Timestamp",
-            priority
+            priority,
+            failures,
+            kind AS "kind: QueueKind",
+            bench_hash,
+            benchmarks
         FROM queue
         JOIN commits USING (hash)
         ORDER BY priority DESC, unixepoch(date) DESC, hash ASC
@@ -141,6 +152,10 @@ async fn get_queue_data(
         commit: components::link_commit(config, r.hash, &r.message, r.reachable),
         since: format::delta_from_now(r.date),
         priority: r.priority,
+        failures: r.failures,
+        kind: r.kind,
+        bench_hash: r.bench_hash,
+        benchmarks: r.benchmarks,
         odd: false,
     })
     .try_collect::<Vec<_>>()
@@ -169,12 +184,14 @@ fn page_inner(workers: Vec<Worker>, tasks: Vec<Task>) -> Markup {
                 thead {
                     tr {
                         th { "worker" }
+                        th { "version" }
                         th { "status" }
                     }
                 }
                 tbody {
                     @for worker in workers { tr {
                         td { (worker.link) }
+                        td { (worker.version) }
                         td { @match worker.status {
                             Status::Idle => "idle",
                             Status::Busy => "busy",
@@ -197,7 +214,31 @@ fn page_inner(workers: Vec<Worker>, tasks: Vec<Task>) -> Markup {
                 }
                 tbody {
                     @for task in tasks { tr .odd[task.odd] {
-                        td { (task.commit) }
+                        td {
+                            (task.commit)
+                            @if task.kind == QueueKind::Bisect {
+                                " " span .queue-kind title="Added to narrow down a jump in a metric." { "(bisection)" }
+                            }
+                            @if task.kind == QueueKind::Scheduled {
+                                " " span .queue-kind title="Added by a schedule." { "(scheduled)" }
+                            }
+                            @if task.kind == QueueKind::Report {
+                                " " span .queue-kind title="Added to compare a ref against its merge base." { "(report)" }
+                            }
+                            @if let Some(benchmarks) = task.benchmarks {
+                                " " span .queue-kind title="Only these benchmarks will be run." { "(" (benchmarks) ")" }
+                            }
+                            @if let Some(bench_hash) = task.bench_hash {
+                                " " span .queue-kind title=(format!("The bench repo at {bench_hash} will be used.")) {
+                                    "(bench repo at " (&bench_hash[..bench_hash.len().min(8)]) ")"
+                                }
+                            }
+                            @if task.failures > 0 {
+                                " " span .run-failed title="Runs of this commit failed, it will be retried." {
+                                    "(failed " (task.failures) "x)"
+                                }
+                            }
+                        }
                         td {
                             (task.since) " ["
                             a href=(task.link_delete) title="Delete from queue" { "del" }
@@ -235,15 +276,60 @@ pub async fn get_queue_inner(
     Ok(page_inner(workers, tasks))
 }
 
+/// How many of the most recently found culprits are listed.
+const RECENT_CULPRITS: i64 = 10;
+
+struct Culprit {
+    commit: Markup,
+    metric: String,
+    old_value: f64,
+    new_value: f64,
+    found: String,
+}
+
+async fn get_culprits(config: &ServerConfig, db: &SqlitePool) -> somehow::Result<Vec<Culprit>> {
+    let culprits = sqlx::query!(
+        r#"
+        SELECT
+            hash,
+            message,
+            reachable AS "reachable: Reachable",
+            metric,
+            old_value,
+            new_value,
+            found AS "found: Timestamp"
+        FROM bisect_culprits
+        JOIN commits USING (hash)
+        ORDER BY unixepoch(found) DESC, hash ASC, metric ASC
+        LIMIT ?
+        "#,
+        RECENT_CULPRITS,
+    )
+    .fetch(db)
+    .map_ok(|r| Culprit {
+        commit: components::link_commit(config, r.hash, &r.message, r.reachable),
+        metric: r.metric,
+        old_value: r.old_value,
+        new_value: r.new_value,
+        found: format::delta_from_now(r.found),
+    })
+    .try_collect::<Vec<_>>()
+    .await?;
+
+    Ok(culprits)
+}
+
 pub async fn get_queue(
     _path: PathQueue,
     State(config): State<&'static ServerConfig>,
     State(db): State<SqlitePool>,
     State(workers): State<Arc<Mutex<Workers>>>,
+    State(bench_repo): State<Option<BenchRepo>>,
 ) -> somehow::Result<impl IntoResponse> {
     let sorted_workers = sorted_workers(&workers);
     let workers = get_workers(config, &db, &sorted_workers).await?;
     let tasks = get_queue_data(config, &db, &sorted_workers).await?;
+    let culprits = get_culprits(config, &db).await?;
 
     let html = Page::new(config)
         .title(format!("queue ({})", tasks.len()))
@@ -262,8 +348,51 @@ pub async fn get_queue(
                     "Priority: "
                     input #priority name="priority" type="number" value="-1" min="-2147483648" max="2147483647";
                 } " "
+                label {
+                    "Strategy: "
+                    select name="strategy" {
+                        option value="recent" title="The most recent commits without runs" { "most recent" }
+                        option value="even" title="Commits evenly spaced across the entire history" { "evenly spaced" }
+                        option value="gaps" title="Commits in the middle of the largest gaps between commits with runs" { "largest gaps first" }
+                        option value="daily" title="The most recent commit of each day without runs" { "one per day" }
+                        option value="weekly" title="The most recent commit of each week without runs" { "one per week" }
+                    }
+                } " "
+                @if bench_repo.is_some() {
+                    label {
+                        "Bench repo revision: "
+                        input name="bench_hash" type="text" placeholder="HEAD";
+                    } " "
+                    label {
+                        "Benchmarks: "
+                        input name="benchmarks" type="text" placeholder="all, or comma-separated";
+                    } " "
+                }
                 button { "Add batch to queue" }
             }
+            @if !culprits.is_empty() {
+                h2 { "Recently bisected" }
+                table .queue-culprits {
+                    thead {
+                        tr {
+                            th { "commit" }
+                            th { "metric" }
+                            th { "from" }
+                            th { "to" }
+                            th { "found" }
+                        }
+                    }
+                    tbody {
+                        @for culprit in culprits { tr {
+                            td { (culprit.commit) }
+                            td { (culprit.metric) }
+                            td { (format::measurement_value(culprit.old_value)) }
+                            td { (format::measurement_value(culprit.new_value)) }
+                            td { (culprit.found) }
+                        } }
+                    }
+                }
+            }
         })
         .build();
 
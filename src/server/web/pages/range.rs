@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures::TryStreamExt;
+use maud::{html, Markup};
+use sqlx::SqlitePool;
+
+use crate::{
+    config::ServerConfig,
+    primitive::Reachable,
+    server::{
+        format, refs,
+        web::{components, page::Page, paths::PathRange},
+    },
+    somehow,
+};
+
+enum Status {
+    Measured,
+    Failed,
+    Queued,
+    Unmeasured,
+}
+
+struct RangeCommit {
+    commit: Markup,
+    status: Status,
+}
+
+struct Endpoint {
+    name: String,
+    hash: String,
+    commit: Markup,
+}
+
+pub(super) struct Value {
+    pub(super) value: f64,
+    pub(super) unit: Option<String>,
+}
+
+async fn endpoint(
+    config: &'static ServerConfig,
+    db: &SqlitePool,
+    name: &str,
+) -> somehow::Result<Option<Endpoint>> {
+    let Some(hash) = refs::resolve(db, name).await? else {
+        return Ok(None);
+    };
+
+    let commit = sqlx::query!(
+        r#"
+        SELECT message, reachable AS "reachable: Reachable"
+        FROM commits
+        WHERE hash = ?
+        "#,
+        hash,
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(Some(Endpoint {
+        name: name.to_string(),
+        commit: components::link_commit(config, hash.clone(), &commit.message, commit.reachable),
+        hash,
+    }))
+}
+
+/// Average value of each metric across all successful runs of a commit.
+pub(super) async fn metric_values(
+    db: &SqlitePool,
+    hash: &str,
+) -> somehow::Result<HashMap<String, Value>> {
+    let values = sqlx::query!(
+        r#"
+        SELECT metric AS "metric!", AVG(value) AS "value!: f64", MAX(unit) AS unit
+        FROM run_measurements
+        JOIN runs USING (id)
+        WHERE hash = ? AND exit_code = 0
+        GROUP BY metric
+        "#,
+        hash,
+    )
+    .fetch(db)
+    .map_ok(|r| {
+        let value = Value {
+            value: r.value,
+            unit: r.unit,
+        };
+        (r.metric, value)
+    })
+    .try_collect::<HashMap<_, _>>()
+    .await?;
+
+    Ok(values)
+}
+
+pub(super) fn relative_change(old: f64, new: f64) -> String {
+    if old == new {
+        "±0%".to_string()
+    } else if old == 0.0 {
+        "new".to_string()
+    } else {
+        format!("{:+.2}%", 100.0 * (new - old) / old.abs())
+    }
+}
+
+/// Commits reachable from `head` but not from `base`, like `git log base..head`.
+pub async fn get_range(
+    path: PathRange,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+) -> somehow::Result<Response> {
+    let Some((base, head)) = path.range.split_once("..") else {
+        return Ok((StatusCode::NOT_FOUND, "range must look like base..head").into_response());
+    };
+    let Some(base) = endpoint(config, &db, base).await? else {
+        return Ok((StatusCode::NOT_FOUND, "unknown base").into_response());
+    };
+    let Some(head) = endpoint(config, &db, head).await? else {
+        return Ok((StatusCode::NOT_FOUND, "unknown head").into_response());
+    };
+
+    let commits = sqlx::query!(
+        r#"
+        WITH RECURSIVE
+            excluded (hash) AS (
+                SELECT ?
+                UNION
+                SELECT parent FROM commit_edges
+                JOIN excluded ON child = hash
+            ),
+            included (hash) AS (
+                SELECT ?
+                UNION
+                SELECT parent FROM commit_edges
+                JOIN included ON child = hash
+                WHERE parent NOT IN excluded
+            )
+        SELECT
+            hash AS "hash!: String",
+            message,
+            reachable AS "reachable: Reachable",
+            EXISTS (
+                SELECT * FROM runs
+                WHERE runs.hash = commits.hash AND exit_code = 0
+            ) AS "measured!: bool",
+            EXISTS (SELECT * FROM runs WHERE runs.hash = commits.hash) AS "attempted!: bool",
+            EXISTS (SELECT * FROM queue WHERE queue.hash = commits.hash) AS "queued!: bool"
+        FROM included
+        JOIN commits USING (hash)
+        WHERE hash NOT IN excluded
+        ORDER BY unixepoch(committer_date) DESC, hash ASC
+        "#,
+        base.hash,
+        head.hash,
+    )
+    .fetch(&db)
+    .map_ok(|r| RangeCommit {
+        commit: components::link_commit(config, r.hash, &r.message, r.reachable),
+        status: if r.measured {
+            Status::Measured
+        } else if r.queued {
+            Status::Queued
+        } else if r.attempted {
+            Status::Failed
+        } else {
+            Status::Unmeasured
+        },
+    })
+    .try_collect::<Vec<_>>()
+    .await?;
+
+    let measured = commits
+        .iter()
+        .filter(|c| matches!(c.status, Status::Measured))
+        .count();
+
+    let base_values = metric_values(&db, &base.hash).await?;
+    let head_values = metric_values(&db, &head.hash).await?;
+    let metrics = base_values
+        .keys()
+        .chain(head_values.keys())
+        .map(|metric| (metric, (base_values.get(metric), head_values.get(metric))))
+        .collect::<BTreeMap<_, _>>();
+
+    let html = Page::new(config)
+        .title(format!("{}..{}", base.name, head.name))
+        .body(html! {
+            h2 { "Range" }
+            div .commit-like .range {
+                span .title { "range " (base.name) ".." (head.name) }
+                dl {
+                    dt { "Base:" }
+                    dd { (base.commit) }
+
+                    dt { "Head:" }
+                    dd { (head.commit) }
+
+                    dt { "Commits:" }
+                    dd { (commits.len()) " (" (measured) " measured)" }
+                }
+            }
+        })
+        .body(html! {
+            h2 { "Comparison" }
+            @if base_values.is_empty() || head_values.is_empty() {
+                p { "Base and head both need a successful run to be compared." }
+            }
+            @if !metrics.is_empty() {
+                table .range-comparison {
+                    thead {
+                        tr {
+                            th { "metric" }
+                            th { "base" }
+                            th { "head" }
+                            th { "change" }
+                            th { "unit" }
+                        }
+                    }
+                    tbody {
+                        @for (metric, (old, new)) in metrics { tr {
+                            td { (metric) }
+                            td { @if let Some(old) = old { (format::measurement_value(old.value)) } }
+                            td { @if let Some(new) = new { (format::measurement_value(new.value)) } }
+                            td {
+                                @if let (Some(old), Some(new)) = (old, new) {
+                                    (relative_change(old.value, new.value))
+                                }
+                            }
+                            td { (new.or(old).and_then(|v| v.unit.as_deref()).unwrap_or_default()) }
+                        } }
+                    }
+                }
+            }
+        })
+        .body(html! {
+            h2 { "Commits" }
+            @if commits.is_empty() {
+                p { "Head can be reached from base, so there are no commits in this range." }
+            } @else {
+                ul .range-commits {
+                    @for commit in commits {
+                        li {
+                            (commit.commit) " "
+                            @match commit.status {
+                                Status::Measured => span .range-measured { "(measured)" },
+                                Status::Queued => span .range-queued { "(queued)" },
+                                Status::Failed => span .run-failed { "(failed)" },
+                                Status::Unmeasured => span .range-unmeasured { "(not measured)" },
+                            }
+                        }
+                    }
+                }
+            }
+        })
+        .build();
+
+    Ok(html.into_response())
+}
@@ -0,0 +1,349 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::Query;
+use maud::html;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::{
+    config::ServerConfig,
+    primitive::Reachable,
+    server::{
+        format,
+        report::{self, Baseline},
+        web::{
+            components,
+            page::Page,
+            paths::{PathRange, PathReport},
+            server_config_ext::ServerConfigExt,
+        },
+        Repo,
+    },
+    somehow,
+};
+
+use super::range::{metric_values, relative_change};
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    #[default]
+    Html,
+    Markdown,
+    Json,
+}
+
+#[derive(Deserialize)]
+pub struct QueryReport {
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// Both commits have successful runs.
+    Complete,
+    /// At least one of the commits is still waiting for a run.
+    Pending,
+    /// At least one of the commits only has failed runs.
+    Failed,
+}
+
+impl Status {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Complete => "Both commits have been measured.",
+            Self::Pending => "Waiting for runs to finish.",
+            Self::Failed => "Runs of at least one of the commits failed.",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MetricDelta {
+    metric: String,
+    base: Option<f64>,
+    head: Option<f64>,
+    /// Relative change from base to head, e.g. 0.1 for an increase by 10%.
+    change: Option<f64>,
+    unit: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    r#ref: String,
+    head: String,
+    base_ref: String,
+    merge_base: String,
+    status: Status,
+    metrics: Vec<MetricDelta>,
+}
+
+async fn status(db: &SqlitePool, hash: &str) -> somehow::Result<Status> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (
+                SELECT * FROM runs
+                WHERE hash = ?1 AND exit_code = 0
+            ) AS "measured!: bool",
+            EXISTS (SELECT * FROM runs WHERE hash = ?1) AS "attempted!: bool",
+            EXISTS (SELECT * FROM queue WHERE hash = ?1) AS "queued!: bool"
+        "#,
+        hash,
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(if row.measured {
+        Status::Complete
+    } else if row.attempted && !row.queued {
+        Status::Failed
+    } else {
+        Status::Pending
+    })
+}
+
+fn combine(a: Status, b: Status) -> Status {
+    match (a, b) {
+        (Status::Failed, _) | (_, Status::Failed) => Status::Failed,
+        (Status::Pending, _) | (_, Status::Pending) => Status::Pending,
+        (Status::Complete, Status::Complete) => Status::Complete,
+    }
+}
+
+async fn build_report(
+    config: &'static ServerConfig,
+    db: &SqlitePool,
+    repo: Repo,
+    name: String,
+    head: String,
+) -> somehow::Result<Option<Report>> {
+    let mut conn = db.acquire().await?;
+    let baseline = report::baseline(config, &mut conn, repo, &name, &head).await?;
+    drop(conn);
+    let Some(Baseline {
+        r#ref: base_ref,
+        hash: merge_base,
+    }) = baseline
+    else {
+        return Ok(None);
+    };
+
+    let status = combine(status(db, &merge_base).await?, status(db, &head).await?);
+
+    let base_values = metric_values(db, &merge_base).await?;
+    let head_values = metric_values(db, &head).await?;
+    let metrics = base_values
+        .keys()
+        .chain(head_values.keys())
+        .map(|metric| (metric, (base_values.get(metric), head_values.get(metric))))
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(metric, (old, new))| MetricDelta {
+            metric: metric.clone(),
+            base: old.map(|v| v.value),
+            head: new.map(|v| v.value),
+            change: match (old, new) {
+                (Some(old), Some(new)) if old.value != 0.0 => {
+                    Some((new.value - old.value) / old.value.abs())
+                }
+                _ => None,
+            },
+            unit: new.or(old).and_then(|v| v.unit.clone()),
+        })
+        .collect();
+
+    Ok(Some(Report {
+        r#ref: name,
+        head,
+        base_ref,
+        merge_base,
+        status,
+        metrics,
+    }))
+}
+
+fn value(value: Option<f64>) -> String {
+    value.map(format::measurement_value).unwrap_or_default()
+}
+
+fn change(delta: &MetricDelta) -> String {
+    match (delta.base, delta.head) {
+        (Some(old), Some(new)) => relative_change(old, new),
+        _ => String::new(),
+    }
+}
+
+fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(8)]
+}
+
+fn render_markdown(report: &Report) -> String {
+    let mut md = String::new();
+    let _ = writeln!(md, "### Benchmarks of `{}`", report.r#ref);
+    let _ = writeln!(md);
+    let _ = writeln!(
+        md,
+        "Comparing `{}` against `{}`, its merge base with `{}`.",
+        short(&report.head),
+        short(&report.merge_base),
+        report.base_ref,
+    );
+    let _ = writeln!(md, "{}", report.status.describe());
+
+    if !report.metrics.is_empty() {
+        let _ = writeln!(md);
+        let _ = writeln!(md, "| Metric | Base | Head | Change | Unit |");
+        let _ = writeln!(md, "| --- | ---: | ---: | ---: | --- |");
+        for delta in &report.metrics {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} |",
+                delta.metric.replace('|', "\\|"),
+                value(delta.base),
+                value(delta.head),
+                change(delta),
+                delta
+                    .unit
+                    .as_deref()
+                    .unwrap_or_default()
+                    .replace('|', "\\|"),
+            );
+        }
+    }
+
+    md
+}
+
+/// Compare a ref against its merge base with the ref it was branched off from.
+///
+/// Available as html, markdown and json, so CI can post it on pull requests.
+pub async fn get_report(
+    path: PathReport,
+    State(config): State<&'static ServerConfig>,
+    State(db): State<SqlitePool>,
+    State(repo): State<Option<Repo>>,
+    Query(query): Query<QueryReport>,
+) -> somehow::Result<Response> {
+    // Merge bases are found in the repo, not the db
+    let Some(repo) = repo else {
+        return Ok((StatusCode::NOT_FOUND, "no repo to find a baseline in").into_response());
+    };
+
+    let Some(r#ref) = sqlx::query!(
+        "
+        SELECT name, hash FROM refs
+        WHERE name = ?1
+        OR name = 'refs/heads/' || ?1
+        OR name = 'refs/remotes/' || ?1
+        ORDER BY name = ?1 DESC, name ASC
+        LIMIT 1
+        ",
+        path.name,
+    )
+    .fetch_optional(&db)
+    .await?
+    else {
+        return Ok((StatusCode::NOT_FOUND, "unknown ref").into_response());
+    };
+
+    let Some(report) = build_report(config, &db, repo, r#ref.name, r#ref.hash).await? else {
+        return Ok((StatusCode::NOT_FOUND, "no baseline to compare against").into_response());
+    };
+
+    match query.format {
+        ReportFormat::Json => return Ok(Json(report).into_response()),
+        ReportFormat::Markdown => {
+            let headers = [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")];
+            return Ok((headers, render_markdown(&report)).into_response());
+        }
+        ReportFormat::Html => {}
+    }
+
+    let commits = sqlx::query!(
+        r#"
+        SELECT hash, message, reachable AS "reachable: Reachable"
+        FROM commits
+        WHERE hash = ? OR hash = ?
+        "#,
+        report.head,
+        report.merge_base,
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|r| {
+        let link = components::link_commit(config, r.hash.clone(), &r.message, r.reachable);
+        (r.hash, link)
+    })
+    .collect::<BTreeMap<_, _>>();
+
+    let range = config.path(PathRange {
+        range: format!("{}..{}", report.merge_base, report.head),
+    });
+
+    let html = Page::new(config)
+        .title(format!("report on {}", report.r#ref))
+        .body(html! {
+            h2 { "Report" }
+            div .commit-like .range {
+                span .title { "report " (report.r#ref) }
+                dl {
+                    dt { "Head:" }
+                    dd { (commits[&report.head]) }
+
+                    dt { "Merge base:" }
+                    dd { (commits[&report.merge_base]) " with " (report.base_ref) }
+
+                    dt { "Status:" }
+                    dd .run-failed[matches!(report.status, Status::Failed)] { (report.status.describe()) }
+                }
+            }
+            p {
+                "See the " a href=(range) { "commits in between" } ", "
+                "or get this report as "
+                a href={ (config.path(PathReport { name: report.r#ref.clone() })) "?format=markdown" } { "markdown" }
+                " or "
+                a href={ (config.path(PathReport { name: report.r#ref.clone() })) "?format=json" } { "json" }
+                "."
+            }
+        })
+        .body(html! {
+            h2 { "Comparison" }
+            @if report.metrics.is_empty() {
+                p { "There are no measurements yet." }
+            } @else {
+                table .range-comparison {
+                    thead {
+                        tr {
+                            th { "metric" }
+                            th { "base" }
+                            th { "head" }
+                            th { "change" }
+                            th { "unit" }
+                        }
+                    }
+                    tbody {
+                        @for delta in &report.metrics { tr {
+                            td { (delta.metric) }
+                            td { (value(delta.base)) }
+                            td { (value(delta.head)) }
+                            td { (change(delta)) }
+                            td { (delta.unit.as_deref().unwrap_or_default()) }
+                        } }
+                    }
+                }
+            }
+        })
+        .build();
+
+    Ok(html.into_response())
+}
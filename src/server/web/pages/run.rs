@@ -1,6 +1,6 @@
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
 use futures::TryStreamExt;
@@ -9,10 +9,15 @@ use sqlx::SqlitePool;
 
 use crate::{
     config::ServerConfig,
-    primitive::{Reachable, Timestamp},
+    primitive::{QueueKind, Reachable, Timestamp},
     server::{
         format,
-        web::{components, page::Page, paths::PathRunById},
+        web::{
+            components,
+            page::Page,
+            paths::{PathRunArtifact, PathRunById},
+            server_config_ext::{AbsPath, ServerConfigExt},
+        },
     },
     somehow,
 };
@@ -23,6 +28,22 @@ struct Measurement {
     unit: String,
 }
 
+struct Artifact {
+    link: AbsPath,
+    name: String,
+    size: String,
+}
+
+struct Phase {
+    name: String,
+    offset: String,
+    duration: String,
+    exit_code: i64,
+    output_start: i64,
+    bar_left: f64,
+    bar_width: f64,
+}
+
 struct Line {
     err: bool,
     text: String,
@@ -42,6 +63,7 @@ async fn from_finished_run(
             start AS "start: Timestamp",
             end AS "end: Timestamp",
             exit_code,
+            kind AS "kind: QueueKind",
             message,
             reachable AS "reachable: Reachable"
         FROM runs
@@ -93,6 +115,74 @@ async fn from_finished_run(
     .try_collect::<Vec<_>>()
     .await?;
 
+    let run_duration = (run.end.0 - run.start.0).as_seconds_f64();
+    let phases = sqlx::query!(
+        r#"
+        SELECT
+            name,
+            start AS "start: Timestamp",
+            end AS "end: Timestamp",
+            exit_code,
+            output_start
+        FROM run_phases
+        WHERE id = ?
+        ORDER BY idx ASC
+        "#,
+        id,
+    )
+    .fetch(db)
+    .map_ok(|r| {
+        let offset = r.start.0 - run.start.0;
+        let duration = r.end.0 - r.start.0;
+        let (bar_left, bar_width) = if run_duration > 0.0 {
+            (
+                100.0 * offset.as_seconds_f64() / run_duration,
+                100.0 * duration.as_seconds_f64() / run_duration,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+        Phase {
+            name: r.name,
+            offset: format!("+{}", format::duration(offset)),
+            duration: format::duration(duration),
+            exit_code: r.exit_code,
+            output_start: r.output_start,
+            bar_left,
+            bar_width,
+        }
+    })
+    .try_collect::<Vec<_>>()
+    .await?;
+
+    let result = if run.exit_code == 0 {
+        "success".to_string()
+    } else if let Some(phase) = phases.iter().find(|p| p.exit_code != 0) {
+        format!("{} failed (exit code {})", phase.name, phase.exit_code)
+    } else {
+        format!("failed (exit code {})", run.exit_code)
+    };
+
+    let artifacts = sqlx::query!(
+        "
+        SELECT name, size FROM run_artifacts
+        WHERE id = ?
+        ORDER BY name ASC
+        ",
+        id,
+    )
+    .fetch(db)
+    .map_ok(|r| Artifact {
+        link: config.path(PathRunArtifact {
+            id: id.to_string(),
+            name: r.name.clone(),
+        }),
+        name: r.name,
+        size: format::size(r.size),
+    })
+    .try_collect::<Vec<_>>()
+    .await?;
+
     let commit = components::link_commit(config, run.hash, &run.message, run.reachable);
 
     let html = Page::new(config)
@@ -117,8 +207,43 @@ async fn from_finished_run(
                     dt { "Duration:" }
                     dd { (format::duration(run.end.0 - run.start.0)) }
 
-                    dt { "Exit code:" }
-                    dd { (run.exit_code) }
+                    dt { "Result:" }
+                    dd .run-failed[run.exit_code != 0] { (result) }
+
+                    @if run.kind == QueueKind::Scheduled {
+                        dt { "Trigger:" }
+                        dd .run-scheduled { "scheduled" }
+                    }
+                }
+            }
+        })
+        .body(html! {
+            @if !phases.is_empty() {
+                h2 { "Timeline" }
+                table .run-timeline {
+                    thead {
+                        tr {
+                            th { "phase" }
+                            th { "start" }
+                            th { "duration" }
+                            th { "exit code" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for (idx, phase) in phases.iter().enumerate() { tr .run-failed[phase.exit_code != 0] {
+                            td { a href={ "#phase-" (idx) } { (phase.name) } }
+                            td { (phase.offset) }
+                            td { (phase.duration) }
+                            td { (phase.exit_code) }
+                            td .timeline {
+                                div .timeline-bar style={
+                                    "margin-left: " (format!("{:.2}", phase.bar_left)) "%; "
+                                    "width: " (format!("{:.2}", phase.bar_width)) "%;"
+                                } {}
+                            }
+                        } }
+                    }
                 }
             }
         })
@@ -141,10 +266,25 @@ async fn from_finished_run(
                 }
             }
         })
+        .body(html! {
+            @if !artifacts.is_empty() {
+                h2 { "Artifacts" }
+                ul {
+                    @for artifact in artifacts {
+                        li { a href=(artifact.link) { (artifact.name) } " (" (artifact.size) ")" }
+                    }
+                }
+            }
+        })
         .body(html! {
             h2 { "Output" }
             div .run-output {
-                @for line in output {
+                @for (i, line) in output.iter().enumerate() {
+                    @for (idx, phase) in phases.iter().enumerate() {
+                        @if phase.output_start == i as i64 {
+                            div .run-output-phase id={ "phase-" (idx) } { "phase " (phase.name) }
+                        }
+                    }
                     pre { (line.text) }
                 }
             }
@@ -166,3 +306,22 @@ pub async fn get_run_by_id(
         Ok(StatusCode::NOT_FOUND.into_response())
     }
 }
+
+pub async fn get_run_artifact(
+    path: PathRunArtifact,
+    State(db): State<SqlitePool>,
+) -> somehow::Result<Response> {
+    let Some(data) = sqlx::query_scalar!(
+        "SELECT data FROM run_artifacts WHERE id = ? AND name = ?",
+        path.id,
+        path.name,
+    )
+    .fetch_optional(&db)
+    .await?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mime = mime_guess::from_path(&path.name).first_or_octet_stream();
+    Ok(([(header::CONTENT_TYPE, mime.as_ref())], data).into_response())
+}
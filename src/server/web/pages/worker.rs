@@ -69,6 +69,9 @@ pub async fn get_worker_by_name(
                     dt { "Connected:" }
                     dd { (format::time(info.first_seen)) }
 
+                    dt { "Version:" }
+                    dd { (format::worker_version(info.version.as_deref(), info.protocol)) }
+
                     @match status {
                         Status::Idle => {
                             dt { "Working on:" }
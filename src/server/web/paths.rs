@@ -31,10 +31,22 @@ pub struct PathGraphMeasurements {}
 #[typed_path("/graph/metrics")]
 pub struct PathGraphMetrics {}
 
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/orphans/")]
+pub struct PathOrphans {}
+
 #[derive(Deserialize, TypedPath)]
 #[typed_path("/queue/")]
 pub struct PathQueue {}
 
+/// A range of commits like `base..head`, where both ends may be refs or
+/// commit hashes.
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/range/*range")]
+pub struct PathRange {
+    pub range: String,
+}
+
 #[derive(Deserialize, TypedPath)]
 #[typed_path("/queue/delete/:hash")]
 pub struct PathQueueDelete {
@@ -45,12 +57,25 @@ pub struct PathQueueDelete {
 #[typed_path("/queue/inner")]
 pub struct PathQueueInner {}
 
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/report/*name")]
+pub struct PathReport {
+    pub name: String,
+}
+
 #[derive(Deserialize, TypedPath)]
 #[typed_path("/run/:id")]
 pub struct PathRunById {
     pub id: String,
 }
 
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/run/:id/artifact/*name")]
+pub struct PathRunArtifact {
+    pub id: String,
+    pub name: String,
+}
+
 #[derive(Deserialize, TypedPath)]
 #[typed_path("/test")]
 pub struct PathTest {}
@@ -101,12 +126,65 @@ pub struct PathAdminRefsUpdate {}
 // Api //
 /////////
 
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/hooks/push")]
+pub struct PathApiHooksPush {}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/openapi.json")]
+pub struct PathApiOpenapiJson {}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/commits/:hash")]
+pub struct PathApiV1CommitByHash {
+    pub hash: String,
+}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/export")]
+pub struct PathApiV1Export {}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/metrics")]
+pub struct PathApiV1Metrics {}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/queue")]
+pub struct PathApiV1Queue {}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/queue/:hash")]
+pub struct PathApiV1QueueByHash {
+    pub hash: String,
+}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/runs")]
+pub struct PathApiV1Runs {}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/v1/runs/:id")]
+pub struct PathApiV1RunById {
+    pub id: String,
+}
+
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/worker/artifact/:id/*name")]
+pub struct PathApiWorkerArtifact {
+    pub id: String,
+    pub name: String,
+}
+
 #[derive(Deserialize, TypedPath)]
 #[typed_path("/api/worker/bench_repo/:hash/tree.tar.gz")]
 pub struct PathApiWorkerBenchRepoByHashTreeTarGz {
     pub hash: String,
 }
 
+#[derive(Deserialize, TypedPath)]
+#[typed_path("/api/worker/projects")]
+pub struct PathApiWorkerProjects {}
+
 #[derive(Deserialize, TypedPath)]
 #[typed_path("/api/worker/repo/:hash/tree.tar.gz")]
 pub struct PathApiWorkerRepoByHashTreeTarGz {
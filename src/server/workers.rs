@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
 
 use time::OffsetDateTime;
 
@@ -15,15 +18,25 @@ pub struct WorkerInfo {
     pub first_seen: Timestamp,
     pub last_seen: Timestamp,
     pub status: WorkerStatus,
+    pub version: Option<String>,
+    pub protocol: u32,
 }
 
 impl WorkerInfo {
-    pub fn new(secret: String, last_seen: Timestamp, status: WorkerStatus) -> Self {
+    pub fn new(
+        secret: String,
+        last_seen: Timestamp,
+        status: WorkerStatus,
+        version: Option<String>,
+        protocol: u32,
+    ) -> Self {
         Self {
             secret,
             first_seen: Timestamp::now(),
             last_seen,
             status,
+            version,
+            protocol,
         }
     }
 }
@@ -31,6 +44,8 @@ impl WorkerInfo {
 pub struct Workers {
     config: &'static ServerConfig,
     workers: HashMap<String, WorkerInfo>,
+    /// Workers that timed out and haven't been reported as disconnected yet.
+    disconnected: Vec<String>,
 }
 
 impl Workers {
@@ -38,16 +53,29 @@ impl Workers {
         Self {
             config,
             workers: HashMap::new(),
+            disconnected: vec![],
         }
     }
 
     pub fn clean(&mut self) -> &mut Self {
         let now = OffsetDateTime::now_utc();
-        self.workers
-            .retain(|_, v| now <= v.last_seen.0 + self.config.worker_timeout);
+        let timeout = self.config.worker_timeout;
+        let disconnected = &mut self.disconnected;
+        self.workers.retain(|name, v| {
+            let alive = now <= v.last_seen.0 + timeout;
+            if !alive {
+                disconnected.push(name.clone());
+            }
+            alive
+        });
         self
     }
 
+    /// Names of the workers removed by [`Self::clean`] since the last call.
+    pub fn take_disconnected(&mut self) -> Vec<String> {
+        mem::take(&mut self.disconnected)
+    }
+
     pub fn verify_secret(&self, name: &str, secret: &str) -> bool {
         if let Some(worker) = self.workers.get(name) {
             worker.secret == secret
@@ -67,7 +95,7 @@ impl Workers {
         &mut self,
         name: &str,
         queue: &[String],
-        bench_method: BenchMethod,
+        bench_method: impl FnOnce(&str) -> BenchMethod,
     ) -> Option<Run> {
         let covered = self
             .workers
@@ -83,8 +111,8 @@ impl Workers {
         let id = id::random_run_id();
         let run = Run {
             id,
+            bench_method: bench_method(&hash),
             hash,
-            bench_method,
             start: Timestamp::now(),
         };
 
@@ -2,40 +2,118 @@
 
 use std::{collections::HashMap, fmt};
 
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{ArrayValidation, InstanceType, Metadata, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::primitive::{Source, Timestamp};
+use crate::primitive::{Direction, Source, Timestamp};
+
+/// Version of the protocol described by this module.
+///
+/// Must be incremented whenever a change would break older workers or
+/// servers, e.g. when a field is renamed or a new field is required.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version of workers the server still understands.
+///
+/// Workers that predate protocol versioning report version 0. They can't
+/// perform bench repo runs with phases or a subset of benchmarks, so they are
+/// rejected.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
 
 fn is_false(b: &bool) -> bool {
     !b
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Lines of output are `[source, line]` pairs. OpenAPI 3.0 can't describe
+/// tuples, so this is a bit looser than the actual format.
+fn output_schema(gen: &mut SchemaGenerator) -> Schema {
+    let line = SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some("A `[source, line]` pair".to_string()),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(
+                Schema::Object(SchemaObject {
+                    subschemas: Some(Box::new(SubschemaValidation {
+                        one_of: Some(vec![
+                            gen.subschema_for::<Source>(),
+                            gen.subschema_for::<String>(),
+                        ]),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                })
+                .into(),
+            ),
+            min_items: Some(2),
+            max_items: Some(2),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+
+    SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(Schema::Object(line).into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "WorkerMeasurement")]
 pub struct Measurement {
     pub value: f64,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unit: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 pub enum BenchMethod {
     Internal,
-    Repo { hash: String },
+    Repo {
+        hash: String,
+        /// Names of the benchmarks to run. If empty, all benchmarks are run.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        benchmarks: Vec<String>,
+    },
 }
 
 impl fmt::Display for BenchMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             BenchMethod::Internal => write!(f, "internal"),
-            BenchMethod::Repo { hash } => write!(f, "bench repo, hash {hash}"),
+            BenchMethod::Repo { hash, benchmarks } if benchmarks.is_empty() => {
+                write!(f, "bench repo, hash {hash}")
+            }
+            BenchMethod::Repo { hash, benchmarks } => {
+                write!(
+                    f,
+                    "bench repo, hash {hash}, benchmarks {}",
+                    benchmarks.join(", ")
+                )
+            }
         }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "WorkerRun")]
 pub struct Run {
     pub id: String,
     pub hash: String,
@@ -43,7 +121,7 @@ pub struct Run {
     pub start: Timestamp,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 pub struct UnfinishedRun {
     pub id: String,
     pub hash: String,
@@ -51,10 +129,27 @@ pub struct UnfinishedRun {
     pub start: Timestamp,
 
     #[serde(default)]
+    #[schemars(schema_with = "output_schema")]
     pub last_output: Vec<(Source, String)>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// A named step of a run, e.g. building or benchmarking the commit.
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "WorkerPhase")]
+pub struct Phase {
+    pub name: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub exit_code: i32,
+
+    /// Index of the phase's first line in [`FinishedRun::output`].
+    pub output_start: usize,
+
+    /// Index after the phase's last line in [`FinishedRun::output`].
+    pub output_end: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FinishedRun {
     pub id: String,
     pub hash: String,
@@ -71,13 +166,20 @@ pub struct FinishedRun {
     pub exit_code: i32,
 
     #[serde(default)]
+    #[schemars(schema_with = "output_schema")]
     pub output: Vec<(Source, String)>,
 
+    /// The phases of the run in the order they were executed.
+    ///
+    /// May be empty if the bench method doesn't have distinct phases.
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+
     #[serde(default)]
     pub measurements: HashMap<String, Measurement>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 pub enum WorkerStatus {
@@ -89,8 +191,18 @@ pub enum WorkerStatus {
     Working(UnfinishedRun),
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WorkerRequest {
+    /// Version of the worker's software, for display purposes only.
+    #[serde(default)]
+    pub version: Option<String>,
+
+    /// The worker's [`PROTOCOL_VERSION`].
+    ///
+    /// The server rejects workers whose protocol it doesn't understand.
+    #[serde(default)]
+    pub protocol: u32,
+
     /// Additional free-form info about the worker.
     ///
     /// This could for example be used to describe the worker's system specs.
@@ -114,7 +226,7 @@ pub struct WorkerRequest {
     pub submit_run: Option<FinishedRun>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ServerResponse {
     /// Run the worker requested using [`WorkerRequest::request_run`].
     ///
@@ -133,3 +245,12 @@ pub struct ServerResponse {
     #[serde(default, skip_serializing_if = "is_false")]
     pub abort_run: bool,
 }
+
+/// The projects hosted by a server.
+///
+/// Each project has its own worker endpoints below `/p/<name>`. Servers that
+/// don't host multiple projects don't respond to this request.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ProjectsResponse {
+    pub projects: Vec<String>,
+}
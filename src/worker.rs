@@ -1,22 +1,133 @@
+pub mod bench;
 mod run;
 mod server;
 mod tree;
 
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use log::{error, info, warn};
 use reqwest::Client;
 use time::OffsetDateTime;
-use tokio::sync::Mutex as AsyncMutex;
+use tokio::{sync::Mutex as AsyncMutex, task::AbortHandle};
+use walkdir::WalkDir;
 
 use crate::{
-    config::WorkerConfig,
+    config::{WorkerConfig, WorkerServerConfig},
     id,
     shared::{FinishedRun, Run},
+    somehow,
     worker::server::Server,
 };
 
-use self::run::RunInProgress;
+use self::{run::RunInProgress, tree::Trees};
+
+/// How often servers are asked again which projects they host.
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The servers the worker talks to, along with their ping tasks, by the name of
+/// the configured server they were discovered from.
+type Servers = Arc<Mutex<BTreeMap<String, Vec<(Server, AbortHandle)>>>>;
+
+/// Finds out which projects a configured server hosts.
+///
+/// Servers hosting multiple projects are treated like one server per project,
+/// so the projects are served as fairly as separate servers. Each project is
+/// pinged and asked for runs separately, but all requests share one client and
+/// thus its connections.
+///
+/// Every configured server is discovered independently, so an unreachable
+/// server doesn't keep the worker from serving the others.
+struct Discovery {
+    name: String,
+    config: &'static WorkerConfig,
+    server_config: &'static WorkerServerConfig,
+    client: Client,
+    current_run: Arc<Mutex<Option<RunInProgress>>>,
+    servers: Servers,
+}
+
+impl Discovery {
+    /// Returns the names and configs of the servers to talk to.
+    async fn discover(&self) -> somehow::Result<Vec<(String, WorkerServerConfig)>> {
+        let projects = server::list_projects(&self.client, self.config, self.server_config).await?;
+
+        let Some(projects) = projects else {
+            let config = WorkerServerConfig {
+                url: self.server_config.url.clone(),
+                token: self.server_config.token.clone(),
+            };
+            return Ok(vec![(self.name.clone(), config)]);
+        };
+
+        let servers = projects
+            .into_iter()
+            .map(|project| {
+                let config = WorkerServerConfig {
+                    url: format!("{}/p/{project}", self.server_config.url),
+                    token: self.server_config.token.clone(),
+                };
+                (format!("{}/{project}", self.name), config)
+            })
+            .collect();
+
+        Ok(servers)
+    }
+
+    /// Add servers that weren't known yet and remove servers that no longer
+    /// exist, keeping the others untouched.
+    fn update(&self, discovered: Vec<(String, WorkerServerConfig)>) {
+        let mut servers = self.servers.lock().unwrap();
+        let known = servers.entry(self.name.clone()).or_default();
+
+        known.retain(|(server, ping)| {
+            let exists = discovered.iter().any(|(name, _)| *name == server.name);
+            if !exists {
+                info!("Disconnecting from server {}", server.name);
+                ping.abort();
+            }
+            exists
+        });
+
+        for (name, server_config) in discovered {
+            if known.iter().any(|(server, _)| server.name == name) {
+                continue;
+            }
+
+            info!("Connecting to server {name}");
+            let server = Server {
+                name,
+                config: self.config,
+                server_config: Box::leak(Box::new(server_config)),
+                secret: id::random_worker_secret(),
+                client: self.client.clone(),
+                current_run: self.current_run.clone(),
+                status_lock: Arc::new(AsyncMutex::new(())),
+            };
+            let ping = tokio::spawn(server.clone().ping_periodically()).abort_handle();
+            known.push((server, ping));
+        }
+    }
+
+    async fn discover_periodically(self) {
+        loop {
+            match self.discover().await {
+                Ok(discovered) => {
+                    self.update(discovered);
+                    tokio::time::sleep(DISCOVERY_INTERVAL).await;
+                }
+                Err(e) => {
+                    warn!("Error listing projects of {}, retrying:\n{e:?}", self.name);
+                    tokio::time::sleep(self.config.ping).await;
+                }
+            }
+        }
+    }
+}
 
 pub struct Worker {
     config: &'static WorkerConfig,
@@ -28,55 +139,56 @@ impl Worker {
     }
 
     pub async fn run(&self) {
+        if self.config.servers.is_empty() {
+            error!("No servers specified in config");
+            return;
+        }
+
         let client = Client::new();
         let current_run = Arc::new(Mutex::new(None));
+        let servers = Arc::new(Mutex::new(BTreeMap::new()));
 
-        let mut servers = self
-            .config
-            .servers
-            .iter()
-            .map(|(name, server_config)| Server {
+        for (name, server_config) in &self.config.servers {
+            let discovery = Discovery {
                 name: name.clone(),
                 config: self.config,
                 server_config,
-                secret: id::random_worker_secret(),
                 client: client.clone(),
                 current_run: current_run.clone(),
-                status_lock: Arc::new(AsyncMutex::new(())),
-            })
-            .collect::<Vec<_>>();
-
-        for server in &servers {
-            info!("Connecting to server {}", server.name);
-            tokio::spawn(server.clone().ping_periodically());
+                servers: servers.clone(),
+            };
+            tokio::spawn(discovery.discover_periodically());
         }
 
-        match servers.len() {
-            0 => error!("No servers specified in config"),
-            1 => self.single_server_mode(servers.pop().unwrap()).await,
-            _ => self.many_server_mode(servers).await,
-        }
-    }
-
-    async fn single_server_mode(&self, server: Server) {
         loop {
-            while self.perform_run(&server).await {}
+            let servers = servers
+                .lock()
+                .unwrap()
+                .values()
+                .flatten()
+                .map(|(server, _)| server.clone())
+                .collect::<Vec<Server>>();
+
+            match servers.as_slice() {
+                [] => {}
+                [server] => while self.perform_run(server).await {},
+                servers => self.perform_batches(servers).await,
+            }
+
             tokio::time::sleep(self.config.ping).await;
         }
     }
 
-    async fn many_server_mode(&self, servers: Vec<Server>) {
-        loop {
-            for server in &servers {
-                let batch_start = OffsetDateTime::now_utc();
-                let batch_end = batch_start + self.config.batch;
-                while OffsetDateTime::now_utc() <= batch_end {
-                    if !self.perform_run(server).await {
-                        break;
-                    }
+    /// Give each server a batch of runs in turn, so they're served fairly.
+    async fn perform_batches(&self, servers: &[Server]) {
+        for server in servers {
+            let batch_start = OffsetDateTime::now_utc();
+            let batch_end = batch_start + self.config.batch;
+            while OffsetDateTime::now_utc() <= batch_end {
+                if !self.perform_run(server).await {
+                    break;
                 }
             }
-            tokio::time::sleep(self.config.ping).await;
         }
     }
 
@@ -94,18 +206,24 @@ impl Worker {
         drop(guard);
 
         // Perform run
-        let Some(run) = run.perform(server).await else {
+        let Some((run, artifacts)) = run.perform(&Trees::Server(server)).await else {
             return false;
         };
 
         // Submit run
         let guard = server.status_lock.lock().await;
         *server.current_run.lock().unwrap() = None;
+        let id = run.id.clone();
         while !self.submit_run(server, run.clone()).await {
             tokio::time::sleep(self.config.ping).await;
         }
         drop(guard);
 
+        // Upload artifacts. The server only accepts them once it knows the run.
+        if let Some(artifacts) = artifacts {
+            self.upload_artifacts(server, &id, artifacts.path()).await;
+        }
+
         true
     }
 
@@ -119,6 +237,37 @@ impl Worker {
         }
     }
 
+    async fn upload_artifacts(&self, server: &Server, id: &str, dir: &Path) {
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Error listing artifacts of run {id}:\n{e:?}");
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let Ok(relative_path) = entry.path().strip_prefix(dir) else {
+                continue;
+            };
+            let name = relative_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if let Err(e) = server.upload_artifact(id, &name, entry.path()).await {
+                warn!(
+                    "Error uploading artifact {name} of run {id} to {}:\n{e:?}",
+                    server.name
+                );
+            }
+        }
+    }
+
     async fn submit_run(&self, server: &Server, run: FinishedRun) -> bool {
         match server.post_status(false, Some(run)).await {
             Ok(_) => true,
@@ -0,0 +1,143 @@
+//! Perform a single run locally, without a server or db.
+//!
+//! The run is performed exactly like a worker would perform it, except that
+//! the worktrees are checked out from repos on disk instead of downloaded.
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::anyhow;
+use gix::ThreadSafeRepository;
+use log::info;
+
+use crate::{
+    args::BenchCommand,
+    id,
+    primitive::{Source, Timestamp},
+    shared::{BenchMethod, FinishedRun, Run},
+    somehow,
+};
+
+use super::{run::RunInProgress, tree::Trees};
+
+fn resolve(repo: &ThreadSafeRepository, rev: &str) -> somehow::Result<String> {
+    let repo = repo.to_thread_local();
+    let commit = repo
+        .rev_parse_single(rev)?
+        .object()?
+        .peel_to_kind(gix::object::Kind::Commit)?;
+    Ok(commit.id.to_string())
+}
+
+fn print_output(run: &FinishedRun) {
+    for (source, line) in &run.output {
+        let prefix = match source {
+            Source::Internal => "int",
+            Source::Stdout => "out",
+            Source::Stderr => "err",
+        };
+        eprintln!("[{prefix}] {line}");
+    }
+}
+
+fn print_measurements(run: &FinishedRun) {
+    let mut measurements = run.measurements.iter().collect::<Vec<_>>();
+    measurements.sort_unstable_by_key(|(metric, _)| *metric);
+
+    let values = measurements
+        .iter()
+        .map(|(_, m)| m.value.to_string())
+        .collect::<Vec<_>>();
+
+    let metric_width = measurements
+        .iter()
+        .map(|(metric, _)| metric.chars().count())
+        .chain(["metric".len()])
+        .max()
+        .unwrap_or_default();
+    let value_width = values
+        .iter()
+        .map(|value| value.len())
+        .chain(["value".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:metric_width$}  {:>value_width$}  unit",
+        "metric", "value"
+    );
+    for ((metric, measurement), value) in measurements.iter().zip(values) {
+        let unit = measurement.unit.as_deref().unwrap_or_default();
+        println!("{metric:metric_width$}  {value:>value_width$}  {unit}");
+    }
+}
+
+/// Keep the artifacts directory around so its contents can be inspected.
+fn keep_artifacts(dir: tempfile::TempDir) -> somehow::Result<()> {
+    if dir.path().read_dir()?.next().is_none() {
+        return Ok(());
+    }
+    let path = dir.into_path();
+    info!("Kept artifacts in {}", path.display());
+    Ok(())
+}
+
+fn open(path: &Path) -> somehow::Result<Arc<ThreadSafeRepository>> {
+    info!("Opening repo at {}", path.display());
+    Ok(Arc::new(ThreadSafeRepository::open(path)?))
+}
+
+pub async fn bench(command: BenchCommand) -> somehow::Result<()> {
+    let repo = open(&command.repo)?;
+    let hash = resolve(&repo, &command.commit)?;
+
+    let (bench_repo, bench_method) = match &command.bench_repo {
+        None => (None, BenchMethod::Internal),
+        Some(path) => {
+            let bench_repo = open(path)?;
+            let bench_hash = resolve(&bench_repo, &command.bench_commit)?;
+            let bench_method = BenchMethod::Repo {
+                hash: bench_hash,
+                benchmarks: command.benchmark,
+            };
+            (Some(bench_repo), bench_method)
+        }
+    };
+
+    let run = Run {
+        id: id::random_run_id(),
+        hash,
+        bench_method,
+        start: Timestamp::now(),
+    };
+    info!(
+        "Performing run {} for {} ({})",
+        run.id, run.hash, run.bench_method
+    );
+
+    let trees = Trees::Local { repo, bench_repo };
+    let run = RunInProgress::new("local".to_string(), run);
+    let Some((mut run, artifacts)) = run.perform(&trees).await else {
+        return Err(somehow::Error(anyhow!("Run was aborted")));
+    };
+    run.end = Some(Timestamp::now());
+
+    if command.json {
+        println!("{}", serde_json::to_string(&run)?);
+    } else {
+        print_output(&run);
+        print_measurements(&run);
+    }
+
+    if let Some(artifacts) = artifacts {
+        keep_artifacts(artifacts)?;
+    }
+
+    if run.exit_code != 0 {
+        return Err(somehow::Error(anyhow!(
+            "Run failed with exit code {}",
+            run.exit_code
+        )));
+    }
+
+    Ok(())
+}
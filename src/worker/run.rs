@@ -7,19 +7,23 @@ use std::{
 };
 
 use log::{error, warn};
+use tempfile::TempDir;
 use tokio::{select, sync::Notify};
 
 use crate::{
     primitive::Source,
-    shared::{BenchMethod, FinishedRun, Measurement, Run, UnfinishedRun},
+    shared::{BenchMethod, FinishedRun, Measurement, Phase, Run, UnfinishedRun},
     somehow,
 };
 
-use super::server::Server;
+use super::tree::Trees;
 
 struct Finished {
     exit_code: i32,
     measurements: HashMap<String, Measurement>,
+    phases: Vec<Phase>,
+    /// Files to upload to the server once the run has been submitted.
+    artifacts: Option<TempDir>,
 }
 
 const SCROLLBACK: usize = 50;
@@ -67,6 +71,10 @@ impl RunInProgress {
         }
     }
 
+    pub fn output_len(&self) -> usize {
+        self.output.lock().unwrap().len()
+    }
+
     pub fn log_internal(&self, line: String) {
         self.output.lock().unwrap().push((Source::Internal, line));
     }
@@ -79,20 +87,24 @@ impl RunInProgress {
         self.output.lock().unwrap().push((Source::Stderr, line));
     }
 
-    async fn execute_bench_method(&self, server: &Server) -> somehow::Result<Option<Finished>> {
+    async fn execute_bench_method(&self, trees: &Trees<'_>) -> somehow::Result<Option<Finished>> {
         match &self.run.bench_method {
-            BenchMethod::Internal => self.execute_internal(server).await,
-            BenchMethod::Repo { hash } => self.execute_repo(server, hash).await,
+            BenchMethod::Internal => self.execute_internal(trees).await,
+            BenchMethod::Repo { hash, benchmarks } => {
+                self.execute_repo(trees, hash, benchmarks).await
+            }
         }
     }
 
-    pub async fn perform(&self, server: &Server) -> Option<FinishedRun> {
+    /// Perform the run and return its results as well as a directory
+    /// containing its artifacts, if any.
+    pub async fn perform(&self, trees: &Trees<'_>) -> Option<(FinishedRun, Option<TempDir>)> {
         // TODO Log system info
 
         let result = select! {
-            result = self.execute_bench_method(server) => result,
+            result = self.execute_bench_method(trees) => result,
             _ = self.abort.notified() => {
-                warn!("Run for {} was aborted", server.name);
+                warn!("Run for {} was aborted", self.server_name);
                 Ok(None)
             },
         };
@@ -100,12 +112,14 @@ impl RunInProgress {
         let run = match result {
             Ok(outcome) => outcome,
             Err(e) => {
-                error!("Error during run for {}:\n{e:?}", server.name);
+                error!("Error during run for {}:\n{e:?}", self.server_name);
                 self.log_internal("Internal error:".to_string());
                 self.log_internal(format!("{e:?}"));
                 Some(Finished {
                     exit_code: -1,
                     measurements: HashMap::new(),
+                    phases: vec![],
+                    artifacts: None,
                 })
             }
         }?;
@@ -113,7 +127,7 @@ impl RunInProgress {
         let mut output = vec![];
         std::mem::swap(&mut output, &mut *self.output.lock().unwrap());
 
-        Some(FinishedRun {
+        let finished = FinishedRun {
             id: self.run.id.clone(),
             hash: self.run.hash.clone(),
             bench_method: self.run.bench_method.to_string(),
@@ -121,8 +135,11 @@ impl RunInProgress {
             end: None,
             exit_code: run.exit_code,
             output,
+            phases: run.phases,
             measurements: run.measurements,
-        })
+        };
+
+        Some((finished, run.artifacts))
     }
 
     pub fn abort(&self) {
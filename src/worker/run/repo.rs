@@ -1,19 +1,232 @@
-use crate::{somehow, worker::server::Server};
+//! Run the bench script of a bench repo.
+
+use std::{collections::HashMap, path::Path, process::Stdio};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+use crate::{
+    primitive::{Direction, Timestamp},
+    shared::{Measurement, Phase},
+    somehow,
+    worker::tree::Trees,
+};
 
 use super::{Finished, RunInProgress};
 
+/// Path of the bench script, relative to the bench repo's root.
+const BENCH_SCRIPT: &str = "bench";
+
+/// Phase scripts in the order they are executed, relative to the bench repo's
+/// root. All scripts except [`BENCH_SCRIPT`] are optional.
+///
+/// If a phase fails, the following phases are skipped, except for the teardown
+/// phase which is always executed.
+const PHASES: [&str; 4] = ["setup", "build", BENCH_SCRIPT, TEARDOWN_SCRIPT];
+
+const TEARDOWN_SCRIPT: &str = "teardown";
+
+/// Prefix of stdout lines the bench script uses to talk to the worker.
+const CONTROL_PREFIX: &str = "@tablejohn ";
+
+/// A message from the bench script to the worker.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+enum Control {
+    Measurement {
+        metric: String,
+        value: f64,
+        unit: Option<String>,
+        direction: Option<Direction>,
+    },
+}
+
+/// What every phase script of a run gets to see.
+struct PhaseEnv<'a> {
+    bench_repo_dir: &'a Path,
+    repo_dir: &'a Path,
+    artifacts_dir: &'a Path,
+    benchmarks: &'a [String],
+}
+
+async fn read_lines(
+    mut reader: impl AsyncBufRead + Unpin,
+    mut f: impl FnMut(String),
+) -> somehow::Result<()> {
+    let mut buf = vec![];
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf).await? == 0 {
+            return Ok(());
+        }
+        // Bench scripts may output arbitrary bytes, so we can't just use
+        // AsyncBufReadExt::lines here.
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        f(line.to_string());
+    }
+}
+
 impl RunInProgress {
+    fn process_stdout_line(&self, line: String, measurements: &mut HashMap<String, Measurement>) {
+        let Some(control) = line.strip_prefix(CONTROL_PREFIX) else {
+            self.log_stdout(line);
+            return;
+        };
+
+        match serde_json::from_str::<Control>(control) {
+            Ok(Control::Measurement {
+                metric,
+                value,
+                unit,
+                direction,
+            }) => {
+                let measurement = Measurement {
+                    value,
+                    unit,
+                    direction,
+                };
+                measurements.insert(metric, measurement);
+            }
+            Err(e) => {
+                self.log_internal(format!("Invalid control line: {e}"));
+                self.log_stdout(line);
+            }
+        }
+    }
+
+    /// Run a phase script to completion and return its exit code.
+    async fn execute_script(
+        &self,
+        name: &str,
+        script: &Path,
+        env: &PhaseEnv<'_>,
+        measurements: &mut HashMap<String, Measurement>,
+    ) -> somehow::Result<i32> {
+        let mut child = Command::new(script)
+            .arg(env.repo_dir)
+            .current_dir(env.bench_repo_dir)
+            .env("TABLEJOHN_REPO", env.repo_dir)
+            .env("TABLEJOHN_ARTIFACTS", env.artifacts_dir)
+            .env("TABLEJOHN_PHASE", name)
+            .env("TABLEJOHN_BENCHMARKS", env.benchmarks.join(","))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // If the run is aborted, this future is dropped and the script
+            // should not keep running in the background.
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+
+        let (stdout, stderr, status) = tokio::join!(
+            read_lines(stdout, |line| self.process_stdout_line(line, measurements)),
+            read_lines(stderr, |line| self.log_stderr(line)),
+            child.wait(),
+        );
+        stdout?;
+        stderr?;
+        let status = status?;
+        self.log_internal(format!("The {name} script exited with {status}"));
+
+        // A process killed by a signal has no exit code.
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    /// Run a phase script. If it can't be run, the phase fails with the error
+    /// in its output, so the teardown phase still gets to run afterwards.
+    async fn execute_phase(
+        &self,
+        name: &str,
+        script: &Path,
+        env: &PhaseEnv<'_>,
+        measurements: &mut HashMap<String, Measurement>,
+    ) -> Phase {
+        let start = Timestamp::now();
+        let output_start = self.output_len();
+
+        self.log_internal(format!("Running {name} script"));
+        let exit_code = match self.execute_script(name, script, env, measurements).await {
+            Ok(exit_code) => exit_code,
+            Err(e) => {
+                self.log_internal(format!("The {name} script failed to run:"));
+                self.log_internal(format!("{e:?}"));
+                -1
+            }
+        };
+
+        Phase {
+            name: name.to_string(),
+            start,
+            end: Timestamp::now(),
+            exit_code,
+            output_start,
+            output_end: self.output_len(),
+        }
+    }
+
     pub(super) async fn execute_repo(
         &self,
-        server: &Server,
+        trees: &Trees<'_>,
         hash: &str,
+        benchmarks: &[String],
     ) -> somehow::Result<Option<Finished>> {
-        // TODO Design bench repo specification (benchmark, compare)
-        // TODO Decide on better name? "benchmark repo", "bench repo", "eval repo"?
-        // TODO Implement specification
-        let repo_dir = server.download_repo(&self.run.hash).await?;
-        let bench_repo_dir = server.download_bench_repo(hash).await?;
+        self.log_internal(format!("Fetching repo at {}", self.run.hash));
+        let repo_dir = trees.repo(&self.run.hash).await?;
+        self.log_internal(format!("Fetching bench repo at {hash}"));
+        let bench_repo_dir = trees.bench_repo(hash).await?;
+        let artifacts_dir = TempDir::new()?;
+
+        if !bench_repo_dir.path().join(BENCH_SCRIPT).is_file() {
+            return Err(somehow::Error(anyhow!(
+                "Bench repo contains no {BENCH_SCRIPT} script"
+            )));
+        }
+
+        let env = PhaseEnv {
+            bench_repo_dir: bench_repo_dir.path(),
+            repo_dir: repo_dir.path(),
+            artifacts_dir: artifacts_dir.path(),
+            benchmarks,
+        };
+
+        let mut measurements = HashMap::new();
+        let mut phases = vec![];
+        let mut exit_code = 0;
+        for name in PHASES {
+            if exit_code != 0 && name != TEARDOWN_SCRIPT {
+                continue;
+            }
+
+            let script = bench_repo_dir.path().join(name);
+            if !script.is_file() {
+                continue;
+            }
+
+            let phase = self
+                .execute_phase(name, &script, &env, &mut measurements)
+                .await;
+
+            if exit_code == 0 {
+                exit_code = phase.exit_code;
+            }
+            phases.push(phase);
+        }
 
-        todo!()
+        Ok(Some(Finished {
+            exit_code,
+            measurements,
+            phases,
+            artifacts: Some(artifacts_dir),
+        }))
     }
 }
@@ -1,17 +1,25 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
+use anyhow::anyhow;
 use log::{debug, warn};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use tempfile::TempDir;
 use tokio::sync::Mutex as AsyncMutex;
 
 use crate::{
+    args::VERSION,
     config::{WorkerConfig, WorkerServerConfig},
     server::web::paths::{
-        PathApiWorkerBenchRepoByHashTreeTarGz, PathApiWorkerRepoByHashTreeTarGz,
-        PathApiWorkerStatus,
+        PathApiWorkerArtifact, PathApiWorkerBenchRepoByHashTreeTarGz, PathApiWorkerProjects,
+        PathApiWorkerRepoByHashTreeTarGz, PathApiWorkerStatus,
+    },
+    shared::{
+        FinishedRun, ProjectsResponse, ServerResponse, WorkerRequest, WorkerStatus,
+        PROTOCOL_VERSION,
     },
-    shared::{FinishedRun, ServerResponse, WorkerRequest, WorkerStatus},
     somehow,
     worker::tree,
 };
@@ -43,6 +51,34 @@ pub struct Server {
     pub status_lock: Arc<AsyncMutex<()>>,
 }
 
+/// Ask a server which projects it hosts.
+///
+/// Returns `None` if the server doesn't host multiple projects.
+pub async fn list_projects(
+    client: &Client,
+    config: &WorkerConfig,
+    server_config: &WorkerServerConfig,
+) -> somehow::Result<Option<Vec<String>>> {
+    let url = format!("{}{}", server_config.url, PathApiWorkerProjects {});
+
+    let response = client
+        .get(url)
+        .basic_auth(&config.name, Some(&server_config.token))
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let response = response
+        .error_for_status()?
+        .json::<ProjectsResponse>()
+        .await?;
+
+    Ok(Some(response.projects))
+}
+
 impl Server {
     /// **Important:** Before using this function, read the documentation of
     /// [`Self::status_lock`]!
@@ -62,6 +98,8 @@ impl Server {
         };
 
         let request = WorkerRequest {
+            version: Some(VERSION.to_string()),
+            protocol: PROTOCOL_VERSION,
             info: None,
             secret: self.secret.clone(),
             status,
@@ -75,7 +113,19 @@ impl Server {
             .basic_auth(&self.config.name, Some(&self.server_config.token))
             .json(&request)
             .send()
-            .await?
+            .await?;
+
+        // The server explains why it rejected a request, e.g. because of
+        // incompatible protocol versions.
+        let status = response.status();
+        if status.is_client_error() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(somehow::Error(anyhow!(
+                "Server rejected status update with {status}: {text}"
+            )));
+        }
+
+        let response = response
             .error_for_status()?
             .json::<ServerResponse>()
             .await?;
@@ -131,6 +181,30 @@ impl Server {
         tree::download(response).await
     }
 
+    pub async fn upload_artifact(&self, id: &str, name: &str, path: &Path) -> somehow::Result<()> {
+        let url = format!(
+            "{}{}",
+            self.server_config.url,
+            PathApiWorkerArtifact {
+                id: id.to_string(),
+                name: name.to_string(),
+            },
+        );
+
+        debug!("Uploading artifact {name} to {url}");
+
+        let data = tokio::fs::read(path).await?;
+        self.client
+            .post(url)
+            .basic_auth(&self.config.name, Some(&self.server_config.token))
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
     async fn ping(&self) -> somehow::Result<()> {
         debug!("Pinging {}", self.name);
 
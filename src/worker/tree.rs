@@ -1,15 +1,20 @@
 //! Download and unpack repo worktrees into temporary directories.
 
-use std::{io, path::PathBuf};
+use std::{io, path::PathBuf, sync::Arc};
 
+use anyhow::anyhow;
+use axum::BoxError;
 use bytes::{Buf, Bytes};
 use flate2::read::GzDecoder;
-use futures::{Stream, StreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
+use gix::{ObjectId, ThreadSafeRepository};
 use reqwest::Response;
 use tempfile::TempDir;
 use tokio::sync::mpsc;
 
-use crate::somehow;
+use crate::{server::web::api::worker::stream, somehow};
+
+use super::server::Server;
 
 struct ReceiverReader {
     rx: mpsc::Receiver<Bytes>,
@@ -42,11 +47,12 @@ impl io::Read for ReceiverReader {
 }
 
 async fn receive_bytes(
-    mut stream: impl Stream<Item = reqwest::Result<Bytes>> + Unpin,
+    mut stream: impl Stream<Item = Result<Bytes, BoxError>> + Unpin,
     tx: mpsc::Sender<Bytes>,
 ) -> somehow::Result<()> {
     while let Some(bytes) = stream.next().await {
-        tx.send(bytes?).await?;
+        let bytes = bytes.map_err(|e| somehow::Error(anyhow!(e)))?;
+        tx.send(bytes).await?;
     }
     Ok(())
 }
@@ -59,9 +65,9 @@ fn unpack_archive(rx: mpsc::Receiver<Bytes>, path: PathBuf) -> somehow::Result<(
     Ok(())
 }
 
-pub async fn download(response: Response) -> somehow::Result<TempDir> {
-    let stream = response.error_for_status()?.bytes_stream();
-
+async fn unpack(
+    stream: impl Stream<Item = Result<Bytes, BoxError>> + Unpin,
+) -> somehow::Result<TempDir> {
     let dir = TempDir::new()?;
     let path = dir.path().to_path_buf();
     let (tx, rx) = mpsc::channel(1);
@@ -75,3 +81,47 @@ pub async fn download(response: Response) -> somehow::Result<TempDir> {
 
     Ok(dir)
 }
+
+pub async fn download(response: Response) -> somehow::Result<TempDir> {
+    let stream = response.error_for_status()?.bytes_stream();
+    unpack(stream.map_err(BoxError::from)).await
+}
+
+/// Unpack a commit's worktree exactly like the server would send it.
+async fn checkout(repo: &Arc<ThreadSafeRepository>, hash: &str) -> somehow::Result<TempDir> {
+    let id = ObjectId::from_hex(hash.as_bytes())?;
+    unpack(stream::tar_and_gzip(repo.clone(), id).into_stream()).await
+}
+
+/// Where the worktrees of a run come from.
+pub enum Trees<'a> {
+    /// Download them from a server.
+    Server(&'a Server),
+    /// Check them out from repos on disk.
+    Local {
+        repo: Arc<ThreadSafeRepository>,
+        bench_repo: Option<Arc<ThreadSafeRepository>>,
+    },
+}
+
+impl Trees<'_> {
+    pub async fn repo(&self, hash: &str) -> somehow::Result<TempDir> {
+        match self {
+            Self::Server(server) => server.download_repo(hash).await,
+            Self::Local { repo, .. } => checkout(repo, hash).await,
+        }
+    }
+
+    pub async fn bench_repo(&self, hash: &str) -> somehow::Result<TempDir> {
+        match self {
+            Self::Server(server) => server.download_bench_repo(hash).await,
+            Self::Local {
+                bench_repo: Some(bench_repo),
+                ..
+            } => checkout(bench_repo, hash).await,
+            Self::Local {
+                bench_repo: None, ..
+            } => Err(somehow::Error(anyhow!("No bench repo"))),
+        }
+    }
+}